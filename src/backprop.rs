@@ -0,0 +1,555 @@
+extern crate rand;
+
+use rand::seq::SliceRandom;
+use rand::Rng;
+
+use crate::error::{Error, Result};
+use crate::gen_alg::NN;
+
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum Loss {
+    MeanSquaredError,
+    // Binary cross-entropy of every output on its own, for sigmoid outputs
+    CrossEntropy,
+    // Cross-entropy of one distribution over all the outputs, for a softmax head. Backpropagated through
+    // the softmax, its gradient is the output minus the target
+    CategoricalCrossEntropy,
+}
+
+impl Loss {
+    pub fn get_loss(&self, outputs: &[f64], targets: &[f64]) -> f64 {
+        match self {
+            Loss::MeanSquaredError => {
+                outputs
+                    .iter()
+                    .zip(targets.iter())
+                    .map(|(y, t)| (y - t).powi(2))
+                    .sum::<f64>()
+                    / outputs.len() as f64
+            }
+            Loss::CrossEntropy => outputs
+                .iter()
+                .zip(targets.iter())
+                .map(|(y, t)| {
+                    let y = clamp_probability(*y);
+                    -(t * y.ln() + (1.0_f64 - t) * (1.0_f64 - y).ln())
+                })
+                .sum(),
+            Loss::CategoricalCrossEntropy => outputs
+                .iter()
+                .zip(targets.iter())
+                .map(|(y, t)| -t * clamp_probability(*y).ln())
+                .sum(),
+        }
+    }
+
+    pub fn get_output_gradient(&self, outputs: &[f64], targets: &[f64]) -> Vec<f64> {
+        match self {
+            Loss::MeanSquaredError => {
+                let n = outputs.len() as f64;
+                outputs
+                    .iter()
+                    .zip(targets.iter())
+                    .map(|(y, t)| 2.0_f64 * (y - t) / n)
+                    .collect()
+            }
+            Loss::CrossEntropy => outputs
+                .iter()
+                .zip(targets.iter())
+                .map(|(y, t)| {
+                    let y = clamp_probability(*y);
+                    (y - t) / (y * (1.0_f64 - y))
+                })
+                .collect(),
+            Loss::CategoricalCrossEntropy => outputs
+                .iter()
+                .zip(targets.iter())
+                .map(|(y, t)| -t / clamp_probability(*y))
+                .collect(),
+        }
+    }
+}
+
+fn clamp_probability(y: f64) -> f64 {
+    y.clamp(1e-12_f64, 1.0_f64 - 1e-12_f64)
+}
+
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum OptimizerType {
+    Sgd,
+    Momentum { momentum: f64 },
+    Adam { beta1: f64, beta2: f64, epsilon: f64 },
+}
+
+pub struct Optimizer {
+    pub optimizer_type: OptimizerType,
+    pub learning_rate: f64,
+    first_moment: Vec<f64>,
+    second_moment: Vec<f64>,
+    steps: i32,
+}
+
+impl Optimizer {
+    pub fn new(optimizer_type: OptimizerType, learning_rate: f64) -> Optimizer {
+        Optimizer {
+            optimizer_type,
+            learning_rate,
+            first_moment: Vec::new(),
+            second_moment: Vec::new(),
+            steps: 0,
+        }
+    }
+
+    pub fn sgd(learning_rate: f64) -> Optimizer {
+        Optimizer::new(OptimizerType::Sgd, learning_rate)
+    }
+
+    pub fn momentum(learning_rate: f64, momentum: f64) -> Optimizer {
+        Optimizer::new(OptimizerType::Momentum { momentum }, learning_rate)
+    }
+
+    pub fn adam(learning_rate: f64) -> Optimizer {
+        Optimizer::new(
+            OptimizerType::Adam {
+                beta1: 0.9,
+                beta2: 0.999,
+                epsilon: 1e-8,
+            },
+            learning_rate,
+        )
+    }
+
+    pub fn step(&mut self, nn: &mut NN, gradients: &Gradients) {
+        let grads = gradients.get_values();
+        if self.first_moment.len() != grads.len() {
+            self.first_moment = vec![0.0_f64; grads.len()];
+            self.second_moment = vec![0.0_f64; grads.len()];
+            self.steps = 0;
+        }
+        self.steps += 1;
+        let lr = self.learning_rate;
+        match self.optimizer_type {
+            OptimizerType::Sgd => nn.apply_gradients(gradients, lr),
+            OptimizerType::Momentum { momentum } => {
+                for ((p, g), v) in get_parameters_mut(nn)
+                    .into_iter()
+                    .zip(grads.iter())
+                    .zip(self.first_moment.iter_mut())
+                {
+                    *v = momentum * *v + g;
+                    *p -= lr * *v;
+                }
+            }
+            OptimizerType::Adam { beta1, beta2, epsilon } => {
+                let correction1 = 1.0_f64 - beta1.powi(self.steps);
+                let correction2 = 1.0_f64 - beta2.powi(self.steps);
+                for (((p, g), m), v) in get_parameters_mut(nn)
+                    .into_iter()
+                    .zip(grads.iter())
+                    .zip(self.first_moment.iter_mut())
+                    .zip(self.second_moment.iter_mut())
+                {
+                    *m = beta1 * *m + (1.0_f64 - beta1) * g;
+                    *v = beta2 * *v + (1.0_f64 - beta2) * g * g;
+                    *p -= lr * (*m / correction1) / ((*v / correction2).sqrt() + epsilon);
+                }
+            }
+        }
+    }
+}
+
+// Parameters in the same order as Gradients::get_values
+fn get_parameters_mut(nn: &mut NN) -> Vec<&mut f64> {
+    let mut params: Vec<&mut f64> = Vec::new();
+    for layer in nn.layers.iter_mut() {
+        params.extend(layer.biases.iter_mut());
+        params.extend(layer.weights.iter_mut());
+    }
+    params
+}
+
+pub struct Gradients {
+    // Laid out like Layer::weights
+    pub weights: Vec<Vec<f64>>,
+    pub biases: Vec<Vec<f64>>,
+}
+
+impl Gradients {
+    pub fn zeros(nn: &NN) -> Gradients {
+        Gradients {
+            weights: nn.layers.iter().map(|l| vec![0.0_f64; l.weights.len()]).collect(),
+            biases: nn
+                .layers
+                .iter()
+                .map(|l| vec![0.0_f64; l.num_neurons as usize])
+                .collect(),
+        }
+    }
+
+    pub fn add(&mut self, other: &Gradients) {
+        for (bs, obs) in self.biases.iter_mut().zip(other.biases.iter()) {
+            for (b, ob) in bs.iter_mut().zip(obs.iter()) {
+                *b += ob;
+            }
+        }
+        for (ws, ows) in self.weights.iter_mut().zip(other.weights.iter()) {
+            for (w, ow) in ws.iter_mut().zip(ows.iter()) {
+                *w += ow;
+            }
+        }
+    }
+
+    pub fn get_values(&self) -> Vec<f64> {
+        let mut values: Vec<f64> = Vec::new();
+        for (ws, bs) in self.weights.iter().zip(self.biases.iter()) {
+            values.extend(bs.iter());
+            values.extend(ws.iter());
+        }
+        values
+    }
+
+    pub fn scale(&mut self, factor: f64) {
+        for b in self.biases.iter_mut().flatten() {
+            *b *= factor;
+        }
+        for w in self.weights.iter_mut().flatten() {
+            *w *= factor;
+        }
+    }
+}
+
+impl NN {
+    // Outputs of every layer, starting with the inputs themselves
+    pub fn get_activations(&self, inputs: &[f64]) -> Result<Vec<Vec<f64>>> {
+        if self.layers.is_empty() {
+            return Err(Error::InvalidConfig("the network has no layers".to_string()));
+        }
+        let mut activations = vec![inputs.to_vec()];
+        for layer in &self.layers {
+            let outputs = layer.feed_forward(activations.last().unwrap())?;
+            activations.push(outputs);
+        }
+        Ok(activations)
+    }
+
+    // The output gradient is the derivative of the loss with respect to each network output
+    pub fn get_gradients(&self, inputs: &[f64], output_gradient: &[f64]) -> Result<Gradients> {
+        let activations = self.get_activations(inputs)?;
+        let outputs = activations.last().unwrap();
+        if outputs.len() != output_gradient.len() {
+            return Err(Error::ShapeMismatch {
+                what: "output gradient",
+                expected: outputs.len(),
+                found: output_gradient.len(),
+            });
+        }
+
+        let mut gradients = Gradients::zeros(self);
+        let mut delta = self
+            .layers
+            .last()
+            .unwrap()
+            .activation
+            .backpropagate(outputs, output_gradient);
+        for l in (0..self.layers.len()).rev() {
+            let layer = &self.layers[l];
+            let layer_inputs = &activations[l];
+            let gws = gradients.weights[l].chunks_exact_mut(layer_inputs.len());
+            for ((d, gb), gws) in delta.iter().zip(gradients.biases[l].iter_mut()).zip(gws) {
+                *gb = *d;
+                for (gw, x) in gws.iter_mut().zip(layer_inputs.iter()) {
+                    *gw = d * x;
+                }
+            }
+            if l > 0 {
+                let back: Vec<f64> = (0..layer_inputs.len())
+                    .map(|j| {
+                        (0..delta.len())
+                            .map(|i| delta[i] * layer.get_neuron_weights(i)[j])
+                            .sum()
+                    })
+                    .collect();
+                delta = self.layers[l - 1].activation.backpropagate(layer_inputs, &back);
+            }
+        }
+        Ok(gradients)
+    }
+
+    pub fn apply_gradients(&mut self, gradients: &Gradients, learning_rate: f64) {
+        for (layer, (gws, gbs)) in self
+            .layers
+            .iter_mut()
+            .zip(gradients.weights.iter().zip(gradients.biases.iter()))
+        {
+            for (b, gb) in layer.biases.iter_mut().zip(gbs.iter()) {
+                *b -= learning_rate * gb;
+            }
+            for (w, gw) in layer.weights.iter_mut().zip(gws.iter()) {
+                *w -= learning_rate * gw;
+            }
+        }
+    }
+}
+
+impl NN {
+    // Averages the gradients over the batch and takes a single optimizer step, returning the mean loss
+    pub fn train_batch(
+        &mut self,
+        batch: &[(Vec<f64>, Vec<f64>)],
+        loss: Loss,
+        optimizer: &mut Optimizer,
+    ) -> Result<f64> {
+        if batch.is_empty() {
+            return Err(Error::InvalidConfig("the batch is empty".to_string()));
+        }
+        let mut gradients = Gradients::zeros(self);
+        let mut total_loss = 0.0_f64;
+        for (inputs, targets) in batch {
+            let outputs = self.propagate(inputs)?;
+            if outputs.len() != targets.len() {
+                return Err(Error::ShapeMismatch {
+                    what: "targets",
+                    expected: outputs.len(),
+                    found: targets.len(),
+                });
+            }
+            total_loss += loss.get_loss(&outputs, targets);
+            let g = self.get_gradients(inputs, &loss.get_output_gradient(&outputs, targets))?;
+            gradients.add(&g);
+        }
+        gradients.scale(1.0_f64 / batch.len() as f64);
+        optimizer.step(self, &gradients);
+        Ok(total_loss / batch.len() as f64)
+    }
+
+    // Shuffles the data every epoch and returns the mean loss of the last epoch
+    pub fn train_supervised(
+        &mut self,
+        data: &[(Vec<f64>, Vec<f64>)],
+        batch_size: usize,
+        epochs: u32,
+        loss: Loss,
+        optimizer: &mut Optimizer,
+        rng: &mut impl Rng,
+    ) -> Result<f64> {
+        if data.is_empty() || epochs == 0 {
+            return Err(Error::InvalidConfig(
+                "supervised training needs data and at least one epoch".to_string(),
+            ));
+        }
+        let mut order: Vec<usize> = (0..data.len()).collect();
+        let mut epoch_loss = 0.0_f64;
+        for _ in 0..epochs {
+            order.shuffle(rng);
+            let mut total_loss = 0.0_f64;
+            for chunk in order.chunks(batch_size.max(1)) {
+                let batch: Vec<(Vec<f64>, Vec<f64>)> = chunk.iter().map(|i| data[*i].clone()).collect();
+                total_loss += self.train_batch(&batch, loss, optimizer)? * batch.len() as f64;
+            }
+            epoch_loss = total_loss / data.len() as f64;
+        }
+        Ok(epoch_loss)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gen_alg::Activation;
+
+    fn get_half_squared_error(nn: &NN, inputs: &[f64], targets: &[f64]) -> f64 {
+        let outputs = nn.propagate(inputs).unwrap();
+        outputs
+            .iter()
+            .zip(targets.iter())
+            .map(|(y, t)| 0.5 * (y - t).powi(2))
+            .sum()
+    }
+
+    #[test]
+    fn test_get_activations() {
+        let nn = NN::new_defined(&[[3, 2], [2, 1]]);
+        let inputs = vec![0.0_f64, 1.0_f64, 0.0_f64];
+        let activations = nn.get_activations(&inputs).unwrap();
+        assert_eq!(activations.len(), 3);
+        assert_eq!(activations[0], inputs);
+        assert_eq!(activations[2], nn.propagate(&inputs).unwrap());
+        assert!(nn.get_activations(&[0.0_f64]).is_err());
+    }
+
+    #[test]
+    fn test_get_gradients_numerically() {
+        let nn = NN::new_defined(&[[3, 4], [4, 2]]);
+        let inputs = vec![0.5_f64, -1.0_f64, 1.0_f64];
+        let targets = [0.2_f64, 0.9_f64];
+        let outputs = nn.propagate(&inputs).unwrap();
+        let output_gradient: Vec<f64> = outputs.iter().zip(targets.iter()).map(|(y, t)| y - t).collect();
+        let gradients = nn.get_gradients(&inputs, &output_gradient).unwrap();
+
+        let eps = 1e-6_f64;
+        for l in 0..nn.layers.len() {
+            for i in 0..nn.layers[l].weights.len() {
+                let mut plus = nn.clone();
+                plus.layers[l].weights[i] += eps;
+                let mut minus = nn.clone();
+                minus.layers[l].weights[i] -= eps;
+                let numeric = (get_half_squared_error(&plus, &inputs, &targets)
+                    - get_half_squared_error(&minus, &inputs, &targets))
+                    / (2.0_f64 * eps);
+                assert_gradient_close(numeric, gradients.weights[l][i]);
+            }
+        }
+    }
+
+    fn assert_gradient_close(numeric: f64, analytic: f64) {
+        let scale = 1.0_f64.max(numeric.abs() + analytic.abs());
+        assert!((numeric - analytic).abs() < 1e-5 * scale, "{} != {}", numeric, analytic);
+    }
+
+    fn check_gradients(nn: &NN, inputs: &[f64], targets: &[f64], loss: Loss) {
+        let outputs = nn.propagate(inputs).unwrap();
+        let gradients = nn
+            .get_gradients(inputs, &loss.get_output_gradient(&outputs, targets))
+            .unwrap();
+        let get_loss = |n: &NN| loss.get_loss(&n.propagate(inputs).unwrap(), targets);
+
+        let eps = 1e-6_f64;
+        for l in 0..nn.layers.len() {
+            for i in 0..nn.layers[l].num_neurons as usize {
+                let mut plus = nn.clone();
+                plus.layers[l].biases[i] += eps;
+                let mut minus = nn.clone();
+                minus.layers[l].biases[i] -= eps;
+                let numeric = (get_loss(&plus) - get_loss(&minus)) / (2.0_f64 * eps);
+                assert_gradient_close(numeric, gradients.biases[l][i]);
+                let n = nn.layers[l].num_inputs as usize;
+                for j in 0..n {
+                    let mut plus = nn.clone();
+                    plus.layers[l].weights[i * n + j] += eps;
+                    let mut minus = nn.clone();
+                    minus.layers[l].weights[i * n + j] -= eps;
+                    let numeric = (get_loss(&plus) - get_loss(&minus)) / (2.0_f64 * eps);
+                    assert_gradient_close(numeric, gradients.weights[l][i * n + j]);
+                }
+            }
+        }
+    }
+
+    fn get_xor_data() -> Vec<(Vec<f64>, Vec<f64>)> {
+        vec![
+            (vec![0.0_f64, 0.0_f64], vec![0.0_f64]),
+            (vec![0.0_f64, 1.0_f64], vec![1.0_f64]),
+            (vec![1.0_f64, 0.0_f64], vec![1.0_f64]),
+            (vec![1.0_f64, 1.0_f64], vec![0.0_f64]),
+        ]
+    }
+
+    #[test]
+    fn test_gradient_check_losses() {
+        let nn = NN::new_defined(&[[3, 5], [5, 4], [4, 2]]);
+        let inputs = [0.3_f64, -0.7_f64, 1.0_f64];
+        let targets = [0.0_f64, 1.0_f64];
+        check_gradients(&nn, &inputs, &targets, Loss::MeanSquaredError);
+        check_gradients(&nn, &inputs, &targets, Loss::CrossEntropy);
+    }
+
+    #[test]
+    fn test_gradient_check_activations() {
+        let inputs = [0.3_f64, -0.7_f64, 1.0_f64];
+        let targets = [0.0_f64, 1.0_f64, 0.0_f64];
+        let hidden = [
+            Activation::Sigmoid,
+            Activation::Tanh,
+            Activation::Relu,
+            Activation::LeakyRelu,
+            Activation::Linear,
+        ];
+        for h in hidden.iter() {
+            let nn = NN::new_defined_with_activations(&[[3, 5], [5, 3]], &[*h, Activation::Linear], &[]);
+            check_gradients(&nn, &inputs, &targets, Loss::MeanSquaredError);
+            let mut nn = NN::new_defined_with_activations(&[[3, 5], [5, 3]], &[*h, Activation::Softmax], &[]);
+            // Saturated softmax outputs make the finite differences of the loss imprecise
+            for layer in nn.layers.iter_mut() {
+                for w in layer.weights.iter_mut() {
+                    *w *= 0.3_f64;
+                }
+            }
+            check_gradients(&nn, &inputs, &targets, Loss::CrossEntropy);
+            check_gradients(&nn, &inputs, &targets, Loss::CategoricalCrossEntropy);
+        }
+    }
+
+    #[test]
+    fn test_categorical_cross_entropy_softmax() {
+        let nn = NN::new_defined_with_activations(&[[3, 4], [4, 3]], &[Activation::Relu, Activation::Softmax], &[]);
+        let inputs = [0.3_f64, -0.7_f64, 1.0_f64];
+        let targets = [0.0_f64, 1.0_f64, 0.0_f64];
+        let loss = Loss::CategoricalCrossEntropy;
+        let outputs = nn.propagate(&inputs).unwrap();
+        let gradients = nn
+            .get_gradients(&inputs, &loss.get_output_gradient(&outputs, &targets))
+            .unwrap();
+        // The output biases take the gradient of the softmax inputs, which is the output minus the target
+        for ((gb, y), t) in gradients.biases[1].iter().zip(outputs.iter()).zip(targets.iter()) {
+            assert!((gb - (y - t)).abs() < 1e-9);
+        }
+        assert!((loss.get_loss(&outputs, &targets) + outputs[1].ln()).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_loss() {
+        let mse = Loss::MeanSquaredError;
+        assert_eq!(mse.get_loss(&[1.0_f64, 0.0_f64], &[1.0_f64, 0.0_f64]), 0.0_f64);
+        assert_eq!(mse.get_loss(&[1.0_f64, 0.0_f64], &[0.0_f64, 0.0_f64]), 0.5_f64);
+        let ce = Loss::CrossEntropy;
+        assert!(ce.get_loss(&[0.9_f64], &[1.0_f64]) < ce.get_loss(&[0.1_f64], &[1.0_f64]));
+        assert!(ce.get_loss(&[1.0_f64], &[0.0_f64]).is_finite());
+        let cce = Loss::CategoricalCrossEntropy;
+        assert!(cce.get_loss(&[1.0_f64, 0.0_f64], &[1.0_f64, 0.0_f64]).abs() < 1e-9);
+        assert!(cce.get_loss(&[0.0_f64, 1.0_f64], &[1.0_f64, 0.0_f64]).is_finite());
+    }
+
+    #[test]
+    fn test_train_supervised_optimizers() {
+        let data = get_xor_data();
+        let mut rng = rand::thread_rng();
+        let optimizers = vec![
+            Optimizer::sgd(2.0_f64),
+            Optimizer::momentum(0.5_f64, 0.9_f64),
+            Optimizer::adam(0.05_f64),
+        ];
+        for mut optimizer in optimizers {
+            let mut nn = NN::new_defined(&[[2, 4], [4, 1]]);
+            let first = nn
+                .train_supervised(&data, 4, 1, Loss::CrossEntropy, &mut optimizer, &mut rng)
+                .unwrap();
+            let last = nn
+                .train_supervised(&data, 2, 500, Loss::CrossEntropy, &mut optimizer, &mut rng)
+                .unwrap();
+            assert!(last < first);
+        }
+    }
+
+    #[test]
+    fn test_train_batch_mismatch() {
+        let mut nn = NN::new_defined(&[[2, 4], [4, 1]]);
+        let mut optimizer = Optimizer::sgd(0.1_f64);
+        assert!(nn.train_batch(&[], Loss::MeanSquaredError, &mut optimizer).is_err());
+        let batch = vec![(vec![0.0_f64, 1.0_f64], vec![0.0_f64, 1.0_f64])];
+        assert!(nn.train_batch(&batch, Loss::MeanSquaredError, &mut optimizer).is_err());
+    }
+
+    #[test]
+    fn test_apply_gradients() {
+        let mut nn = NN::new_defined(&[[2, 3], [3, 1]]);
+        let inputs = vec![1.0_f64, 0.0_f64];
+        let targets = [1.0_f64];
+        let error_before = get_half_squared_error(&nn, &inputs, &targets);
+        for _ in 0..100 {
+            let outputs = nn.propagate(&inputs).unwrap();
+            let gradients = nn.get_gradients(&inputs, &[outputs[0] - targets[0]]).unwrap();
+            nn.apply_gradients(&gradients, 0.5_f64);
+        }
+        assert!(get_half_squared_error(&nn, &inputs, &targets) < error_before);
+    }
+}
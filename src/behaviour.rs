@@ -0,0 +1,119 @@
+use crate::constants::*;
+use crate::game::{Direction, Game};
+
+// What a brain does over its games, independent of how well it scores
+#[derive(Debug, PartialEq, Clone)]
+pub struct Behaviour {
+    // Fraction of moves that ended with the head in each cell, row by row
+    pub cells: Vec<f64>,
+    pub mean_length: f64,
+    pub turns_per_food: f64,
+}
+
+impl Behaviour {
+    // Fraction of the board the head has visited
+    pub fn get_coverage(&self) -> f64 {
+        self.cells.iter().filter(|c| **c > 0.0_f64).count() as f64 / self.cells.len() as f64
+    }
+
+    pub fn get_distance(&self, other: &Behaviour) -> f64 {
+        let cells: f64 = self.cells.iter().zip(other.cells.iter()).map(|(a, b)| (a - b).abs()).sum();
+        cells
+            + (self.mean_length - other.mean_length).abs() / BEHAVIOUR_LENGTH_SCALE
+            + (self.turns_per_food - other.turns_per_food).abs() / BEHAVIOUR_TURNS_SCALE
+    }
+}
+
+// Accumulates a Behaviour over several games, fed from Game::run_brain_observed
+pub struct BehaviourRecorder {
+    visits: Vec<u32>,
+    moves: u32,
+    turns: u32,
+    food: u32,
+    length: u32,
+    games: u32,
+    last_direction: Option<Direction>,
+}
+
+impl BehaviourRecorder {
+    pub fn new() -> BehaviourRecorder {
+        BehaviourRecorder {
+            visits: vec![0; BOARD_WIDTH as usize * BOARD_HEIGHT as usize],
+            moves: 0,
+            turns: 0,
+            food: 0,
+            length: 0,
+            games: 0,
+            last_direction: None,
+        }
+    }
+
+    pub fn record_move(&mut self, game: &Game) {
+        let head = game.snake.body[0].position;
+        self.visits[head.y as usize * BOARD_WIDTH as usize + head.x as usize] += 1;
+        self.moves += 1;
+        if self.last_direction.is_some_and(|d| d != game.snake.direction) {
+            self.turns += 1;
+        }
+        self.last_direction = Some(game.snake.direction);
+    }
+
+    pub fn finish_game(&mut self, game: &Game) {
+        self.food += game.score;
+        self.length += game.snake.body.len() as u32;
+        self.games += 1;
+        self.last_direction = None;
+    }
+
+    pub fn get_behaviour(&self) -> Behaviour {
+        let moves = self.moves.max(1) as f64;
+        Behaviour {
+            cells: self.visits.iter().map(|v| *v as f64 / moves).collect(),
+            mean_length: self.length as f64 / self.games.max(1) as f64,
+            turns_per_food: self.turns as f64 / self.food.max(1) as f64,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_behaviour_recorder() {
+        let mut game = Game::new();
+        game.init();
+        // Keep the food out of the way
+        game.food.position.x = 0;
+        game.food.position.y = 0;
+        let mut recorder = BehaviourRecorder::new();
+        game.next_tick(1.0);
+        recorder.record_move(&game);
+        game.update(Direction::UP);
+        game.next_tick(1.0);
+        recorder.record_move(&game);
+        game.next_tick(1.0);
+        recorder.record_move(&game);
+        game.score = 1;
+        recorder.finish_game(&game);
+        let behaviour = recorder.get_behaviour();
+        assert_eq!(behaviour.cells.iter().filter(|c| **c > 0.0).count(), 3);
+        assert!((behaviour.cells.iter().sum::<f64>() - 1.0).abs() < 1e-12);
+        assert_eq!(behaviour.turns_per_food, 1.0);
+        assert_eq!(behaviour.mean_length, 3.0);
+        assert_eq!(behaviour.get_coverage(), 3.0 / (BOARD_WIDTH as f64 * BOARD_HEIGHT as f64));
+    }
+
+    #[test]
+    fn test_behaviour_distance() {
+        let mut a = BehaviourRecorder::new().get_behaviour();
+        a.cells[0] = 1.0;
+        let mut b = a.clone();
+        assert_eq!(a.get_distance(&b), 0.0);
+        b.cells[0] = 0.0;
+        b.cells[1] = 1.0;
+        b.mean_length = BEHAVIOUR_LENGTH_SCALE;
+        assert_eq!(a.get_distance(&b), 3.0);
+        assert_eq!(b.get_distance(&a), 3.0);
+    }
+}
@@ -19,10 +19,71 @@ pub const NN_MAX_GAME_TIME: u32 = 100;
 // Q-Learing Game
 pub const NUM_GAMES_QL: u32 = 2000; // Plateau after 2000 games
 pub const NUM_QLS: u32 = 4; // Should be a multiple of number of cores
+pub const QL_REPORT_GAMES: u32 = 100;
 
 // Genetic Algorithm Properties
 pub const MUTATION_PROBABILITY: f64 = 0.005;
 pub const CROSSOVER_PROBABILITY: f64 = 0.01;
+pub const MUTATION_SIGMA: f64 = 0.1;
+pub const TOURNAMENT_SIZE: usize = 3;
+pub const GA_CHECKPOINT_EVERY: u32 = 5;
+pub const GA_CHECKPOINT_PATH: &str = "ga_checkpoint.json";
+pub const GA_ELITISM: usize = 2;
+pub const HALL_OF_FAME_SIZE: usize = 5;
+pub const NUM_ISLANDS: usize = 4;
+pub const MIGRATION_INTERVAL: u32 = 5;
+pub const NUM_MIGRANTS: usize = 2;
+
+// NEAT Properties
+pub const NEAT_NUM_INDIVIDUALS: usize = 150;
+pub const NEAT_NUM_GENERATIONS: u32 = 50;
+pub const NEAT_COMPATIBILITY_THRESHOLD: f64 = 3.0;
+pub const NEAT_EXCESS_COEFFICIENT: f64 = 1.0;
+pub const NEAT_DISJOINT_COEFFICIENT: f64 = 1.0;
+pub const NEAT_WEIGHT_COEFFICIENT: f64 = 0.4;
+pub const NEAT_WEIGHT_MUTATION_PROBABILITY: f64 = 0.8;
+pub const NEAT_WEIGHT_REPLACE_PROBABILITY: f64 = 0.1; // Per gene, otherwise perturbed
+pub const NEAT_WEIGHT_SIGMA: f64 = 0.5;
+pub const NEAT_ADD_CONNECTION_PROBABILITY: f64 = 0.05;
+pub const NEAT_ADD_CONNECTION_ATTEMPTS: u32 = 20;
+pub const NEAT_ADD_NODE_PROBABILITY: f64 = 0.03;
+pub const NEAT_CROSSOVER_PROBABILITY: f64 = 0.75;
+pub const NEAT_DISABLED_GENE_PROBABILITY: f64 = 0.75; // Gene stays disabled if disabled in either parent
+pub const NEAT_SURVIVAL_FRACTION: f64 = 0.2;
+pub const NEAT_CHAMPION_MIN_SPECIES_SIZE: usize = 5;
+
+// Evolution Strategy Properties
+pub const ES_NUM_GENERATIONS: u32 = 100;
+pub const ES_NUM_PAIRS: usize = 25; // Antithetic ES evaluates twice as many candidates
+pub const ES_SIGMA: f64 = 0.5;
+pub const ES_LEARNING_RATE: f64 = 5.0; // Centered ranks keep the update small, and the early landscape is flat
+pub const CMA_SIGMA: f64 = 0.5;
+pub const JACOBI_MAX_SWEEPS: u32 = 50;
+
+// Novelty Search & MAP-Elites Properties
+pub const BEHAVIOUR_LENGTH_SCALE: f64 = 5.0;
+pub const BEHAVIOUR_TURNS_SCALE: f64 = 10.0;
+pub const NOVELTY_K_NEAREST: usize = 15;
+pub const NOVELTY_ARCHIVE_ADD: usize = 5; // Most novel individuals archived every generation
+pub const MAP_ELITES_BINS: usize = 10;
+pub const MAP_ELITES_MAX_TURNS_PER_FOOD: f64 = 20.0; // Turns per food above this fall in the last bin
+pub const MAP_ELITES_DIR: &str = "elites";
+
+// Neural Network Properties
+pub const INPUT_ENCODING: &str = "danger_food_4dir"; // Game::get_nn_inputs
+pub const NN_SAVE_PATH: &str = "champion.json";
+pub const LEAKY_RELU_SLOPE: f64 = 0.01;
+
+// Benchmark Properties
+pub const BENCH_NUM_INDIVIDUALS: usize = 1000;
+pub const BENCH_NUM_GAMES: u32 = 50;
+pub const BENCH_NUM_PASSES: usize = 1000000;
+pub const BENCH_BATCH_SIZE: usize = 50; // About the number of games a network plays at once
+
+// Supervised Distillation Properties
+pub const DISTILL_EPOCHS: u32 = 500;
+pub const DISTILL_BATCH_SIZE: usize = 16;
+pub const DISTILL_LEARNING_RATE: f64 = 0.01;
 
 // Game Render Properties
 pub const BLOCK_SIZE: u32 = 30;
@@ -33,3 +94,28 @@ pub const RENDER_FPS_MAX: u64 = 20;
 pub const EPSILON_GREEDY: f64 = 0.0; // Looks like best results are with 0. Probably SARSA would de better here
 pub const LEARNING_RATE: f64 = 0.01; // Lower seems to be better, but too low gets worse
 pub const DISCOUNT_FACTOR: f64 = 0.9; // Seems to make not much difference
+pub const QL_SAVE_PATH: &str = "qtable.json";
+
+// Eligibility Trace Properties
+pub const LAMBDA: f64 = 0.8;
+pub const REPLACING_TRACES: bool = true;
+
+// N-Step & Monte Carlo Properties
+pub const NSTEP_N: usize = 4;
+pub const MC_EPSILON_GREEDY: f64 = 0.05;
+
+// Deep Q-Network Properties
+pub const DQN_REPLAY_CAPACITY: usize = 10000;
+pub const DQN_BATCH_SIZE: usize = 32;
+pub const DQN_TARGET_SYNC_STEPS: u32 = 500;
+pub const DQN_LEARNING_RATE: f64 = 0.001; // Adam
+pub const DQN_EPSILON_START: f64 = 1.0;
+pub const DQN_EPSILON_DECAY: f64 = 0.995;
+pub const DQN_EPSILON_MIN: f64 = 0.01;
+
+// Dyna-Q Properties
+pub const DYNA_PLANNING_STEPS: usize = 10;
+
+// Evaluation Properties
+pub const EVAL_NUM_GAMES: u32 = 5000;
+pub const EVAL_SEED: u64 = 0;
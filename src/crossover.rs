@@ -0,0 +1,161 @@
+extern crate rand;
+
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, PartialEq, Copy, Clone, Serialize, Deserialize)]
+pub enum CrossoverMethod {
+    // Every gene is swapped between the children with probability rate
+    Uniform { rate: f64 },
+    // The genomes are cut at one random point and the tails swapped
+    SinglePoint,
+    // The segment between two random points is swapped
+    TwoPoint,
+    // Every neuron keeps its bias and incoming weights together and is swapped with probability rate
+    Neuron { rate: f64 },
+    // The children are the weighted averages alpha * p1 + (1 - alpha) * p2 and vice versa, for a random alpha
+    Arithmetic,
+    // BLX-alpha: every gene is sampled from the range spanned by the parents, widened by alpha on each side
+    Blend { alpha: f64 },
+}
+
+impl CrossoverMethod {
+    // neuron_sizes gives the length of each neuron's block of genes, in genome order
+    pub fn crossover(
+        &self,
+        g1: &[f64],
+        g2: &[f64],
+        neuron_sizes: &[usize],
+        rng: &mut impl Rng,
+    ) -> (Vec<f64>, Vec<f64>) {
+        let mut c1 = g1.to_vec();
+        let mut c2 = g2.to_vec();
+        let len = g1.len().min(g2.len());
+        match *self {
+            CrossoverMethod::Uniform { rate } => {
+                for i in 0..len {
+                    if rng.gen::<f64>() < rate {
+                        c1[i] = g2[i];
+                        c2[i] = g1[i];
+                    }
+                }
+            }
+            CrossoverMethod::SinglePoint => {
+                if len > 1 {
+                    let point = rng.gen_range(1, len);
+                    swap_range(&mut c1, &mut c2, point, len);
+                }
+            }
+            CrossoverMethod::TwoPoint => {
+                if len > 1 {
+                    let a = rng.gen_range(0, len);
+                    let b = rng.gen_range(0, len);
+                    swap_range(&mut c1, &mut c2, a.min(b), a.max(b) + 1);
+                }
+            }
+            CrossoverMethod::Neuron { rate } => {
+                let mut start = 0;
+                for size in neuron_sizes.iter() {
+                    let end = (start + size).min(len);
+                    if rng.gen::<f64>() < rate {
+                        swap_range(&mut c1, &mut c2, start, end);
+                    }
+                    start = end;
+                }
+            }
+            CrossoverMethod::Arithmetic => {
+                let alpha = rng.gen::<f64>();
+                for i in 0..len {
+                    c1[i] = alpha * g1[i] + (1.0_f64 - alpha) * g2[i];
+                    c2[i] = (1.0_f64 - alpha) * g1[i] + alpha * g2[i];
+                }
+            }
+            CrossoverMethod::Blend { alpha } => {
+                for i in 0..len {
+                    let low = g1[i].min(g2[i]);
+                    let high = g1[i].max(g2[i]);
+                    let margin = alpha * (high - low);
+                    c1[i] = low - margin + rng.gen::<f64>() * (high - low + 2.0_f64 * margin);
+                    c2[i] = low - margin + rng.gen::<f64>() * (high - low + 2.0_f64 * margin);
+                }
+            }
+        }
+        (c1, c2)
+    }
+}
+
+fn swap_range(c1: &mut [f64], c2: &mut [f64], start: usize, end: usize) {
+    c1[start..end].swap_with_slice(&mut c2[start..end]);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+    use rand_pcg::Pcg32;
+
+    fn get_parents() -> (Vec<f64>, Vec<f64>) {
+        ((0..10).map(|_| 0.0).collect(), (0..10).map(|_| 1.0).collect())
+    }
+
+    #[test]
+    fn test_crossover_swaps_genes() {
+        let mut rng = Pcg32::seed_from_u64(2);
+        let (g1, g2) = get_parents();
+        let sizes = [2, 3, 5];
+        let methods = [
+            CrossoverMethod::Uniform { rate: 0.5 },
+            CrossoverMethod::SinglePoint,
+            CrossoverMethod::TwoPoint,
+            CrossoverMethod::Neuron { rate: 0.5 },
+        ];
+        for method in methods.iter() {
+            for _ in 0..20 {
+                let (c1, c2) = method.crossover(&g1, &g2, &sizes, &mut rng);
+                // Genes are only moved between the children, never changed
+                for i in 0..10 {
+                    assert_eq!(c1[i] + c2[i], 1.0, "{:?}", method);
+                }
+            }
+        }
+        let (c1, _) = CrossoverMethod::Uniform { rate: 0.0 }.crossover(&g1, &g2, &sizes, &mut rng);
+        assert_eq!(c1, g1);
+        let (c1, _) = CrossoverMethod::Uniform { rate: 1.0 }.crossover(&g1, &g2, &sizes, &mut rng);
+        assert_eq!(c1, g2);
+    }
+
+    #[test]
+    fn test_crossover_structure() {
+        let mut rng = Pcg32::seed_from_u64(3);
+        let (g1, g2) = get_parents();
+        for _ in 0..20 {
+            // A single point leaves a prefix from one parent and a suffix from the other
+            let (c1, _) = CrossoverMethod::SinglePoint.crossover(&g1, &g2, &[10], &mut rng);
+            assert_eq!(c1[0], 0.0);
+            assert_eq!(c1[9], 1.0);
+            assert!(c1.windows(2).all(|w| w[0] <= w[1]));
+            // Whole neurons are swapped
+            let (c1, _) = CrossoverMethod::Neuron { rate: 0.5 }.crossover(&g1, &g2, &[2, 3, 5], &mut rng);
+            assert_eq!(c1[0], c1[1]);
+            assert!(c1[2] == c1[3] && c1[3] == c1[4]);
+            assert!(c1[5..].iter().all(|g| *g == c1[5]));
+        }
+    }
+
+    #[test]
+    fn test_crossover_arithmetic_blend() {
+        let mut rng = Pcg32::seed_from_u64(4);
+        let (g1, g2) = get_parents();
+        let (c1, c2) = CrossoverMethod::Arithmetic.crossover(&g1, &g2, &[], &mut rng);
+        for i in 0..10 {
+            assert!((c1[i] + c2[i] - 1.0).abs() < 1e-12);
+            assert_eq!(c1[i], c1[0]);
+        }
+        let (c1, c2) = CrossoverMethod::Blend { alpha: 0.5 }.crossover(&g1, &g2, &[], &mut rng);
+        assert!(c1.iter().chain(c2.iter()).all(|g| *g >= -0.5 && *g <= 1.5));
+        assert!(c1.iter().any(|g| *g != c1[0]));
+        // Identical parents give identical children
+        let (c1, _) = CrossoverMethod::Blend { alpha: 0.5 }.crossover(&g1, &g1, &[], &mut rng);
+        assert_eq!(c1, g1);
+    }
+}
@@ -0,0 +1,270 @@
+extern crate rand;
+
+use rand::Rng;
+
+use crate::backprop::{Gradients, Loss, Optimizer};
+use crate::constants::*;
+use crate::error::Result;
+use crate::game::{Brain, EpisodeOutcome, Transition};
+use crate::gen_alg::{get_output_action, Activation, NN};
+
+pub struct Experience {
+    pub state: Vec<f64>,
+    pub action: usize,
+    pub reward: f64,
+    pub next_state: Vec<f64>,
+    pub done: bool,
+}
+
+pub struct ReplayBuffer {
+    pub capacity: usize,
+    pub experiences: Vec<Experience>,
+    next: usize,
+}
+
+impl ReplayBuffer {
+    pub fn new(capacity: usize) -> ReplayBuffer {
+        ReplayBuffer {
+            capacity: capacity.max(1),
+            experiences: Vec::new(),
+            next: 0,
+        }
+    }
+
+    pub fn push(&mut self, experience: Experience) {
+        // Once full, the oldest experience is overwritten
+        if self.experiences.len() < self.capacity {
+            self.experiences.push(experience);
+        } else {
+            self.experiences[self.next] = experience;
+        }
+        self.next = (self.next + 1) % self.capacity;
+    }
+
+    pub fn len(&self) -> usize {
+        self.experiences.len()
+    }
+
+    pub fn sample_indices(&self, batch_size: usize) -> Vec<usize> {
+        let mut rng = rand::thread_rng();
+        (0..batch_size)
+            .map(|_| rng.gen_range(0, self.experiences.len()))
+            .collect()
+    }
+}
+
+pub struct Dqn {
+    pub nn: NN,
+    pub target_nn: NN,
+    pub replay: ReplayBuffer,
+    pub epsilon: f64,
+    pub optimizer: Optimizer,
+    pub discount_factor: f64,
+    pub batch_size: usize,
+    pub target_sync_steps: u32,
+    pub steps: u32,
+    pub training: bool,
+}
+
+impl Dqn {
+    pub fn new(layer_def: &[[usize; 2]]) -> Dqn {
+        // Q values are unbounded, so the output layer is linear
+        let mut activations = vec![Activation::Relu; layer_def.len()];
+        if let Some(last) = activations.last_mut() {
+            *last = Activation::Linear;
+        }
+        let nn = NN::new_defined_with_activations(layer_def, &activations, &[]);
+        Dqn {
+            target_nn: nn.clone(),
+            nn,
+            replay: ReplayBuffer::new(DQN_REPLAY_CAPACITY),
+            epsilon: DQN_EPSILON_START,
+            optimizer: Optimizer::adam(DQN_LEARNING_RATE),
+            discount_factor: DISCOUNT_FACTOR,
+            batch_size: DQN_BATCH_SIZE,
+            target_sync_steps: DQN_TARGET_SYNC_STEPS,
+            steps: 0,
+            training: true,
+        }
+    }
+
+    fn get_experience_gradients(&self, experience: &Experience) -> Result<Gradients> {
+        let target = if experience.done {
+            experience.reward
+        } else {
+            let next_outputs = self.target_nn.propagate(&experience.next_state)?;
+            let next_max = next_outputs.iter().cloned().fold(f64::NEG_INFINITY, f64::max);
+            experience.reward + self.discount_factor * next_max
+        };
+
+        // Only the Q value of the action taken is moved towards the target
+        let outputs = self.nn.propagate(&experience.state)?;
+        let mut targets = outputs.clone();
+        targets[experience.action] = target;
+        let output_gradient = Loss::MeanSquaredError.get_output_gradient(&outputs, &targets);
+        self.nn.get_gradients(&experience.state, &output_gradient)
+    }
+
+    fn learn_from_replay(&mut self) -> Option<bool> {
+        let indices = self.replay.sample_indices(self.batch_size);
+        let mut gradients = Gradients::zeros(&self.nn);
+        for i in indices {
+            let g = self.get_experience_gradients(&self.replay.experiences[i]).ok()?;
+            gradients.add(&g);
+        }
+        gradients.scale(1.0_f64 / self.batch_size as f64);
+        self.optimizer.step(&mut self.nn, &gradients);
+        Some(true)
+    }
+}
+
+impl Brain for Dqn {
+    fn get_action(&mut self, inputs: &[f64]) -> Result<usize> {
+        let mut rng = rand::thread_rng();
+        let outputs = self.nn.propagate(inputs)?;
+        if self.training && rng.gen::<f64>() > 1.0_f64 - self.epsilon {
+            Ok(rng.gen_range(0, outputs.len()))
+        } else {
+            get_output_action(&outputs)
+        }
+    }
+
+    fn train(&mut self, transition: &Transition) -> Option<bool> {
+        let num_actions = self.nn.layers.last()?.num_neurons as usize;
+        if transition.action >= num_actions {
+            return None;
+        }
+        if !self.training {
+            return Some(false);
+        }
+        self.replay.push(Experience {
+            state: transition.state.to_vec(),
+            action: transition.action,
+            reward: transition.reward,
+            next_state: transition.next_state.to_vec(),
+            done: transition.done,
+        });
+
+        let result = if self.replay.len() < self.batch_size {
+            Some(true)
+        } else {
+            self.learn_from_replay()
+        };
+        self.steps += 1;
+        if self.steps.is_multiple_of(self.target_sync_steps) {
+            self.target_nn = self.nn.clone();
+        }
+        result
+    }
+
+    fn on_episode_end(&mut self, _outcome: &EpisodeOutcome) {
+        if self.training {
+            self.epsilon = (self.epsilon * DQN_EPSILON_DECAY).max(DQN_EPSILON_MIN);
+        }
+    }
+
+    fn set_training(&mut self, training: bool) {
+        self.training = training;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::Game;
+
+    #[test]
+    fn test_replay_buffer_push() {
+        let mut rb = ReplayBuffer::new(2);
+        for i in 0..3 {
+            rb.push(Experience {
+                state: vec![i as f64],
+                action: 0,
+                reward: 0.0_f64,
+                next_state: vec![0.0_f64],
+                done: false,
+            });
+        }
+        assert_eq!(rb.len(), 2);
+        assert_eq!(rb.experiences[0].state, vec![2.0_f64]);
+        assert_eq!(rb.experiences[1].state, vec![1.0_f64]);
+        assert!(rb.sample_indices(10).iter().all(|i| *i < 2));
+    }
+
+    #[test]
+    fn test_dqn_new() {
+        let dqn = Dqn::new(&[[8, 8], [8, 4]]);
+        assert_eq!(dqn.nn.layers[0].activation, Activation::Relu);
+        assert_eq!(dqn.nn.layers[1].activation, Activation::Linear);
+        assert_eq!(dqn.target_nn.layers[1].weights, dqn.nn.layers[1].weights);
+    }
+
+    #[test]
+    fn test_dqn_train() {
+        let mut dqn = Dqn::new(&[[2, 4], [4, 2]]);
+        dqn.discount_factor = 0.0_f64;
+        dqn.optimizer.learning_rate = 0.1_f64;
+        let s0 = vec![1.0_f64, 0.0_f64];
+        let before = dqn.nn.propagate(&s0).unwrap()[1];
+        for _ in 0..500 {
+            assert_eq!(
+                dqn.train(&Transition::new(&s0, 1, before + 5.0_f64, &s0, false)),
+                Some(true)
+            );
+        }
+        // A constant reward pushes the Q value of that action towards it
+        let after = dqn.nn.propagate(&s0).unwrap()[1];
+        assert!((after - before - 5.0_f64).abs() < 1.0_f64);
+        assert_eq!(dqn.train(&Transition::new(&s0, 2, 0.0_f64, &s0, false)), None);
+    }
+
+    #[test]
+    fn test_dqn_done() {
+        let mut dqn = Dqn::new(&[[2, 4], [4, 2]]);
+        let s0 = vec![1.0_f64, 0.0_f64];
+        let s1 = vec![0.0_f64, 1.0_f64];
+        dqn.target_nn.layers[1].biases = vec![100.0_f64; 2];
+        let experience = |done| Experience {
+            state: s0.clone(),
+            action: 0,
+            reward: 1.0_f64,
+            next_state: s1.clone(),
+            done,
+        };
+        let q = dqn.nn.propagate(&s0).unwrap()[0];
+        let g_done = dqn.get_experience_gradients(&experience(true)).unwrap().biases[1][0];
+        let g_next = dqn.get_experience_gradients(&experience(false)).unwrap().biases[1][0];
+        // A final move has nothing to bootstrap from, so its target is the reward alone
+        assert!((g_done - (q - 1.0_f64)).abs() < 1e-9);
+        assert!(g_next < g_done);
+    }
+
+    #[test]
+    fn test_dqn_frozen() {
+        let mut dqn = Dqn::new(&[[2, 4], [4, 2]]);
+        dqn.batch_size = 1;
+        dqn.set_training(false);
+        let s0 = vec![1.0_f64, 0.0_f64];
+        let greedy = get_output_action(&dqn.nn.propagate(&s0).unwrap()).unwrap();
+        for _ in 0..20 {
+            assert_eq!(dqn.get_action(&s0).unwrap(), greedy);
+        }
+        assert_eq!(dqn.train(&Transition::new(&s0, 0, 1.0_f64, &s0, false)), Some(false));
+        assert_eq!(dqn.replay.len(), 0);
+        let epsilon = dqn.epsilon;
+        dqn.on_episode_end(&Game::new().get_outcome());
+        assert_eq!(dqn.epsilon, epsilon);
+    }
+
+    #[test]
+    fn test_dqn_target_sync() {
+        let mut dqn = Dqn::new(&[[2, 4], [4, 2]]);
+        dqn.target_sync_steps = 1;
+        let s0 = vec![1.0_f64, 0.0_f64];
+        dqn.batch_size = 1;
+        let before = dqn.nn.layers[1].biases.clone();
+        dqn.train(&Transition::new(&s0, 0, 1.0_f64, &s0, false));
+        assert!(dqn.nn.layers[1].biases != before);
+        assert_eq!(dqn.target_nn.layers[1].biases, dqn.nn.layers[1].biases);
+    }
+}
@@ -0,0 +1,151 @@
+extern crate rand;
+
+use rand::Rng;
+use std::collections::HashMap;
+
+use crate::error::Result;
+use crate::game::{Brain, EpisodeOutcome, Transition};
+use crate::qlearn::QLearner;
+
+// The 8 input state does not fully describe the board, so the same state-action pair can lead to different outcomes
+pub struct ModelEntry {
+    pub reward_sum: f64,
+    pub count: u32,
+    // Keyed by next state and whether the move ended the game
+    pub next_states: HashMap<(usize, bool), u32>,
+}
+
+impl ModelEntry {
+    fn new() -> ModelEntry {
+        ModelEntry {
+            reward_sum: 0.0_f64,
+            count: 0,
+            next_states: HashMap::new(),
+        }
+    }
+
+    fn add(&mut self, reward: f64, index_final: usize, done: bool) {
+        self.reward_sum += reward;
+        self.count += 1;
+        *self.next_states.entry((index_final, done)).or_insert(0) += 1;
+    }
+
+    fn sample(&self, rng: &mut impl Rng) -> (f64, usize, bool) {
+        let mut pick = rng.gen_range(0, self.count);
+        let mut next = (0, false);
+        for (&key, &count) in self.next_states.iter() {
+            next = key;
+            if pick < count {
+                break;
+            }
+            pick -= count;
+        }
+        (self.reward_sum / self.count as f64, next.0, next.1)
+    }
+}
+
+pub struct DynaQ {
+    pub ql: QLearner,
+    pub planning_steps: usize,
+    pub model: HashMap<(usize, usize), ModelEntry>,
+    observed: Vec<(usize, usize)>,
+}
+
+impl DynaQ {
+    pub fn new(len_states: usize, num_actions: usize, planning_steps: usize) -> DynaQ {
+        DynaQ {
+            ql: QLearner::new(len_states, num_actions),
+            planning_steps,
+            model: HashMap::new(),
+            observed: Vec::new(),
+        }
+    }
+
+    fn plan(&mut self) {
+        if self.observed.is_empty() {
+            return;
+        }
+        let mut rng = rand::thread_rng();
+        for _ in 0..self.planning_steps {
+            let (si, action) = self.observed[rng.gen_range(0, self.observed.len())];
+            let (reward, fi, done) = self.model[&(si, action)].sample(&mut rng);
+            self.ql.update(si, action, reward, fi, done);
+        }
+    }
+}
+
+impl Brain for DynaQ {
+    fn get_action(&mut self, state: &[f64]) -> Result<usize> {
+        let index = self.ql.get_state_index(state);
+        Ok(self.ql.get_epsilon_greedy_action(index))
+    }
+
+    fn train(&mut self, transition: &Transition) -> Option<bool> {
+        let action = transition.action;
+        if action >= self.ql.num_actions {
+            return None;
+        }
+        if !self.ql.training {
+            return Some(false);
+        }
+        let ii = self.ql.get_state_index(transition.state);
+        let fi = self.ql.get_state_index(transition.next_state);
+        self.ql.update(ii, action, transition.reward, fi, transition.done);
+
+        let observed = &mut self.observed;
+        self.model
+            .entry((ii, action))
+            .or_insert_with(|| {
+                observed.push((ii, action));
+                ModelEntry::new()
+            })
+            .add(transition.reward, fi, transition.done);
+        self.plan();
+        Some(true)
+    }
+
+    fn on_episode_end(&mut self, outcome: &EpisodeOutcome) {
+        self.ql.on_episode_end(outcome);
+    }
+
+    fn set_training(&mut self, training: bool) {
+        self.ql.set_training(training);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dynaq_new() {
+        let dq = DynaQ::new(8, 4, 5);
+        assert_eq!(dq.ql.q.len(), 2_usize.pow(8));
+        assert!(dq.model.is_empty());
+    }
+
+    #[test]
+    fn test_dynaq_train() {
+        let s0 = vec![0.0_f64, 0.0_f64];
+        let s1 = vec![0.0_f64, 1.0_f64];
+        let mut ql = QLearner::new(2, 4);
+        let mut dq = DynaQ::new(2, 4, 20);
+        ql.train(&Transition::new(&s0, 0, 1.0_f64, &s1, false));
+        dq.train(&Transition::new(&s0, 0, 1.0_f64, &s1, false));
+        assert_eq!(dq.model[&(0, 0)].count, 1);
+        assert_eq!(dq.observed.len(), 1);
+        // Planning replays the same transition, so the value moves further than a single update
+        assert!(dq.ql.q[0].quality[0] > ql.q[0].quality[0]);
+        dq.train(&Transition::new(&s0, 0, 2.0_f64, &s0, true));
+        assert_eq!(dq.observed.len(), 1);
+        let entry = &dq.model[&(0, 0)];
+        assert_eq!(entry.count, 2);
+        assert_eq!(entry.next_states.len(), 2);
+        let mut rng = rand::thread_rng();
+        for _ in 0..10 {
+            let (reward, fi, done) = entry.sample(&mut rng);
+            assert_eq!(reward, 1.5_f64);
+            assert!((fi == 1 && !done) || (fi == 0 && done));
+        }
+    }
+}
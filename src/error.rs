@@ -0,0 +1,96 @@
+use std::fmt;
+use std::io;
+
+#[derive(Debug)]
+pub enum Error {
+    // A slice or layer does not have the size it is combined with
+    ShapeMismatch {
+        what: &'static str,
+        expected: usize,
+        found: usize,
+    },
+    InvalidConfig(String),
+    Io(io::Error),
+    // A file that could be read but not understood
+    Parse(String),
+    // Wraps the error of one member of a population, so a trainer can say which one failed
+    Individual {
+        index: usize,
+        source: Box<Error>,
+    },
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl Error {
+    pub fn individual(index: usize, source: Error) -> Error {
+        Error::Individual {
+            index,
+            source: Box::new(source),
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::ShapeMismatch { what, expected, found } => {
+                write!(f, "{} has size {}, expected {}", what, found, expected)
+            }
+            Error::InvalidConfig(message) => write!(f, "invalid config: {}", message),
+            Error::Io(e) => write!(f, "{}", e),
+            Error::Parse(message) => write!(f, "{}", message),
+            Error::Individual { index, source } => write!(f, "individual {}: {}", index, source),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(e) => Some(e),
+            Error::Individual { source, .. } => Some(source.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Error {
+        Error::Io(e)
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(e: serde_json::Error) -> Error {
+        Error::Parse(e.to_string())
+    }
+}
+
+impl From<bincode::Error> for Error {
+    fn from(e: bincode::Error) -> Error {
+        Error::Parse(e.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_error_display() {
+        let e = Error::ShapeMismatch {
+            what: "inputs",
+            expected: 8,
+            found: 7,
+        };
+        assert_eq!(e.to_string(), "inputs has size 7, expected 8");
+        let e = Error::individual(3, e);
+        assert_eq!(e.to_string(), "individual 3: inputs has size 7, expected 8");
+        assert!(std::error::Error::source(&e).is_some());
+        let e: Error = io::Error::new(io::ErrorKind::NotFound, "missing").into();
+        assert_eq!(e.to_string(), "missing");
+        let e: Error = serde_json::from_str::<u32>("x").unwrap_err().into();
+        assert!(matches!(e, Error::Parse(_)));
+    }
+}
@@ -0,0 +1,360 @@
+extern crate rand;
+
+use rand::SeedableRng;
+use rand_pcg::Pcg32;
+
+use crate::constants::*;
+use crate::gen_alg::{get_normal_with, get_sorted_index};
+
+// Ask-tell interface over a weight vector, e.g. NN::get_genome
+pub trait EvolutionStrategy {
+    // Candidate weight vectors to evaluate this generation
+    fn ask(&mut self) -> Vec<Vec<f64>>;
+    // Updates the search distribution from the fitness of the candidates returned by ask
+    fn tell(&mut self, candidates: &[Vec<f64>], fitness: &[f64]);
+    // The current best estimate
+    fn get_mean(&self) -> &[f64];
+}
+
+// Fitness rank shaping: ranks mapped linearly onto [-0.5, 0.5], best highest
+pub fn get_centered_ranks(fitness: &[f64]) -> Vec<f64> {
+    let n = fitness.len();
+    let mut ranks = vec![0.0_f64; n];
+    if n < 2 {
+        return ranks;
+    }
+    for (rank, i) in get_sorted_index(fitness).into_iter().enumerate() {
+        ranks[i] = (n - 1 - rank) as f64 / (n - 1) as f64 - 0.5_f64;
+    }
+    ranks
+}
+
+// Natural evolution strategy with antithetic (mirrored) sampling, as in Salimans et al. 2017
+pub struct AntitheticEs {
+    pub mean: Vec<f64>,
+    pub sigma: f64,
+    pub learning_rate: f64,
+    // Number of mirrored pairs, so every generation evaluates twice as many candidates
+    pub num_pairs: usize,
+    pub rng: Pcg32,
+    noise: Vec<Vec<f64>>,
+}
+
+impl AntitheticEs {
+    pub fn new(mean: Vec<f64>) -> AntitheticEs {
+        AntitheticEs {
+            mean,
+            sigma: ES_SIGMA,
+            learning_rate: ES_LEARNING_RATE,
+            num_pairs: ES_NUM_PAIRS,
+            rng: Pcg32::from_rng(rand::thread_rng()).unwrap(),
+            noise: Vec::new(),
+        }
+    }
+}
+
+impl EvolutionStrategy for AntitheticEs {
+    fn ask(&mut self) -> Vec<Vec<f64>> {
+        let n = self.mean.len();
+        let rng = &mut self.rng;
+        self.noise = (0..self.num_pairs)
+            .map(|_| (0..n).map(|_| get_normal_with(rng)).collect())
+            .collect();
+        let mut candidates = Vec::new();
+        for eps in self.noise.iter() {
+            candidates.push(self.mean.iter().zip(eps.iter()).map(|(m, e)| m + self.sigma * e).collect());
+            candidates.push(self.mean.iter().zip(eps.iter()).map(|(m, e)| m - self.sigma * e).collect());
+        }
+        candidates
+    }
+
+    fn tell(&mut self, _candidates: &[Vec<f64>], fitness: &[f64]) {
+        let ranks = get_centered_ranks(fitness);
+        let scale = self.learning_rate / (fitness.len() as f64 * self.sigma);
+        for (k, eps) in self.noise.iter().enumerate() {
+            // The mirrored pair contributes the difference of its shaped fitness
+            let weight = ranks[2 * k] - ranks[2 * k + 1];
+            for (m, e) in self.mean.iter_mut().zip(eps.iter()) {
+                *m += scale * weight * e;
+            }
+        }
+    }
+
+    fn get_mean(&self) -> &[f64] {
+        &self.mean
+    }
+}
+
+// Covariance matrix adaptation, following Hansen's "The CMA Evolution Strategy: A Tutorial"
+pub struct CmaEs {
+    pub mean: Vec<f64>,
+    pub sigma: f64,
+    pub lambda: usize,
+    pub rng: Pcg32,
+    mu: usize,
+    weights: Vec<f64>,
+    mueff: f64,
+    cc: f64,
+    cs: f64,
+    c1: f64,
+    cmu: f64,
+    damps: f64,
+    chi_n: f64,
+    pc: Vec<f64>,
+    ps: Vec<f64>,
+    c: Vec<Vec<f64>>,
+    // Eigenvectors of c as columns, and the square roots of its eigenvalues
+    b: Vec<Vec<f64>>,
+    d: Vec<f64>,
+    generation: u32,
+    eigen_generation: u32,
+}
+
+impl CmaEs {
+    pub fn new(mean: Vec<f64>, sigma: f64) -> CmaEs {
+        let n = mean.len();
+        let nf = n as f64;
+        let lambda = 4 + (3.0_f64 * nf.ln()).floor() as usize;
+        let mu = lambda / 2;
+        let raw: Vec<f64> = (0..mu)
+            .map(|i| (mu as f64 + 0.5_f64).ln() - ((i + 1) as f64).ln())
+            .collect();
+        let total: f64 = raw.iter().sum();
+        let weights: Vec<f64> = raw.iter().map(|w| w / total).collect();
+        let mueff = 1.0_f64 / weights.iter().map(|w| w * w).sum::<f64>();
+        let cc = (4.0_f64 + mueff / nf) / (nf + 4.0_f64 + 2.0_f64 * mueff / nf);
+        let cs = (mueff + 2.0_f64) / (nf + mueff + 5.0_f64);
+        let c1 = 2.0_f64 / ((nf + 1.3_f64).powi(2) + mueff);
+        let cmu = (1.0_f64 - c1)
+            .min(2.0_f64 * (mueff - 2.0_f64 + 1.0_f64 / mueff) / ((nf + 2.0_f64).powi(2) + mueff));
+        let damps = 1.0_f64 + 2.0_f64 * (((mueff - 1.0_f64) / (nf + 1.0_f64)).sqrt() - 1.0_f64).max(0.0_f64) + cs;
+        let chi_n = nf.sqrt() * (1.0_f64 - 1.0_f64 / (4.0_f64 * nf) + 1.0_f64 / (21.0_f64 * nf * nf));
+        CmaEs {
+            mean,
+            sigma,
+            lambda,
+            rng: Pcg32::from_rng(rand::thread_rng()).unwrap(),
+            mu,
+            weights,
+            mueff,
+            cc,
+            cs,
+            c1,
+            cmu,
+            damps,
+            chi_n,
+            pc: vec![0.0_f64; n],
+            ps: vec![0.0_f64; n],
+            c: get_identity(n),
+            b: get_identity(n),
+            d: vec![1.0_f64; n],
+            generation: 0,
+            eigen_generation: 0,
+        }
+    }
+
+    fn update_eigen(&mut self) {
+        let n = self.mean.len();
+        for i in 0..n {
+            for j in 0..i {
+                let v = 0.5_f64 * (self.c[i][j] + self.c[j][i]);
+                self.c[i][j] = v;
+                self.c[j][i] = v;
+            }
+        }
+        let (values, vectors) = get_eigen(&self.c);
+        self.d = values.iter().map(|v| v.max(1e-20_f64).sqrt()).collect();
+        self.b = vectors;
+        self.eigen_generation = self.generation;
+    }
+}
+
+impl EvolutionStrategy for CmaEs {
+    fn ask(&mut self) -> Vec<Vec<f64>> {
+        let n = self.mean.len();
+        (0..self.lambda)
+            .map(|_| {
+                let z: Vec<f64> = (0..n).map(|_| get_normal_with(&mut self.rng)).collect();
+                // x = m + sigma * B * D * z
+                (0..n)
+                    .map(|i| self.mean[i] + self.sigma * (0..n).map(|j| self.b[i][j] * self.d[j] * z[j]).sum::<f64>())
+                    .collect()
+            })
+            .collect()
+    }
+
+    fn tell(&mut self, candidates: &[Vec<f64>], fitness: &[f64]) {
+        let n = self.mean.len();
+        let nf = n as f64;
+        let order = get_sorted_index(fitness);
+        let ys: Vec<Vec<f64>> = order
+            .iter()
+            .take(self.mu)
+            .map(|k| (0..n).map(|i| (candidates[*k][i] - self.mean[i]) / self.sigma).collect())
+            .collect();
+        let y_w: Vec<f64> = (0..n)
+            .map(|i| ys.iter().zip(self.weights.iter()).map(|(y, w)| w * y[i]).sum())
+            .collect();
+        for (m, y) in self.mean.iter_mut().zip(y_w.iter()) {
+            *m += self.sigma * y;
+        }
+
+        // C^(-1/2) * y_w = B * D^-1 * B^T * y_w
+        let bt_y: Vec<f64> = (0..n)
+            .map(|j| (0..n).map(|i| self.b[i][j] * y_w[i]).sum::<f64>() / self.d[j])
+            .collect();
+        let c_inv_sqrt_y: Vec<f64> = (0..n).map(|i| (0..n).map(|j| self.b[i][j] * bt_y[j]).sum()).collect();
+        let cs_scale = (self.cs * (2.0_f64 - self.cs) * self.mueff).sqrt();
+        for (p, y) in self.ps.iter_mut().zip(c_inv_sqrt_y.iter()) {
+            *p = (1.0_f64 - self.cs) * *p + cs_scale * y;
+        }
+        let ps_norm = self.ps.iter().map(|p| p * p).sum::<f64>().sqrt();
+        self.generation += 1;
+        let ps_expected = (1.0_f64 - (1.0_f64 - self.cs).powi(2 * self.generation as i32)).sqrt();
+        let hsig = ps_norm / ps_expected / self.chi_n < 1.4_f64 + 2.0_f64 / (nf + 1.0_f64);
+        let hsig = if hsig { 1.0_f64 } else { 0.0_f64 };
+        let cc_scale = (self.cc * (2.0_f64 - self.cc) * self.mueff).sqrt();
+        for (p, y) in self.pc.iter_mut().zip(y_w.iter()) {
+            *p = (1.0_f64 - self.cc) * *p + hsig * cc_scale * y;
+        }
+
+        // Rank-one update from the evolution path plus rank-mu update from the selected steps
+        let decay = 1.0_f64 - self.c1 - self.cmu + (1.0_f64 - hsig) * self.c1 * self.cc * (2.0_f64 - self.cc);
+        for i in 0..n {
+            for j in 0..n {
+                let rank_mu: f64 = ys.iter().zip(self.weights.iter()).map(|(y, w)| w * y[i] * y[j]).sum();
+                self.c[i][j] = decay * self.c[i][j] + self.c1 * self.pc[i] * self.pc[j] + self.cmu * rank_mu;
+            }
+        }
+        self.sigma *= ((self.cs / self.damps) * (ps_norm / self.chi_n - 1.0_f64)).exp();
+
+        // The eigendecomposition is O(n^3), so it is only refreshed every few generations
+        let interval = self.lambda as f64 / (self.c1 + self.cmu) / nf / 10.0_f64;
+        if (self.generation - self.eigen_generation) as f64 > interval {
+            self.update_eigen();
+        }
+    }
+
+    fn get_mean(&self) -> &[f64] {
+        &self.mean
+    }
+}
+
+fn get_identity(n: usize) -> Vec<Vec<f64>> {
+    (0..n)
+        .map(|i| (0..n).map(|j| if i == j { 1.0_f64 } else { 0.0_f64 }).collect())
+        .collect()
+}
+
+// Cyclic Jacobi eigenvalue algorithm for a symmetric matrix, returning the eigenvalues and the eigenvectors as columns
+pub fn get_eigen(matrix: &[Vec<f64>]) -> (Vec<f64>, Vec<Vec<f64>>) {
+    let n = matrix.len();
+    let mut a = matrix.to_vec();
+    let mut v = get_identity(n);
+    for _ in 0..JACOBI_MAX_SWEEPS {
+        let off: f64 = (0..n)
+            .flat_map(|i| (0..n).filter(move |j| *j != i).map(move |j| (i, j)))
+            .map(|(i, j)| a[i][j] * a[i][j])
+            .sum();
+        if off < 1e-22_f64 {
+            break;
+        }
+        for p in 0..n {
+            for q in p + 1..n {
+                if a[p][q].abs() < 1e-300_f64 {
+                    continue;
+                }
+                let theta = (a[q][q] - a[p][p]) / (2.0_f64 * a[p][q]);
+                let t = theta.signum() / (theta.abs() + (theta * theta + 1.0_f64).sqrt());
+                let c = 1.0_f64 / (t * t + 1.0_f64).sqrt();
+                let s = t * c;
+                for row in a.iter_mut() {
+                    let akp = row[p];
+                    let akq = row[q];
+                    row[p] = c * akp - s * akq;
+                    row[q] = s * akp + c * akq;
+                }
+                let (top, bottom) = a.split_at_mut(q);
+                for (apk, aqk) in top[p].iter_mut().zip(bottom[0].iter_mut()) {
+                    let (x, y) = (*apk, *aqk);
+                    *apk = c * x - s * y;
+                    *aqk = s * x + c * y;
+                }
+                for row in v.iter_mut() {
+                    let vkp = row[p];
+                    let vkq = row[q];
+                    row[p] = c * vkp - s * vkq;
+                    row[q] = s * vkp + c * vkq;
+                }
+            }
+        }
+    }
+    ((0..n).map(|i| a[i][i]).collect(), v)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Maximised at x = (1, 2, ..., n)
+    fn get_sphere_fitness(x: &[f64]) -> f64 {
+        -x.iter().enumerate().map(|(i, v)| (v - (i + 1) as f64).powi(2)).sum::<f64>()
+    }
+
+    fn run<T: EvolutionStrategy>(es: &mut T, generations: u32) -> f64 {
+        for _ in 0..generations {
+            let candidates = es.ask();
+            let fitness: Vec<f64> = candidates.iter().map(|x| get_sphere_fitness(x)).collect();
+            es.tell(&candidates, &fitness);
+        }
+        get_sphere_fitness(es.get_mean())
+    }
+
+    #[test]
+    fn test_centered_ranks() {
+        assert_eq!(get_centered_ranks(&[3.0, -1.0, 10.0]), vec![0.0, -0.5, 0.5]);
+        assert_eq!(get_centered_ranks(&[3.0]), vec![0.0]);
+    }
+
+    #[test]
+    fn test_eigen() {
+        let m = vec![vec![4.0, 1.0, 0.5], vec![1.0, 3.0, 0.0], vec![0.5, 0.0, 2.0]];
+        let (values, vectors) = get_eigen(&m);
+        // B * diag(values) * B^T reconstructs the matrix
+        for i in 0..3 {
+            for j in 0..3 {
+                let r: f64 = (0..3).map(|k| vectors[i][k] * values[k] * vectors[j][k]).sum();
+                assert!((r - m[i][j]).abs() < 1e-9, "{} {}", i, j);
+            }
+        }
+        assert!((values.iter().sum::<f64>() - 9.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_antithetic_es() {
+        let mut es = AntitheticEs::new(vec![0.0; 5]);
+        es.rng = Pcg32::seed_from_u64(1);
+        es.learning_rate = 0.5;
+        let candidates = es.ask();
+        assert_eq!(candidates.len(), 2 * ES_NUM_PAIRS);
+        // Mirrored pairs are symmetric around the mean
+        for (a, b) in candidates[0].iter().zip(candidates[1].iter()) {
+            assert!((a + b).abs() < 1e-12);
+        }
+        es.tell(&candidates, &candidates.iter().map(|x| get_sphere_fitness(x)).collect::<Vec<f64>>());
+        let before = get_sphere_fitness(&[0.0; 5]);
+        let after = run(&mut es, 300);
+        assert!(after > before / 20.0, "{} {}", before, after);
+    }
+
+    #[test]
+    fn test_cma_es() {
+        let mut es = CmaEs::new(vec![0.0; 5], 0.5);
+        es.rng = Pcg32::seed_from_u64(1);
+        assert_eq!(es.lambda, 8);
+        assert_eq!(es.ask().len(), 8);
+        assert!(run(&mut es, 200) > -1e-6);
+        // The step size shrinks as the mean converges
+        assert!(es.sigma < 0.5);
+    }
+}
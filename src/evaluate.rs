@@ -0,0 +1,260 @@
+use rand::SeedableRng;
+use rand_pcg::Pcg32;
+use std::fmt;
+
+use crate::error::Result;
+use crate::game::{Brain, Ending, EpisodeOutcome, Game};
+
+// Standard normal quantile for two-sided 95% confidence intervals
+const Z_95: f64 = 1.96;
+
+// Summary of one measure over many games
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub struct Distribution {
+    pub count: usize,
+    pub mean: f64,
+    pub std_dev: f64,
+    pub min: f64,
+    pub lower_quartile: f64,
+    pub median: f64,
+    pub upper_quartile: f64,
+    pub max: f64,
+}
+
+impl Distribution {
+    pub fn new(values: &[f64]) -> Distribution {
+        if values.is_empty() {
+            return Distribution {
+                count: 0,
+                mean: 0.0_f64,
+                std_dev: 0.0_f64,
+                min: 0.0_f64,
+                lower_quartile: 0.0_f64,
+                median: 0.0_f64,
+                upper_quartile: 0.0_f64,
+                max: 0.0_f64,
+            };
+        }
+        let mut sorted = values.to_vec();
+        sorted.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+        let n = values.len() as f64;
+        let mean = values.iter().sum::<f64>() / n;
+        let variance = values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / n;
+        Distribution {
+            count: values.len(),
+            mean,
+            std_dev: variance.sqrt(),
+            min: sorted[0],
+            lower_quartile: get_quantile(&sorted, 0.25_f64),
+            median: get_quantile(&sorted, 0.5_f64),
+            upper_quartile: get_quantile(&sorted, 0.75_f64),
+            max: sorted[sorted.len() - 1],
+        }
+    }
+
+    // 95% confidence interval of the mean, from the normal approximation
+    pub fn get_confidence_interval(&self) -> (f64, f64) {
+        let half_width = Z_95 * self.std_dev / (self.count.max(1) as f64).sqrt();
+        (self.mean - half_width, self.mean + half_width)
+    }
+}
+
+impl fmt::Display for Distribution {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (low, high) = self.get_confidence_interval();
+        write!(
+            f,
+            "mean {:.2} (95% CI {:.2} to {:.2}, sd {:.2}), min {}, quartiles {} / {} / {}, max {}",
+            self.mean,
+            low,
+            high,
+            self.std_dev,
+            self.min,
+            self.lower_quartile,
+            self.median,
+            self.upper_quartile,
+            self.max
+        )
+    }
+}
+
+// Interpolates between the two closest of the sorted values
+fn get_quantile(sorted: &[f64], q: f64) -> f64 {
+    let position = q * (sorted.len() - 1) as f64;
+    let below = position.floor() as usize;
+    let above = position.ceil() as usize;
+    sorted[below] + (sorted[above] - sorted[below]) * (position - below as f64)
+}
+
+// Wilson score interval of a proportion, which unlike the normal approximation stays within 0 and 1
+// when hardly any or almost all of the games end one way
+fn get_proportion_interval(count: usize, total: usize) -> (f64, f64) {
+    if total == 0 {
+        return (0.0_f64, 1.0_f64);
+    }
+    let n = total as f64;
+    let p = count as f64 / n;
+    let z2 = Z_95 * Z_95;
+    let centre = (p + z2 / (2.0_f64 * n)) / (1.0_f64 + z2 / n);
+    let half_width = Z_95 * (p * (1.0_f64 - p) / n + z2 / (4.0_f64 * n * n)).sqrt() / (1.0_f64 + z2 / n);
+    ((centre - half_width).max(0.0_f64), (centre + half_width).min(1.0_f64))
+}
+
+// How a brain played a set of games
+pub struct Report {
+    pub games: usize,
+    pub score: Distribution,
+    pub length: Distribution,
+    pub steps: Distribution,
+    // Number of games that stopped in each way, in the order of Ending::ALL
+    pub endings: Vec<(Ending, usize)>,
+}
+
+impl Report {
+    pub fn new(outcomes: &[EpisodeOutcome]) -> Report {
+        let get = |f: fn(&EpisodeOutcome) -> f64| -> Vec<f64> { outcomes.iter().map(f).collect() };
+        Report {
+            games: outcomes.len(),
+            score: Distribution::new(&get(|o| o.score as f64)),
+            length: Distribution::new(&get(|o| o.length as f64)),
+            steps: Distribution::new(&get(|o| o.steps as f64)),
+            endings: Ending::ALL
+                .iter()
+                .map(|e| (*e, outcomes.iter().filter(|o| o.ending == Some(*e)).count()))
+                .collect(),
+        }
+    }
+
+    // Fraction of the games that stopped this way
+    pub fn get_rate(&self, ending: Ending) -> f64 {
+        let count = self.endings.iter().find(|(e, _)| *e == ending).map_or(0, |(_, c)| *c);
+        count as f64 / self.games.max(1) as f64
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "Games: {}", self.games)?;
+        writeln!(f, "Score: {}", self.score)?;
+        writeln!(f, "Length: {}", self.length)?;
+        write!(f, "Steps: {}", self.steps)?;
+        for (ending, count) in self.endings.iter() {
+            let (low, high) = get_proportion_interval(*count, self.games);
+            write!(
+                f,
+                "\n{:?}: {} ({:.1}%, 95% CI {:.1}% to {:.1}%)",
+                ending,
+                count,
+                100.0_f64 * self.get_rate(*ending),
+                100.0_f64 * low,
+                100.0_f64 * high
+            )?;
+        }
+        Ok(())
+    }
+}
+
+// Plays game i with its food seeded from seed + i, so that evaluations can be repeated and compared.
+// The brain is frozen first, so it neither explores nor learns from these games, and they are
+// measured by how they end rather than by a training fitness
+pub fn play_seeded<T: Brain>(brain: &mut T, num_games: u32, seed: u64) -> Result<Vec<EpisodeOutcome>> {
+    let mut game = Game::new();
+    let mut outcomes: Vec<EpisodeOutcome> = Vec::new();
+    brain.set_training(false);
+    for i in 0..num_games {
+        game.rng = Pcg32::seed_from_u64(seed.wrapping_add(i as u64));
+        game.init();
+        game.run_brain(brain, |_, _, _, _, _| 0.0_f64)?;
+        outcomes.push(game.get_outcome());
+    }
+    Ok(outcomes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::qlearn::QLearner;
+    use crate::scripted::{PathfinderBrain, RandomBrain, SafeRandomBrain};
+
+    #[test]
+    fn test_distribution_new() {
+        let d = Distribution::new(&[4.0_f64, 1.0_f64, 3.0_f64, 2.0_f64, 5.0_f64]);
+        assert_eq!(d.mean, 3.0_f64);
+        assert!((d.std_dev - 2.0_f64.sqrt()).abs() < 1e-12);
+        assert_eq!(d.min, 1.0_f64);
+        assert_eq!(d.lower_quartile, 2.0_f64);
+        assert_eq!(d.median, 3.0_f64);
+        assert_eq!(d.upper_quartile, 4.0_f64);
+        assert_eq!(d.max, 5.0_f64);
+        assert_eq!(Distribution::new(&[1.0_f64, 2.0_f64]).median, 1.5_f64);
+        assert_eq!(Distribution::new(&[]).mean, 0.0_f64);
+        let (low, high) = d.get_confidence_interval();
+        assert!((high - low - 2.0_f64 * Z_95 * (2.0_f64 / 5.0_f64).sqrt()).abs() < 1e-12);
+        assert!((high + low - 6.0_f64).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_get_proportion_interval() {
+        let (low, high) = get_proportion_interval(50, 100);
+        assert!((low + high - 1.0_f64).abs() < 1e-12);
+        assert!(low > 0.39_f64 && high < 0.61_f64);
+        // Never outside 0 and 1, even with no games ending that way
+        let (low, high) = get_proportion_interval(0, 100);
+        assert!(low.abs() < 1e-12);
+        assert!(high > 0.0_f64 && high < 0.05_f64);
+        assert_eq!(get_proportion_interval(0, 0), (0.0_f64, 1.0_f64));
+    }
+
+    #[test]
+    fn test_report_new() {
+        let outcome = |score: u32, ending| EpisodeOutcome {
+            score,
+            length: score as usize + 3,
+            steps: 10 * score,
+            ending: Some(ending),
+        };
+        let report = Report::new(&[
+            outcome(1, Ending::Wall),
+            outcome(2, Ending::Wall),
+            outcome(6, Ending::TimedOut),
+        ]);
+        assert_eq!(report.games, 3);
+        assert_eq!(report.score.mean, 3.0_f64);
+        assert_eq!(report.length.median, 5.0_f64);
+        assert_eq!(report.steps.max, 60.0_f64);
+        assert_eq!(report.endings[0], (Ending::Wall, 2));
+        assert!((report.get_rate(Ending::Wall) - 2.0_f64 / 3.0_f64).abs() < 1e-12);
+        assert_eq!(report.get_rate(Ending::Body), 0.0_f64);
+        assert!(report.to_string().contains("TimedOut: 1 (33.3%"));
+    }
+
+    #[test]
+    fn test_play_seeded() {
+        let mut brain = PathfinderBrain;
+        let outcomes = play_seeded(&mut brain, 5, 3).unwrap();
+        assert_eq!(outcomes.len(), 5);
+        // The same seed gives the same food, so a deterministic brain plays the same games
+        assert_eq!(play_seeded(&mut brain, 5, 3).unwrap(), outcomes);
+        let mut ql = QLearner::new(8, 4);
+        let num_states = ql.q.len();
+        play_seeded(&mut ql, 5, 3).unwrap();
+        assert!(!ql.training);
+        assert_eq!(ql.episodes, 0);
+        assert_eq!(ql.q.len(), num_states);
+    }
+
+    #[test]
+    fn test_random_baselines() {
+        let mut random = RandomBrain::new();
+        random.rng = Pcg32::seed_from_u64(1);
+        let random = Report::new(&play_seeded(&mut random, 500, 0).unwrap());
+        let mut safe = SafeRandomBrain::new();
+        safe.rng = Pcg32::seed_from_u64(1);
+        let safe = Report::new(&play_seeded(&mut safe, 500, 0).unwrap());
+        assert_eq!(random.games, 500);
+        // Never walking into a wall or the body keeps the snake alive for longer
+        assert!(safe.steps.mean > random.steps.mean);
+        assert!(safe.get_rate(Ending::TimedOut) > random.get_rate(Ending::TimedOut));
+        assert!(safe.get_rate(Ending::Wall) < random.get_rate(Ending::Wall));
+    }
+}
@@ -1,16 +1,81 @@
 extern crate rand;
 
-use rand::Rng;
+use rand::{Rng, SeedableRng};
+use rand_pcg::Pcg32;
 use std::collections::VecDeque;
 use std::fmt;
 
 use crate::constants::*;
-use crate::gen_alg::NN;
-use crate::qlearn::QLearner;
+use crate::error::Result;
+
+// One move of a game, as learned from by Brain::train
+pub struct Transition<'a> {
+    pub state: &'a [f64],
+    pub action: usize,
+    pub reward: f64,
+    pub next_state: &'a [f64],
+    // The snake died or filled the board with this move, so next_state has no future value to bootstrap from.
+    // A game cut short by NN_MAX_GAME_TIME is not done, since the snake could have played on
+    pub done: bool,
+}
+
+impl<'a> Transition<'a> {
+    pub fn new(state: &'a [f64], action: usize, reward: f64, next_state: &'a [f64], done: bool) -> Transition<'a> {
+        Transition {
+            state,
+            action,
+            reward,
+            next_state,
+            done,
+        }
+    }
+}
+
+// Why a game stopped
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum Ending {
+    Wall,
+    Body,
+    // Stopped at NN_MAX_GAME_TIME rather than by the snake dying
+    TimedOut,
+    BoardFull,
+}
+
+impl Ending {
+    pub const ALL: [Ending; 4] = [Ending::Wall, Ending::Body, Ending::TimedOut, Ending::BoardFull];
+
+    // Whether the game could not have gone on, unlike running out of time
+    pub fn is_terminal(&self) -> bool {
+        *self != Ending::TimedOut
+    }
+}
+
+// How a game ended
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub struct EpisodeOutcome {
+    pub score: u32,
+    pub length: usize,
+    pub steps: u32,
+    // None while the game is still running
+    pub ending: Option<Ending>,
+}
 
+// Every method but get_action has a default, so a brain only implements the hooks it needs
 pub trait Brain {
-    fn get_action(&mut self, inputs: &Vec<f64>) -> Option<usize>;
-    fn train(&mut self, state_initial: &Vec<f64>, action: usize, reward: f64, state_final: &Vec<f64>) -> Option<bool>;
+    fn get_action(&mut self, inputs: &[f64]) -> Result<usize>;
+    // The game loops ask for actions here, so that scripted brains can look at the whole board
+    fn get_game_action(&mut self, _game: &Game, inputs: &[f64]) -> Result<usize> {
+        self.get_action(inputs)
+    }
+    fn train(&mut self, _transition: &Transition) -> Option<bool> {
+        Some(true)
+    }
+    // Called once a new game has been set up, e.g. to reset a recurrent hidden state
+    fn on_episode_start(&mut self) {}
+    // Called once the game loop has finished an episode, e.g. to reset eligibility traces
+    fn on_episode_end(&mut self, _outcome: &EpisodeOutcome) {}
+    // Outside of training a learner neither explores nor learns
+    fn set_training(&mut self, _training: bool) {}
 }
 
 #[derive(Copy, Clone)]
@@ -96,6 +161,7 @@ pub struct Snake {
     pub direction: Direction,
     pub alive: bool,
     pub eat: bool,
+    pub ending: Option<Ending>,
 }
 
 impl Snake {
@@ -118,6 +184,7 @@ impl Snake {
             direction: Direction::RIGHT,
             alive: true,
             eat: false,
+            ending: None,
         }
     }
 
@@ -132,8 +199,10 @@ impl Snake {
     fn perform_next(&mut self, food_pos: &mut Position) {
         if self.alive {
             let next_pos = self.next_head_pos();
-            if self.check_collide_wall(next_pos) || self.check_collide_body(next_pos) {
-                self.alive = false;
+            if self.check_collide_wall(next_pos) {
+                self.end(Ending::Wall);
+            } else if self.check_collide_body(next_pos) {
+                self.end(Ending::Body);
             } else if self.check_eat_food(next_pos, *food_pos) {
                 self.eat_next(food_pos);
                 self.eat = true;
@@ -143,6 +212,11 @@ impl Snake {
         }
     }
 
+    fn end(&mut self, ending: Ending) {
+        self.alive = false;
+        self.ending = Some(ending);
+    }
+
     fn next_head_pos(&mut self) -> Position {
         let mut current_head = self.body[0].position;
         match self.direction {
@@ -188,6 +262,8 @@ pub struct Game {
     pub food: Block,
     pub time: u32,
     pub score: u32,
+    // Places the food, so seeding it replays the same games for the same moves
+    pub rng: Pcg32,
 }
 
 impl Game {
@@ -200,6 +276,7 @@ impl Game {
             },
             time: 0,
             score: 0,
+            rng: Pcg32::from_rng(rand::thread_rng()).unwrap(),
         }
     }
 
@@ -220,58 +297,98 @@ impl Game {
             self.time += 1;
             if self.snake.eat {
                 self.score += 1;
-                self.food.position = self.get_food_pos();
+                // There is nowhere left for food on a full board, so the game is won
+                if self.is_board_full() {
+                    self.snake.end(Ending::BoardFull);
+                } else {
+                    self.food.position = self.get_food_pos();
+                }
                 self.snake.eat = false;
             }
         }
     }
 
-    pub fn run_brain<T: Brain>(&mut self, brain: &mut T, fitness_function: fn(i64, i64, i64, i64, i64) -> f64) -> f64 {
-        self.init();
+    // Plays the game from where it is, so it must be set up with init first
+    pub fn run_brain<T: Brain>(
+        &mut self,
+        brain: &mut T,
+        fitness_function: fn(i64, i64, i64, i64, i64) -> f64,
+    ) -> Result<f64> {
+        self.run_brain_observed(brain, fitness_function, |_| {})
+    }
+
+    // Same as run_brain, showing the game to observe after every move
+    pub fn run_brain_observed<T: Brain, F: FnMut(&Game)>(
+        &mut self,
+        brain: &mut T,
+        fitness_function: fn(i64, i64, i64, i64, i64) -> f64,
+        mut observe: F,
+    ) -> Result<f64> {
+        brain.on_episode_start();
         let mut fitness: f64 = 0f64;
         while self.snake.alive {
             let state_initial = self.get_nn_inputs();
-            let action = brain.get_action(&state_initial).unwrap();
-            let dir = self.get_direction_from_index(action);
-            self.update(dir);
-
-            // Before moving store some results
-            let dist_before = self.get_food_dist();
-            let time_before = self.time;
-
-            // Make the move
-            self.next_tick(1f64);
-
-            // After the move store some results
-            let dist_after = self.get_food_dist();
-            let time_after = self.time;
-            let snake_eat = if self.snake.eat { 1i64 } else { 0i64 };
-            let snake_dead = if self.snake.alive { 0i64 } else { 1i64 };
-
-            // Get fitness & State
-            let fit = fitness_function(
-                time_after as i64 - time_before as i64,
-                dist_before,
-                dist_after,
-                snake_eat,
-                snake_dead,
-            );
+            let action = brain.get_game_action(self, &state_initial)?;
+            let fit = self.play_action(action, fitness_function);
+            observe(self);
             let state_final = self.get_nn_inputs();
             fitness += fit;
+            let done = self.snake.ending.is_some_and(|e| e.is_terminal());
 
             // Update the Q Matrix
-            brain.train(&state_initial, action, fit, &state_final);
+            brain.train(&Transition::new(&state_initial, action, fit, &state_final, done));
+        }
+        brain.on_episode_end(&self.get_outcome());
+        Ok(fitness)
+    }
 
-            // End if we are out of time
-            if self.time >= NN_MAX_GAME_TIME {
-                self.snake.alive = false;
-            }
+    pub fn get_outcome(&self) -> EpisodeOutcome {
+        EpisodeOutcome {
+            score: self.score,
+            length: self.snake.body.len(),
+            steps: self.time,
+            ending: self.snake.ending,
+        }
+    }
+
+    // Makes one move of a brain-driven game and returns its fitness
+    pub fn play_action(&mut self, action: usize, fitness_function: fn(i64, i64, i64, i64, i64) -> f64) -> f64 {
+        let dir = self.get_direction_from_index(action);
+        self.update(dir);
+
+        // Before moving store some results
+        let dist_before = self.get_food_dist();
+        let time_before = self.time;
+
+        // Make the move
+        self.next_tick(1f64);
+
+        // After the move store some results
+        let dist_after = self.get_food_dist();
+        let time_after = self.time;
+        let snake_eat = if self.snake.eat { 1i64 } else { 0i64 };
+        let snake_dead = if self.snake.alive { 0i64 } else { 1i64 };
+
+        // End if we are out of time
+        if self.time >= NN_MAX_GAME_TIME && self.snake.alive {
+            self.snake.end(Ending::TimedOut);
         }
-        fitness
+
+        fitness_function(
+            time_after as i64 - time_before as i64,
+            dist_before,
+            dist_after,
+            snake_eat,
+            snake_dead,
+        )
+    }
+
+    pub fn get_dir_from_brain<T: Brain>(&self, brain: &mut T) -> Result<Direction> {
+        Ok(self.get_direction_from_index(brain.get_game_action(self, &self.get_nn_inputs())?))
     }
 
-    pub fn get_dir_from_brain<T: Brain>(&self, brain: &mut T) -> Direction {
-        self.get_direction_from_index(brain.get_action(&self.get_nn_inputs()).unwrap())
+    pub fn is_board_full(&self) -> bool {
+        self.snake.body.len() >= BOARD_WIDTH as usize * BOARD_HEIGHT as usize
     }
 
     pub fn get_direction_from_index(&self, index: usize) -> Direction {
@@ -285,11 +402,10 @@ impl Game {
     }
 
     fn get_food_pos(&mut self) -> Position {
-        let mut rng = rand::thread_rng();
         loop {
             let pos = Position {
-                x: rng.gen_range(0, BOARD_WIDTH),
-                y: rng.gen_range(0, BOARD_HEIGHT),
+                x: self.rng.gen_range(0, BOARD_WIDTH),
+                y: self.rng.gen_range(0, BOARD_HEIGHT),
             };
             if !self.snake.check_collide_body(pos) {
                 return pos;
@@ -360,6 +476,8 @@ impl Game {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::gen_alg::NN;
+    use crate::qlearn::QLearner;
 
     #[test]
     fn test_position_new() {
@@ -534,6 +652,7 @@ mod tests {
         assert!(snake.alive);
         snake.perform_next(&mut food);
         assert!(!snake.alive);
+        assert_eq!(snake.ending, Some(Ending::Wall));
         // Check whether we collide with ourself and die
         snake = Snake::new();
         food = Position::new_offset(1, 0);
@@ -548,6 +667,7 @@ mod tests {
         snake.update(Direction::DOWN);
         snake.perform_next(&mut food);
         assert!(!snake.alive);
+        assert_eq!(snake.ending, Some(Ending::Body));
         // Check whether we collide with the walls and die
         snake = Snake::new();
         snake.update(Direction::DOWN);
@@ -588,6 +708,79 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_game_get_outcome() {
+        let mut game = Game::new();
+        game.init();
+        assert_eq!(game.get_outcome().ending, None);
+        // Going round in a square away from the food never dies, so the game runs out of time
+        game.food.position = Position::new_offset(-4, -4);
+        let mut action = 0;
+        while game.snake.alive {
+            game.play_action(action, |_, _, _, _, _| 0.0_f64);
+            action = (action + 1) % 4;
+        }
+        let outcome = game.get_outcome();
+        assert_eq!(outcome.ending, Some(Ending::TimedOut));
+        assert_eq!(outcome.steps, NN_MAX_GAME_TIME);
+    }
+
+    // Plays the actions in turn, remembering which moves it was told were final
+    struct CyclingBrain {
+        num_actions: usize,
+        action: usize,
+        done: Vec<bool>,
+    }
+
+    impl Brain for CyclingBrain {
+        fn get_action(&mut self, _inputs: &[f64]) -> Result<usize> {
+            let action = self.action;
+            self.action = (self.action + 1) % self.num_actions;
+            Ok(action)
+        }
+
+        fn train(&mut self, transition: &Transition) -> Option<bool> {
+            self.done.push(transition.done);
+            Some(true)
+        }
+    }
+
+    #[test]
+    fn test_game_run_brain_done() {
+        let mut game = Game::new();
+        game.init();
+        game.food.position = Position::new_offset(-4, -4);
+        let mut brain = CyclingBrain {
+            num_actions: 4,
+            action: 0,
+            done: Vec::new(),
+        };
+        game.run_brain(&mut brain, |_, _, _, _, _| 0.0_f64).unwrap();
+        // Running out of time is not the end of the snake, so no move is final
+        assert_eq!(game.snake.ending, Some(Ending::TimedOut));
+        assert_eq!(brain.done.len(), NN_MAX_GAME_TIME as usize);
+        assert!(brain.done.iter().all(|d| !d));
+        // Going straight on dies at the wall, and only that last move is final
+        game.init();
+        brain.num_actions = 1;
+        brain.done.clear();
+        game.run_brain(&mut brain, |_, _, _, _, _| 0.0_f64).unwrap();
+        assert_eq!(game.snake.ending, Some(Ending::Wall));
+        assert_eq!(brain.done.last(), Some(&true));
+        assert_eq!(brain.done.iter().filter(|d| **d).count(), 1);
+    }
+
+    #[test]
+    fn test_game_seeded_food() {
+        let mut game = Game::new();
+        let mut other = Game::new();
+        game.rng = Pcg32::seed_from_u64(7);
+        other.rng = Pcg32::seed_from_u64(7);
+        for _ in 0..10 {
+            assert_eq!(game.get_food_pos(), other.get_food_pos());
+        }
+    }
+
     #[test]
     fn test_game_init() {
         let mut game = Game::new();
@@ -626,10 +819,15 @@ mod tests {
         let mut nn = NN::new_defined(&[[8, 8], [8, 6], [6, 4]]);
         let dir = game.get_dir_from_brain(&mut nn);
         println!("{:?}", dir);
+        assert!(dir.is_ok());
         let mut ql = QLearner::new(8, 4);
         let dir = game.get_dir_from_brain(&mut ql);
         println!("{:?}", dir);
-        assert!(true);
+        assert!(dir.is_ok());
+        // A network built for other inputs is an error rather than a panic
+        let mut nn = NN::new_defined(&[[7, 4]]);
+        assert!(game.get_dir_from_brain(&mut nn).is_err());
+        assert!(game.run_brain(&mut nn, |_, _, _, _, _| 0.0_f64).is_err());
     }
 
     #[test]
@@ -639,7 +837,7 @@ mod tests {
         let mut nn = NN::new_defined(&[[8, 8], [8, 6], [6, 4]]);
         let board = game.get_nn_inputs();
         let out = nn.propagate(&board).unwrap();
-        let dir = game.get_dir_from_brain(&mut nn);
+        let dir = game.get_dir_from_brain(&mut nn).unwrap();
 
         fn get_index_max_float(input: &Vec<f64>) -> Option<usize> {
             input
@@ -676,7 +874,7 @@ mod tests {
         let mut game = Game::new();
         game.init();
         let mut nn = NN::new_defined(&[[8, 8], [8, 4]]);
-        game.run_brain(&mut nn, fitness_function);
+        game.run_brain(&mut nn, fitness_function).unwrap();
         println!("{}", game.time);
         assert!(game.time >= cmp::min(BOARD_WIDTH as u32, BOARD_HEIGHT as u32) / 2);
     }
@@ -1,199 +1,597 @@
 extern crate rand;
 
-use rand::Rng;
+use rand::{Rng, SeedableRng};
 use rand_distr::{Distribution, Normal};
+use rand_pcg::Pcg32;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
 
 use crate::constants::*;
+use crate::crossover::CrossoverMethod;
+use crate::error::{Error, Result};
 use crate::game::Brain;
+use crate::island::IslandConfig;
+use crate::mutation::{Mutation, MutationConfig};
+use crate::nn32::Precision;
+use crate::selection::SelectionMethod;
 
-fn sigmoid(z: f64) -> f64 {
-    let e = std::f64::consts::E;
-    1.0 / (1.0 + e.powf(-z))
+pub fn sigmoid(z: f64) -> f64 {
+    1.0 / (1.0 + (-z).exp())
+}
+
+#[derive(Debug, PartialEq, Copy, Clone, Serialize, Deserialize)]
+pub enum Activation {
+    Sigmoid,
+    Tanh,
+    Relu,
+    LeakyRelu,
+    Linear,
+    Softmax,
+}
+
+impl Activation {
+    pub fn activate_in_place(&self, zs: &mut [f64]) {
+        match self {
+            Activation::Sigmoid => zs.iter_mut().for_each(|z| *z = sigmoid(*z)),
+            Activation::Tanh => zs.iter_mut().for_each(|z| *z = z.tanh()),
+            Activation::Relu => zs.iter_mut().for_each(|z| *z = z.max(0.0_f64)),
+            Activation::LeakyRelu => zs.iter_mut().for_each(|z| {
+                if *z <= 0.0_f64 {
+                    *z *= LEAKY_RELU_SLOPE
+                }
+            }),
+            Activation::Linear => {}
+            Activation::Softmax => {
+                // Shifting by the maximum keeps the exponentials from overflowing
+                let max = zs.iter().cloned().fold(f64::NEG_INFINITY, f64::max);
+                zs.iter_mut().for_each(|z| *z = (*z - max).exp());
+                let sum: f64 = zs.iter().sum();
+                zs.iter_mut().for_each(|z| *z /= sum);
+            }
+        }
+    }
+
+    // Converts the gradient with respect to the outputs into the gradient with respect to the weighted sums
+    pub fn backpropagate(&self, outputs: &[f64], output_gradient: &[f64]) -> Vec<f64> {
+        match self {
+            Activation::Softmax => {
+                let dot: f64 = outputs.iter().zip(output_gradient.iter()).map(|(y, g)| y * g).sum();
+                outputs
+                    .iter()
+                    .zip(output_gradient.iter())
+                    .map(|(y, g)| y * (g - dot))
+                    .collect()
+            }
+            _ => outputs
+                .iter()
+                .zip(output_gradient.iter())
+                .map(|(y, g)| g * self.get_derivative(*y))
+                .collect(),
+        }
+    }
+
+    // Derivative of the element-wise activations, expressed in terms of their output
+    fn get_derivative(&self, output: f64) -> f64 {
+        match self {
+            Activation::Sigmoid => output * (1.0_f64 - output),
+            Activation::Tanh => 1.0_f64 - output * output,
+            Activation::Relu => {
+                if output > 0.0_f64 {
+                    1.0_f64
+                } else {
+                    0.0_f64
+                }
+            }
+            Activation::LeakyRelu => {
+                if output > 0.0_f64 {
+                    1.0_f64
+                } else {
+                    LEAKY_RELU_SLOPE
+                }
+            }
+            Activation::Linear | Activation::Softmax => 1.0_f64,
+        }
+    }
 }
 
 fn get_normal() -> f64 {
-    let mut rng = rand::thread_rng();
+    get_normal_with(&mut rand::thread_rng())
+}
+
+pub fn get_normal_with(rng: &mut impl Rng) -> f64 {
     let normal = Normal::new(0.0, 1.0).unwrap();
-    normal.sample(&mut rng)
+    normal.sample(rng)
 }
 
+#[derive(Clone)]
 pub struct Layer {
     pub num_inputs: u32,
     pub num_neurons: u32,
-    pub weights: Vec<Vec<f64>>,
+    // Row-major num_neurons x num_inputs, so the incoming weights of a neuron are contiguous
+    pub weights: Vec<f64>,
     pub biases: Vec<f64>,
+    pub activation: Activation,
+    // Row-major num_neurons x num_neurons weights from the previous outputs, empty for a feed-forward layer
+    pub recurrent_weights: Vec<f64>,
+    // Outputs of the previous NN::step, fed back through recurrent_weights
+    pub state: Vec<f64>,
+}
+
+// The hidden state is not part of the network, so it is left out of the comparison
+impl PartialEq for Layer {
+    fn eq(&self, other: &Layer) -> bool {
+        self.num_inputs == other.num_inputs
+            && self.num_neurons == other.num_neurons
+            && self.weights == other.weights
+            && self.biases == other.biases
+            && self.activation == other.activation
+            && self.recurrent_weights == other.recurrent_weights
+    }
 }
 
 impl Layer {
-    pub fn new(num_inputs: u32, num_neurons: u32) -> Option<Layer> {
+    pub fn new(num_inputs: u32, num_neurons: u32) -> Result<Layer> {
+        Layer::new_with_activation(num_inputs, num_neurons, Activation::Sigmoid)
+    }
+
+    pub fn new_with_activation(num_inputs: u32, num_neurons: u32, activation: Activation) -> Result<Layer> {
         if num_inputs == 0 || num_neurons == 0 {
-            None
+            Err(Error::InvalidConfig(format!(
+                "a layer of {} inputs and {} neurons is empty",
+                num_inputs, num_neurons
+            )))
         } else {
             let biases: Vec<f64> = (0..num_neurons).map(|_| get_normal()).collect();
-            let weights: Vec<Vec<f64>> = (0..num_neurons)
-                .map(|_| (0..num_inputs).map(|_| get_normal()).collect())
-                .collect();
-            Some(Layer {
+            let weights: Vec<f64> = (0..num_neurons * num_inputs).map(|_| get_normal()).collect();
+            Ok(Layer {
                 num_inputs,
                 num_neurons,
                 weights,
                 biases,
+                activation,
+                recurrent_weights: Vec::new(),
+                state: Vec::new(),
             })
         }
     }
 
-    fn feed_forward(&self, inputs: &Vec<f64>) -> Option<Vec<f64>> {
-        if self.num_inputs != inputs.len() as u32 {
-            None
-        } else {
-            let wx: Vec<f64> = self
-                .weights
-                .iter()
-                .map(|ws| ws.iter().zip(inputs.iter()).map(|(w, x)| w * x).sum())
-                .collect();
-            let wx_b: Vec<f64> = wx.iter().zip(self.biases.iter()).map(|(wx, b)| wx + b).collect();
-            Some(wx_b.iter().map(|z| sigmoid(*z)).collect())
-        }
+    // Elman layer: every neuron also sees the outputs of the whole layer at the previous step
+    pub fn new_recurrent(num_inputs: u32, num_neurons: u32, activation: Activation) -> Result<Layer> {
+        let mut layer = Layer::new_with_activation(num_inputs, num_neurons, activation)?;
+        layer.recurrent_weights = (0..num_neurons * num_neurons).map(|_| get_normal()).collect();
+        layer.state = vec![0.0_f64; num_neurons as usize];
+        Ok(layer)
     }
 
-    fn should_mutate() -> bool {
-        let mut rng = rand::thread_rng();
-        rng.gen::<f64>() > 1f64 - MUTATION_PROBABILITY
+    pub fn is_recurrent(&self) -> bool {
+        !self.recurrent_weights.is_empty()
     }
 
-    fn mutate(&mut self) {
-        self.biases = self
-            .biases
-            .iter()
-            .map(|b| if Layer::should_mutate() { get_normal() } else { *b })
-            .collect();
-        self.weights = self
-            .weights
-            .iter()
-            .map(|ws| {
-                ws.iter()
-                    .map(|w| if Layer::should_mutate() { get_normal() } else { *w })
-                    .collect()
-            })
-            .collect();
+    // Incoming weights of a neuron
+    pub fn get_neuron_weights(&self, neuron: usize) -> &[f64] {
+        let n = self.num_inputs as usize;
+        &self.weights[neuron * n..(neuron + 1) * n]
     }
 
-    fn should_crossover() -> bool {
-        let mut rng = rand::thread_rng();
-        rng.gen::<f64>() > 1f64 - CROSSOVER_PROBABILITY
+    pub fn feed_forward(&self, inputs: &[f64]) -> Result<Vec<f64>> {
+        let mut outputs = Vec::with_capacity(self.num_neurons as usize);
+        self.feed_forward_into(inputs, &mut outputs)?;
+        Ok(outputs)
     }
 
-    fn crossover(&self, other: &Layer) -> (Layer, Layer) {
-        let mut child1 = Layer::new(self.num_inputs, self.num_neurons).unwrap();
-        let mut child2 = Layer::new(self.num_inputs, self.num_neurons).unwrap();
-
-        // Cannot iter() over here since destructuring assignments are not allowed
-        // https://github.com/rust-lang/rfcs/issues/372
+    fn check_inputs(&self, found: usize) -> Result<()> {
+        if self.num_inputs as usize == found {
+            Ok(())
+        } else {
+            Err(Error::ShapeMismatch {
+                what: "layer inputs",
+                expected: self.num_inputs as usize,
+                found,
+            })
+        }
+    }
 
-        for i in 0..self.num_neurons as usize {
-            if Layer::should_crossover() {
-                child1.biases[i] = other.biases[i];
-                child2.biases[i] = self.biases[i];
-            } else {
-                child1.biases[i] = self.biases[i];
-                child2.biases[i] = other.biases[i];
+    // Writes the outputs into a buffer the caller keeps between calls, so that nothing is allocated
+    pub fn feed_forward_into(&self, inputs: &[f64], outputs: &mut Vec<f64>) -> Result<()> {
+        self.check_inputs(inputs.len())?;
+        outputs.clear();
+        for (ws, b) in self.weights.chunks_exact(inputs.len()).zip(self.biases.iter()) {
+            outputs.push(b + ws.iter().zip(inputs.iter()).map(|(w, x)| w * x).sum::<f64>());
+        }
+        if self.is_recurrent() {
+            for (z, us) in outputs
+                .iter_mut()
+                .zip(self.recurrent_weights.chunks_exact(self.state.len()))
+            {
+                *z += us.iter().zip(self.state.iter()).map(|(u, h)| u * h).sum::<f64>();
             }
-            for j in 0..self.num_inputs as usize {
-                if Layer::should_crossover() {
-                    child1.weights[i][j] = other.weights[i][j];
-                    child2.weights[i][j] = self.weights[i][j];
-                } else {
-                    child1.weights[i][j] = self.weights[i][j];
-                    child2.weights[i][j] = other.weights[i][j];
-                }
+        }
+        self.activation.activate_in_place(outputs);
+        Ok(())
+    }
+
+    // inputs holds batch_size samples one after the other, and so do the outputs
+    pub fn feed_forward_batch(&self, inputs: &[f64], batch_size: usize, outputs: &mut Vec<f64>) -> Result<()> {
+        let (n_in, n_out) = (self.num_inputs as usize, self.num_neurons as usize);
+        if self.is_recurrent() {
+            return Err(Error::InvalidConfig(
+                "a recurrent layer cannot play several games at once".to_string(),
+            ));
+        }
+        if inputs.len() != batch_size * n_in {
+            return Err(Error::ShapeMismatch {
+                what: "batched layer inputs",
+                expected: batch_size * n_in,
+                found: inputs.len(),
+            });
+        }
+        outputs.clear();
+        outputs.resize(batch_size * n_out, 0.0_f64);
+        for (xs, zs) in inputs.chunks_exact(n_in).zip(outputs.chunks_exact_mut(n_out)) {
+            for ((z, ws), b) in zs
+                .iter_mut()
+                .zip(self.weights.chunks_exact(n_in))
+                .zip(self.biases.iter())
+            {
+                *z = b + ws.iter().zip(xs.iter()).map(|(w, x)| w * x).sum::<f64>();
             }
+            self.activation.activate_in_place(zs);
         }
+        Ok(())
+    }
 
-        (child1, child2)
+    fn mutate(&mut self, mutation: &Mutation, rng: &mut impl Rng) {
+        for b in self.biases.iter_mut() {
+            if mutation.should_mutate(rng) {
+                *b = mutation.perturb(*b, rng);
+            }
+        }
+        for w in self.weights.iter_mut().chain(self.recurrent_weights.iter_mut()) {
+            if mutation.should_mutate(rng) {
+                *w = mutation.perturb(*w, rng);
+            }
+        }
     }
 }
 
+#[derive(Clone)]
 pub struct NN {
     pub layers: Vec<Layer>,
+    // Outputs of every layer, reused by step
+    buffers: Vec<Vec<f64>>,
+}
+
+impl PartialEq for NN {
+    fn eq(&self, other: &NN) -> bool {
+        self.layers == other.layers
+    }
 }
 
 impl NN {
     pub fn new() -> NN {
-        NN { layers: Vec::new() }
+        NN {
+            layers: Vec::new(),
+            buffers: Vec::new(),
+        }
     }
 
     pub fn new_defined(layer_def: &[[usize; 2]]) -> NN {
         let mut nn = NN::new();
         for layer in layer_def {
-            nn.add(Layer::new(layer[0] as u32, layer[1] as u32).unwrap());
+            nn.add(Layer::new(layer[0] as u32, layer[1] as u32).unwrap()).unwrap();
         }
 
         nn
     }
 
-    pub fn add(&mut self, layer: Layer) -> bool {
-        if self.layers.is_empty() || self.layers.last().unwrap().num_neurons == layer.num_inputs {
-            self.layers.push(layer);
-            true
-        } else {
-            false
+    // Layers without a matching activation fall back to sigmoid, and layers whose index is in recurrent_layers
+    // are Elman layers
+    pub fn new_defined_with_activations(
+        layer_def: &[[usize; 2]],
+        activations: &[Activation],
+        recurrent_layers: &[usize],
+    ) -> NN {
+        let mut nn = NN::new();
+        for (i, layer) in layer_def.iter().enumerate() {
+            let activation = activations.get(i).copied().unwrap_or(Activation::Sigmoid);
+            let layer = if recurrent_layers.contains(&i) {
+                Layer::new_recurrent(layer[0] as u32, layer[1] as u32, activation)
+            } else {
+                Layer::new_with_activation(layer[0] as u32, layer[1] as u32, activation)
+            };
+            nn.add(layer.unwrap()).unwrap();
         }
+        nn
     }
 
-    pub fn propagate(&self, inputs: &Vec<f64>) -> Option<Vec<f64>> {
-        if self.layers.is_empty() || self.layers[0].num_inputs != inputs.len() as u32 {
-            None
-        } else {
-            let mut this_in = inputs;
-            let mut this_out: Vec<f64> = Vec::new();
-            for layer in &self.layers {
-                let temp = layer.feed_forward(&this_in);
-                match temp {
-                    Some(vals) => this_out = vals,
-                    None => return None,
-                }
-                //this_out = layer.feed_forward(&this_in).unwrap();
-                this_in = &this_out;
+    pub fn from_config(config: &GaConfig) -> NN {
+        NN::new_defined_with_activations(&config.layer_def, &config.activations, &config.recurrent_layers)
+    }
+
+    pub fn add(&mut self, layer: Layer) -> Result<()> {
+        if let Some(last) = self.layers.last() {
+            if last.num_neurons != layer.num_inputs {
+                return Err(Error::ShapeMismatch {
+                    what: "layer inputs",
+                    expected: last.num_neurons as usize,
+                    found: layer.num_inputs as usize,
+                });
+            }
+        }
+        self.layers.push(layer);
+        Ok(())
+    }
+
+    fn check_inputs(&self, found: usize) -> Result<()> {
+        match self.layers.first() {
+            Some(layer) => layer.check_inputs(found),
+            None => Err(Error::InvalidConfig("the network has no layers".to_string())),
+        }
+    }
+
+    pub fn propagate(&self, inputs: &[f64]) -> Result<Vec<f64>> {
+        self.check_inputs(inputs.len())?;
+        let mut this_in = inputs.to_vec();
+        let mut this_out: Vec<f64> = Vec::new();
+        for layer in &self.layers {
+            layer.feed_forward_into(&this_in, &mut this_out)?;
+            std::mem::swap(&mut this_in, &mut this_out);
+        }
+        Ok(this_in)
+    }
+
+    // Same as propagate without allocating, then remembers the outputs of the recurrent layers for the next step
+    pub fn step(&mut self, inputs: &[f64]) -> Result<&[f64]> {
+        self.check_inputs(inputs.len())?;
+        self.buffers.resize_with(self.layers.len(), Vec::new);
+        for (l, layer) in self.layers.iter_mut().enumerate() {
+            let (done, rest) = self.buffers.split_at_mut(l);
+            let layer_inputs = if l == 0 { inputs } else { &done[l - 1] };
+            layer.feed_forward_into(layer_inputs, &mut rest[0])?;
+            if layer.is_recurrent() {
+                layer.state.copy_from_slice(&rest[0]);
+            }
+        }
+        Ok(&self.buffers[self.layers.len() - 1])
+    }
+
+    // Propagates batch_size samples laid out one after the other at once, for feed-forward networks only
+    pub fn propagate_batch(&self, inputs: &[f64], batch_size: usize) -> Result<Vec<f64>> {
+        if self.layers.is_empty() {
+            return Err(Error::InvalidConfig("the network has no layers".to_string()));
+        }
+        let mut this_in = inputs.to_vec();
+        let mut this_out: Vec<f64> = Vec::new();
+        for layer in &self.layers {
+            layer.feed_forward_batch(&this_in, batch_size, &mut this_out)?;
+            std::mem::swap(&mut this_in, &mut this_out);
+        }
+        Ok(this_in)
+    }
+
+    pub fn is_recurrent(&self) -> bool {
+        self.layers.iter().any(|l| l.is_recurrent())
+    }
+
+    // Forgets the hidden state, e.g. at the start of a game
+    pub fn reset_state(&mut self) {
+        for layer in &mut self.layers {
+            for h in layer.state.iter_mut() {
+                *h = 0.0_f64;
+            }
+        }
+    }
+
+    fn mutate(&mut self, mutation: &Mutation, rng: &mut impl Rng) {
+        for layer in &mut self.layers {
+            layer.mutate(mutation, rng);
+        }
+    }
+
+    // The genome lists every neuron's bias followed by its incoming and recurrent weights, layer by layer
+    pub fn get_genome(&self) -> Vec<f64> {
+        let mut genome = Vec::new();
+        for layer in &self.layers {
+            let mut recurrent = layer.recurrent_weights.chunks_exact(layer.num_neurons as usize);
+            for (b, ws) in layer
+                .biases
+                .iter()
+                .zip(layer.weights.chunks_exact(layer.num_inputs as usize))
+            {
+                genome.push(*b);
+                genome.extend(ws.iter());
+                genome.extend(recurrent.next().into_iter().flatten());
             }
-            Some(this_out)
         }
+        genome
     }
 
-    fn mutate(&mut self) {
+    pub fn set_genome(&mut self, genome: &[f64]) -> Result<()> {
+        let num_genes = self.get_neuron_sizes().iter().sum::<usize>();
+        if genome.len() != num_genes {
+            return Err(Error::ShapeMismatch {
+                what: "genome",
+                expected: num_genes,
+                found: genome.len(),
+            });
+        }
+        let mut genes = genome.iter();
         for layer in &mut self.layers {
-            layer.mutate();
+            let mut recurrent = layer.recurrent_weights.chunks_exact_mut(layer.num_neurons as usize);
+            for (b, ws) in layer
+                .biases
+                .iter_mut()
+                .zip(layer.weights.chunks_exact_mut(layer.num_inputs as usize))
+            {
+                *b = *genes.next().unwrap();
+                for w in ws.iter_mut().chain(recurrent.next().into_iter().flatten()) {
+                    *w = *genes.next().unwrap();
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // Number of genes of each neuron in the genome
+    pub fn get_neuron_sizes(&self) -> Vec<usize> {
+        self.layers
+            .iter()
+            .flat_map(|l| {
+                let num_recurrent = if l.is_recurrent() { l.num_neurons as usize } else { 0 };
+                vec![1 + l.num_inputs as usize + num_recurrent; l.num_neurons as usize]
+            })
+            .collect()
+    }
+
+    // Mean absolute difference between the genes of two networks of the same shape
+    pub fn get_distance(&self, other: &NN) -> f64 {
+        let g1 = self.get_genome();
+        let g2 = other.get_genome();
+        if g1.is_empty() {
+            return 0.0_f64;
         }
+        g1.iter().zip(g2.iter()).map(|(a, b)| (a - b).abs()).sum::<f64>() / g1.len() as f64
     }
 
-    fn crossover(&self, other: &NN) -> (NN, NN) {
-        let mut child1 = NN::new();
-        let mut child2 = NN::new();
-        for (p1, p2) in self.layers.iter().zip(other.layers.iter()) {
-            let (c1, c2) = p1.crossover(p2);
-            child1.add(c1);
-            child2.add(c2);
+    fn crossover(&self, other: &NN, method: &CrossoverMethod, rng: &mut impl Rng) -> (NN, NN) {
+        let (g1, g2) = method.crossover(&self.get_genome(), &other.get_genome(), &self.get_neuron_sizes(), rng);
+        let mut child1 = self.clone();
+        let mut child2 = other.clone();
+        // Parents of different shapes cannot be crossed, so they are copied unchanged
+        if child1.set_genome(&g1).is_err() || child2.set_genome(&g2).is_err() {
+            return (self.clone(), other.clone());
         }
         (child1, child2)
     }
+
+    // Crossover and mutation outside of a Population, at the configured initial mutation step size
+    pub fn get_offspring(&self, other: &NN, config: &GaConfig, generation: u32, rng: &mut impl Rng) -> (NN, NN) {
+        let (mut c1, mut c2) = self.crossover(other, &config.crossover, rng);
+        let mutation =
+            config
+                .mutation
+                .get_mutation(config.mutation.get_initial_sigma(), generation, config.num_generations);
+        c1.mutate(&mutation, rng);
+        c2.mutate(&mutation, rng);
+        (c1, c2)
+    }
 }
 
 impl Brain for NN {
-    fn get_action(&mut self, inputs: &Vec<f64>) -> Option<usize> {
-        let output = self.propagate(inputs);
-        match output {
-            Some(vals) => get_index_max_float(&vals),
-            None => None,
+    fn get_action(&mut self, inputs: &[f64]) -> Result<usize> {
+        get_output_action(self.step(inputs)?)
+    }
+
+    fn on_episode_start(&mut self) {
+        self.reset_state();
+    }
+}
+
+// Fields missing from a config file take their default values
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GaConfig {
+    pub num_individuals: u32,
+    pub num_games: u32,
+    pub num_generations: u32,
+    pub layer_def: Vec<[usize; 2]>,
+    // Activation of every layer of layer_def, sigmoid for the layers past its end
+    pub activations: Vec<Activation>,
+    // Indices into layer_def of the layers that keep a hidden state between moves
+    pub recurrent_layers: Vec<usize>,
+    // Precision of the weights while playing the games
+    pub precision: Precision,
+    pub checkpoint_every: u32,
+    pub checkpoint_path: String,
+    pub seed: Option<u64>,
+    pub selection: SelectionMethod,
+    pub elitism: usize,
+    pub hall_of_fame_size: usize,
+    pub mutation: MutationConfig,
+    pub crossover: CrossoverMethod,
+    pub speciation: Option<SpeciationConfig>,
+    // Evolves several populations of num_individuals in parallel instead of one
+    pub islands: Option<IslandConfig>,
+}
+
+impl GaConfig {
+    pub fn new() -> GaConfig {
+        GaConfig {
+            num_individuals: NUM_INDIVIDUALS,
+            num_games: NUM_GAMES_NN,
+            num_generations: NUM_GENERATIONS,
+            layer_def: vec![[8, 8], [8, 4]],
+            activations: Vec::new(),
+            recurrent_layers: Vec::new(),
+            precision: Precision::F64,
+            checkpoint_every: GA_CHECKPOINT_EVERY,
+            checkpoint_path: GA_CHECKPOINT_PATH.to_string(),
+            seed: None,
+            selection: SelectionMethod::Tournament { size: TOURNAMENT_SIZE },
+            elitism: GA_ELITISM,
+            hall_of_fame_size: HALL_OF_FAME_SIZE,
+            mutation: MutationConfig::new(),
+            crossover: CrossoverMethod::Uniform {
+                rate: CROSSOVER_PROBABILITY,
+            },
+            speciation: None,
+            islands: None,
+        }
+    }
+
+    pub fn load(path: &Path) -> Result<GaConfig> {
+        let config: GaConfig = serde_json::from_str(&fs::read_to_string(path)?)?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    // Catches a layer_def that NN::new_defined could not build before any network is created
+    pub fn validate(&self) -> Result<()> {
+        if self.layer_def.is_empty() {
+            return Err(Error::InvalidConfig("layer_def has no layers".to_string()));
+        }
+        for (i, layer) in self.layer_def.iter().enumerate() {
+            if layer[0] == 0 || layer[1] == 0 {
+                return Err(Error::InvalidConfig(format!("layer {} of layer_def is empty", i)));
+            }
+            if i > 0 && self.layer_def[i - 1][1] != layer[0] {
+                return Err(Error::InvalidConfig(format!(
+                    "layer {} of layer_def takes {} inputs but the previous layer has {} neurons",
+                    i,
+                    layer[0],
+                    self.layer_def[i - 1][1]
+                )));
+            }
+        }
+        if self.activations.len() > self.layer_def.len() {
+            return Err(Error::InvalidConfig(format!(
+                "{} activations for the {} layers of layer_def",
+                self.activations.len(),
+                self.layer_def.len()
+            )));
         }
+        if let Some(i) = self.recurrent_layers.iter().find(|i| **i >= self.layer_def.len()) {
+            return Err(Error::InvalidConfig(format!(
+                "recurrent layer {} is not in layer_def",
+                i
+            )));
+        }
+        Ok(())
     }
+}
 
-    fn train(
-        &mut self,
-        _state_initial: &Vec<f64>,
-        _action: usize,
-        _reward: f64,
-        _state_final: &Vec<f64>,
-    ) -> Option<bool> {
-        Some(true)
+#[derive(Debug, PartialEq, Copy, Clone, Serialize, Deserialize)]
+pub struct SpeciationConfig {
+    // Maximum NN::get_distance from a species' leader to join it
+    pub threshold: f64,
+    // Divides every individual's fitness by the size of its species before selection
+    pub fitness_sharing: bool,
+}
+
+impl Default for GaConfig {
+    fn default() -> GaConfig {
+        GaConfig::new()
     }
 }
 
@@ -201,6 +599,10 @@ pub struct Population {
     pub length: usize,
     pub nn: Vec<NN>,
     pub fitness: Vec<f64>,
+    // Mutation step size of every individual, only evolved by self-adaptive mutation
+    pub sigma: Vec<f64>,
+    // Owned by the population so that a checkpoint can resume the exact same random sequence
+    pub rng: Pcg32,
 }
 
 impl Population {
@@ -209,51 +611,236 @@ impl Population {
             length: 0,
             nn: Vec::new(),
             fitness: Vec::new(),
+            sigma: Vec::new(),
+            rng: Pcg32::from_rng(rand::thread_rng()).unwrap(),
         }
     }
 
-    pub fn new_defined(num_nn: u32, layer_def: &[[usize; 2]]) -> Population {
+    pub fn new_defined(
+        num_nn: u32,
+        layer_def: &[[usize; 2]],
+        activations: &[Activation],
+        recurrent_layers: &[usize],
+    ) -> Population {
         let mut pop = Population::new();
         for _ in 0..num_nn {
-            pop.add(NN::new_defined(layer_def));
+            pop.add(NN::new_defined_with_activations(
+                layer_def,
+                activations,
+                recurrent_layers,
+            ));
         }
         pop
     }
 
     pub fn add(&mut self, nn: NN) {
+        self.add_with_sigma(nn, MUTATION_SIGMA);
+    }
+
+    pub fn add_with_sigma(&mut self, nn: NN, sigma: f64) {
         self.nn.push(nn);
         self.fitness.push(0f64);
+        self.sigma.push(sigma);
         self.length += 1;
     }
 
-    pub fn create_next_generation(&self) -> Population {
+    pub fn from_config(config: &GaConfig) -> Population {
+        let mut pop = Population::new_defined(
+            config.num_individuals,
+            &config.layer_def,
+            &config.activations,
+            &config.recurrent_layers,
+        );
+        if let Some(seed) = config.seed {
+            pop.rng = Pcg32::seed_from_u64(seed);
+        }
+        pop.sigma = vec![config.mutation.get_initial_sigma(); pop.length];
+        pop
+    }
+
+    // generation is the index of the generation being created, used by the mutation schedule
+    pub fn create_next_generation(&mut self, config: &GaConfig, generation: u32) -> Population {
         let mut pop = Population::new();
         let sorted_index = self.get_sorted_index();
-        let mid = self.length % 2 + self.length / 2;
-        for i in 0..mid {
-            let p1 = &self.nn[sorted_index[i]];
-            let p2 = &self.nn[sorted_index[i + 1]];
-            let (mut c1, mut c2) = p1.crossover(p2);
-            c1.mutate();
-            c2.mutate();
-            pop.add(c1);
-            if i == mid - 1 && self.length % 2 == 1 {
-                // Do nothing
-            } else {
-                pop.add(c2);
+        let selection = config.selection.build();
+        // The elite are copied unchanged so the best fitness cannot be lost to mutation
+        for &i in sorted_index.iter().take(config.elitism.min(self.length)) {
+            pop.add_with_sigma(self.nn[i].clone(), self.sigma[i]);
+        }
+        let (fitness, sorted_index) = match config.speciation {
+            Some(speciation) if speciation.fitness_sharing => {
+                let fitness = self.get_shared_fitness(&self.get_species(speciation.threshold));
+                let sorted_index = get_sorted_index(&fitness);
+                (fitness, sorted_index)
+            }
+            _ => (self.fitness.clone(), sorted_index),
+        };
+        while pop.length < self.length {
+            let i1 = selection.select(&fitness, &sorted_index, &mut self.rng);
+            let i2 = selection.select(&fitness, &sorted_index, &mut self.rng);
+            let (mut c1, mut c2) = self.nn[i1].crossover(&self.nn[i2], &config.crossover, &mut self.rng);
+            let sigma1 = config.mutation.adapt_sigma(self.sigma[i1], &mut self.rng);
+            let sigma2 = config.mutation.adapt_sigma(self.sigma[i2], &mut self.rng);
+            let mutation1 = config.mutation.get_mutation(sigma1, generation, config.num_generations);
+            let mutation2 = config.mutation.get_mutation(sigma2, generation, config.num_generations);
+            c1.mutate(&mutation1, &mut self.rng);
+            c2.mutate(&mutation2, &mut self.rng);
+            pop.add_with_sigma(c1, sigma1);
+            if pop.length < self.length {
+                pop.add_with_sigma(c2, sigma2);
             }
         }
+        pop.rng = self.rng.clone();
         pop
     }
 
     pub fn get_sorted_index(&self) -> Vec<usize> {
-        let mut index: Vec<(usize, f64)> = self.fitness.clone().into_iter().enumerate().collect();
-        index.sort_by(|(_, af), (_, bf)| bf.partial_cmp(af).unwrap_or(std::cmp::Ordering::Equal));
-        index.iter().map(|(i, _)| *i).collect()
+        get_sorted_index(&self.fitness)
+    }
+
+    // Clusters the population into species, each led by its fittest member, best species first
+    pub fn get_species(&self, threshold: f64) -> Vec<Vec<usize>> {
+        let mut species: Vec<Vec<usize>> = Vec::new();
+        for i in self.get_sorted_index() {
+            match species
+                .iter_mut()
+                .find(|s| self.nn[s[0]].get_distance(&self.nn[i]) < threshold)
+            {
+                Some(s) => s.push(i),
+                None => species.push(vec![i]),
+            }
+        }
+        species
+    }
+
+    // Fitness divided by species size, so that a large species cannot take over the population
+    pub fn get_shared_fitness(&self, species: &[Vec<usize>]) -> Vec<f64> {
+        // Fitness can be negative, so it is shifted to start at zero first
+        let min = self.fitness.iter().cloned().fold(f64::INFINITY, f64::min);
+        let mut shared = vec![0.0_f64; self.length];
+        for s in species.iter() {
+            for &i in s.iter() {
+                shared[i] = (self.fitness[i] - min) / s.len() as f64;
+            }
+        }
+        shared
+    }
+
+    // Mean absolute difference of every gene from the mean genome of the population
+    pub fn get_diversity(&self) -> f64 {
+        let genomes: Vec<Vec<f64>> = self.nn.iter().map(|nn| nn.get_genome()).collect();
+        let num_genes = genomes.first().map_or(0, |g| g.len());
+        if num_genes == 0 {
+            return 0.0_f64;
+        }
+        let mut mean = vec![0.0_f64; num_genes];
+        for g in genomes.iter() {
+            for (m, x) in mean.iter_mut().zip(g.iter()) {
+                *m += x / genomes.len() as f64;
+            }
+        }
+        let total: f64 = genomes
+            .iter()
+            .map(|g| g.iter().zip(mean.iter()).map(|(x, m)| (x - m).abs()).sum::<f64>())
+            .sum();
+        total / (genomes.len() * num_genes) as f64
+    }
+}
+
+// Logs the individuals that failed to play and leaves them out as None, so that one bad individual does not end
+// the generation. It is only an error when every individual failed, as then there is nothing to select from
+pub fn log_failures<R>(results: Vec<Result<R>>) -> Result<Vec<Option<R>>> {
+    if !results.is_empty() && results.iter().all(|r| r.is_err()) {
+        return Err(results.into_iter().find_map(|r| r.err()).unwrap());
+    }
+    Ok(results
+        .into_iter()
+        .map(|r| match r {
+            Ok(result) => Some(result),
+            Err(e) => {
+                println!("Given the worst fitness, {}", e);
+                None
+            }
+        })
+        .collect())
+}
+
+// Individuals that failed get the worst fitness of the others, so that selection drops them
+pub fn get_fitness_or_worst(fitness: &[Option<f64>]) -> Vec<f64> {
+    let worst = fitness.iter().flatten().cloned().fold(f64::INFINITY, f64::min);
+    fitness.iter().map(|f| f.unwrap_or(worst)).collect()
+}
+
+pub fn get_sorted_index(fitness: &[f64]) -> Vec<usize> {
+    let mut index: Vec<(usize, f64)> = fitness.iter().cloned().enumerate().collect();
+    index.sort_by(|(_, af), (_, bf)| bf.partial_cmp(af).unwrap_or(std::cmp::Ordering::Equal));
+    index.iter().map(|(i, _)| *i).collect()
+}
+
+#[derive(Clone)]
+pub struct HallOfFameEntry {
+    // Mean over every evaluation of the network, since a single noisy evaluation overrates lucky networks
+    pub fitness: f64,
+    pub evaluations: u32,
+    // Generation the network entered the hall of fame
+    pub generation: u32,
+    pub nn: NN,
+}
+
+// The best individuals seen across all generations, best first
+pub struct HallOfFame {
+    pub capacity: usize,
+    pub members: Vec<HallOfFameEntry>,
+}
+
+impl HallOfFame {
+    pub fn new(capacity: usize) -> HallOfFame {
+        HallOfFame {
+            capacity,
+            members: Vec::new(),
+        }
+    }
+
+    pub fn update(&mut self, pop: &Population, generation: u32) {
+        // A member copied into later generations is evaluated again on new games, and its fitness becomes the
+        // running mean of its evaluations rather than the best of them
+        for member in self.members.iter_mut() {
+            if let Some(i) = pop.nn.iter().position(|nn| *nn == member.nn) {
+                member.evaluations += 1;
+                member.fitness += (pop.fitness[i] - member.fitness) / member.evaluations as f64;
+            }
+        }
+        self.sort();
+        for i in pop.get_sorted_index().into_iter().take(self.capacity) {
+            if self.members.iter().any(|m| m.nn == pop.nn[i]) {
+                continue;
+            }
+            let fitness = pop.fitness[i];
+            if self.members.len() == self.capacity && self.members.last().is_some_and(|m| m.fitness >= fitness) {
+                break;
+            }
+            self.members.push(HallOfFameEntry {
+                fitness,
+                evaluations: 1,
+                generation,
+                nn: pop.nn[i].clone(),
+            });
+            self.sort();
+            self.members.truncate(self.capacity);
+        }
+    }
+
+    fn sort(&mut self) {
+        self.members
+            .sort_by(|a, b| b.fitness.partial_cmp(&a.fitness).unwrap_or(std::cmp::Ordering::Equal));
+    }
+
+    pub fn best(&self) -> Option<&HallOfFameEntry> {
+        self.members.first()
     }
 }
 
-fn get_index_max_float(input: &Vec<f64>) -> Option<usize> {
+pub fn get_index_max_float(input: &[f64]) -> Option<usize> {
     input
         .iter()
         .enumerate()
@@ -261,9 +848,19 @@ fn get_index_max_float(input: &Vec<f64>) -> Option<usize> {
         .map(|(index, _)| index)
 }
 
+// The action of a network is its strongest output
+pub fn get_output_action(outputs: &[f64]) -> Result<usize> {
+    get_index_max_float(outputs).ok_or(Error::ShapeMismatch {
+        what: "network outputs",
+        expected: 1,
+        found: 0,
+    })
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::mutation::MutationMethod;
 
     #[test]
     fn test_sigmoid() {
@@ -274,19 +871,57 @@ mod tests {
         assert_eq!(sigmoid(0f64), 0.5f64);
     }
 
+    fn activate(activation: Activation, zs: &[f64]) -> Vec<f64> {
+        let mut outputs = zs.to_vec();
+        activation.activate_in_place(&mut outputs);
+        outputs
+    }
+
+    #[test]
+    fn test_activation_activate() {
+        let zs = [-2.0_f64, 0.0_f64, 3.0_f64];
+        assert_eq!(activate(Activation::Linear, &zs), zs.to_vec());
+        assert_eq!(activate(Activation::Relu, &zs), vec![0.0_f64, 0.0_f64, 3.0_f64]);
+        assert_eq!(activate(Activation::LeakyRelu, &zs)[0], -2.0_f64 * LEAKY_RELU_SLOPE);
+        assert!(activate(Activation::Tanh, &zs)
+            .iter()
+            .all(|y| *y > -1.0_f64 && *y < 1.0_f64));
+        let soft = activate(Activation::Softmax, &zs);
+        assert!((soft.iter().sum::<f64>() - 1.0_f64).abs() < 1e-12);
+        assert!(soft[2] > soft[1] && soft[1] > soft[0]);
+        let soft = activate(Activation::Softmax, &[1000.0_f64, 1000.0_f64]);
+        assert_eq!(soft, vec![0.5_f64, 0.5_f64]);
+    }
+
+    #[test]
+    fn test_nn_new_defined_with_activations() {
+        let nn =
+            NN::new_defined_with_activations(&[[4, 3], [3, 2], [2, 1]], &[Activation::Relu, Activation::Tanh], &[]);
+        assert_eq!(nn.layers[0].activation, Activation::Relu);
+        assert_eq!(nn.layers[1].activation, Activation::Tanh);
+        assert_eq!(nn.layers[2].activation, Activation::Sigmoid);
+        let nn = NN::new_defined_with_activations(&[[3, 4], [4, 2]], &[Activation::Relu, Activation::Linear], &[]);
+        let mut nn_big = nn.clone();
+        for b in nn_big.layers[1].biases.iter_mut() {
+            *b = 10.0_f64;
+        }
+        // Linear outputs are not squashed into (0, 1)
+        let outputs = nn_big.propagate(&[0.0_f64, 0.0_f64, 0.0_f64]).unwrap();
+        assert!(outputs.iter().all(|y| *y > 1.0_f64));
+        let (c1, c2) = nn.crossover(&nn, &CrossoverMethod::TwoPoint, &mut rand::thread_rng());
+        assert_eq!(c1.layers[1].activation, Activation::Linear);
+        assert_eq!(c2.layers[0].activation, Activation::Relu);
+    }
+
     #[test]
     fn test_layer_new() {
         let layer = Layer::new(3, 2).unwrap();
         assert_eq!(layer.num_inputs, 3);
         assert_eq!(layer.num_neurons, 2);
-        assert_eq!(layer.weights.len(), 2);
-        assert_eq!(layer.weights.first().unwrap().len(), 3);
+        assert_eq!(layer.weights.len(), 6);
+        assert_eq!(layer.get_neuron_weights(1), &layer.weights[3..]);
         assert_eq!(layer.biases.len(), 2);
-        let res = Layer::new(0, 0);
-        match res {
-            Some(_) => assert!(false),
-            None => assert!(true),
-        }
+        assert!(Layer::new(0, 0).is_err());
     }
 
     #[test]
@@ -294,7 +929,7 @@ mod tests {
         let layer = Layer::new(3, 2).unwrap();
         let mut inputs = vec![1f64, 2f64];
         let mut outputs = layer.feed_forward(&inputs);
-        assert_eq!(outputs, None);
+        assert!(outputs.is_err());
         inputs = vec![0f64, 1f64, 0f64];
         outputs = layer.feed_forward(&inputs);
         println!("{:?}", outputs);
@@ -324,9 +959,16 @@ mod tests {
         let layer1 = Layer::new(3, 2).unwrap();
         let layer2 = Layer::new(2, 1).unwrap();
         let layer3 = Layer::new(3, 1).unwrap();
-        assert!(nn.add(layer1));
-        assert!(nn.add(layer2));
-        assert!(!nn.add(layer3));
+        assert!(nn.add(layer1).is_ok());
+        assert!(nn.add(layer2).is_ok());
+        assert!(matches!(
+            nn.add(layer3),
+            Err(Error::ShapeMismatch {
+                expected: 1,
+                found: 3,
+                ..
+            })
+        ));
     }
 
     #[test]
@@ -334,16 +976,80 @@ mod tests {
         let nn = NN::new_defined(&[[3, 2], [2, 1]]);
         let inputs = vec![0.0_f64, 1.0_f64];
         let outputs = nn.propagate(&inputs);
-        assert_eq!(outputs, None);
+        assert!(outputs.is_err());
+        assert!(NN::new().propagate(&inputs).is_err());
         let inputs = vec![0.0_f64, 1.0_f64, 0.0_f64];
         let outputs = nn.propagate(&inputs);
-        assert_ne!(outputs, None);
+        assert!(outputs.is_ok());
         let vals = outputs.unwrap();
         assert_eq!(vals.len(), 1);
         assert!(vals[0] >= 0f64);
         assert!(vals[0] <= 1f64);
     }
 
+    #[test]
+    fn test_nn_genome() {
+        let nn = NN::new_defined(&[[3, 2], [2, 1]]);
+        let genome = nn.get_genome();
+        assert_eq!(genome.len(), 2 * 4 + 3);
+        assert_eq!(nn.get_neuron_sizes(), vec![4, 4, 3]);
+        assert_eq!(genome[0], nn.layers[0].biases[0]);
+        assert_eq!(genome[1..4], nn.layers[0].weights[..3]);
+        assert_eq!(genome[8], nn.layers[1].biases[0]);
+        let mut other = NN::new_defined(&[[3, 2], [2, 1]]);
+        assert!(other.set_genome(&genome).is_ok());
+        assert!(other == nn);
+        assert!(matches!(
+            other.set_genome(&genome[1..]),
+            Err(Error::ShapeMismatch { what: "genome", .. })
+        ));
+    }
+
+    #[test]
+    fn test_nn_recurrent() {
+        let mut nn = NN::new_defined_with_activations(&[[3, 2], [2, 1]], &[], &[0]);
+        assert!(nn.layers[0].is_recurrent() && !nn.layers[1].is_recurrent());
+        // Every neuron of the recurrent layer also carries a weight from each neuron of its layer
+        assert_eq!(nn.get_neuron_sizes(), vec![6, 6, 3]);
+        let genome = nn.get_genome();
+        assert_eq!(genome[4..6], nn.layers[0].recurrent_weights[..2]);
+        let mut other = NN::new_defined_with_activations(&[[3, 2], [2, 1]], &[], &[0]);
+        assert!(other.set_genome(&genome).is_ok());
+        assert!(other == nn);
+
+        // The hidden state makes the same input give different outputs until it is reset
+        let inputs = vec![1.0_f64, 0.0_f64, 1.0_f64];
+        let first = nn.step(&inputs).unwrap().to_vec();
+        assert_eq!(first, other.propagate(&inputs).unwrap());
+        assert_ne!(nn.step(&inputs).unwrap().to_vec(), first);
+        assert!(nn == other);
+        nn.on_episode_start();
+        assert_eq!(nn.step(&inputs).unwrap().to_vec(), first);
+        assert!(nn.step(&inputs[1..]).is_err());
+    }
+
+    #[test]
+    fn test_population_crossover_methods() {
+        let methods = [
+            CrossoverMethod::Uniform { rate: 0.5 },
+            CrossoverMethod::SinglePoint,
+            CrossoverMethod::TwoPoint,
+            CrossoverMethod::Neuron { rate: 0.5 },
+            CrossoverMethod::Arithmetic,
+            CrossoverMethod::Blend { alpha: 0.5 },
+        ];
+        for method in methods.iter() {
+            let mut config = GaConfig::new();
+            config.crossover = *method;
+            config.mutation.probability = 0.0;
+            let mut pop = Population::new_defined(5, &[[3, 2], [2, 1]], &[], &[]);
+            pop.fitness = vec![1.0, 2.0, 3.0, 4.0, 5.0];
+            let next_gen = pop.create_next_generation(&config, 1);
+            assert_eq!(next_gen.length, 5);
+            assert!(next_gen.nn.iter().all(|nn| nn.get_genome().len() == 11));
+        }
+    }
+
     #[test]
     fn test_population_new() {
         let pop = Population::new();
@@ -352,7 +1058,7 @@ mod tests {
 
     #[test]
     fn test_population_new_defined() {
-        let pop = Population::new_defined(10, &[[4, 3], [3, 2], [2, 1]]);
+        let pop = Population::new_defined(10, &[[4, 3], [3, 2], [2, 1]], &[], &[]);
         assert_eq!(pop.length, 10);
         assert_eq!(pop.nn[0].layers.len(), 3);
         assert_eq!(pop.nn[0].layers[0].num_inputs, 4);
@@ -361,21 +1067,40 @@ mod tests {
         assert_eq!(pop.nn[9].layers[2].num_neurons, 1);
     }
 
+    #[test]
+    fn test_population_from_config_activations() {
+        let mut config = GaConfig::new();
+        config.num_individuals = 3;
+        config.activations = vec![Activation::Relu, Activation::Linear];
+        config.recurrent_layers = vec![0];
+        let pop = Population::from_config(&config);
+        for nn in pop.nn.iter() {
+            // The recurrent layer keeps its configured activation
+            assert!(nn.layers[0].is_recurrent());
+            assert_eq!(nn.layers[0].activation, Activation::Relu);
+            assert_eq!(nn.layers[1].activation, Activation::Linear);
+        }
+        config.activations = vec![Activation::Tanh];
+        let nn = NN::from_config(&config);
+        assert_eq!(nn.layers[0].activation, Activation::Tanh);
+        assert_eq!(nn.layers[1].activation, Activation::Sigmoid);
+    }
+
     #[test]
     fn test_population_add() {
         let mut pop = Population::new();
         let mut nn1 = NN::new();
         let layer1 = Layer::new(3, 2).unwrap();
         let layer2 = Layer::new(2, 1).unwrap();
-        nn1.add(layer1);
-        nn1.add(layer2);
+        nn1.add(layer1).unwrap();
+        nn1.add(layer2).unwrap();
         pop.add(nn1);
         assert_eq!(pop.length, 1);
         let mut nn2 = NN::new();
         let layer1 = Layer::new(3, 2).unwrap();
         let layer2 = Layer::new(2, 1).unwrap();
-        nn2.add(layer1);
-        nn2.add(layer2);
+        nn2.add(layer1).unwrap();
+        nn2.add(layer2).unwrap();
         pop.add(nn2);
         assert_eq!(pop.length, 2);
         assert_eq!(pop.nn.len(), 2);
@@ -388,13 +1113,13 @@ mod tests {
         let mut nn1 = NN::new();
         let layer1 = Layer::new(3, 2).unwrap();
         let layer2 = Layer::new(2, 1).unwrap();
-        nn1.add(layer1);
-        nn1.add(layer2);
+        nn1.add(layer1).unwrap();
+        nn1.add(layer2).unwrap();
         let mut nn2 = NN::new();
         let layer1 = Layer::new(3, 2).unwrap();
         let layer2 = Layer::new(2, 1).unwrap();
-        nn2.add(layer1);
-        nn2.add(layer2);
+        nn2.add(layer1).unwrap();
+        nn2.add(layer2).unwrap();
         pop.nn.push(nn1);
         pop.nn.push(nn2);
         pop.fitness = vec![5.0, 10.0];
@@ -403,8 +1128,8 @@ mod tests {
         let mut nn3 = NN::new();
         let layer1 = Layer::new(3, 2).unwrap();
         let layer2 = Layer::new(2, 1).unwrap();
-        nn3.add(layer1);
-        nn3.add(layer2);
+        nn3.add(layer1).unwrap();
+        nn3.add(layer2).unwrap();
         pop.nn.push(nn3);
         pop.fitness = vec![5.0, 10.0, 7.0];
         let si = pop.get_sorted_index();
@@ -417,26 +1142,214 @@ mod tests {
         let mut nn1 = NN::new();
         let layer1 = Layer::new(3, 2).unwrap();
         let layer2 = Layer::new(2, 1).unwrap();
-        nn1.add(layer1);
-        nn1.add(layer2);
+        nn1.add(layer1).unwrap();
+        nn1.add(layer2).unwrap();
         let mut nn2 = NN::new();
         let layer1 = Layer::new(3, 2).unwrap();
         let layer2 = Layer::new(2, 1).unwrap();
-        nn2.add(layer1);
-        nn2.add(layer2);
+        nn2.add(layer1).unwrap();
+        nn2.add(layer2).unwrap();
         pop.add(nn1);
         pop.add(nn2);
         pop.fitness = vec![5.0, 10.0];
-        let next_gen = pop.create_next_generation();
+        let next_gen = pop.create_next_generation(&GaConfig::new(), 1);
         assert_eq!(pop.length, next_gen.length);
         let mut nn3 = NN::new();
         let layer1 = Layer::new(3, 2).unwrap();
         let layer2 = Layer::new(2, 1).unwrap();
-        nn3.add(layer1);
-        nn3.add(layer2);
+        nn3.add(layer1).unwrap();
+        nn3.add(layer2).unwrap();
         pop.add(nn3);
         pop.fitness = vec![5.0, 10.0, 7.0];
-        let next_gen = pop.create_next_generation();
+        let next_gen = pop.create_next_generation(&GaConfig::new(), 1);
         assert_eq!(pop.length, next_gen.length);
     }
+
+    #[test]
+    fn test_population_selection_methods() {
+        let methods = [
+            SelectionMethod::Tournament { size: 2 },
+            SelectionMethod::Roulette,
+            SelectionMethod::Rank,
+            SelectionMethod::Truncation { fraction: 0.2 },
+        ];
+        for method in methods.iter() {
+            let mut config = GaConfig::new();
+            config.selection = *method;
+            let mut pop = Population::new_defined(5, &[[3, 2], [2, 1]], &[], &[]);
+            pop.fitness = vec![1.0, 2.0, 3.0, 4.0, 5.0];
+            let next_gen = pop.create_next_generation(&config, 1);
+            assert_eq!(next_gen.length, 5);
+            assert_eq!(next_gen.fitness.len(), 5);
+        }
+    }
+
+    #[test]
+    fn test_population_elitism() {
+        let mut config = GaConfig::new();
+        config.elitism = 2;
+        let mut pop = Population::new_defined(5, &[[3, 2], [2, 1]], &[], &[]);
+        pop.fitness = vec![1.0, 5.0, 3.0, 4.0, 2.0];
+        let next_gen = pop.create_next_generation(&config, 1);
+        assert_eq!(next_gen.length, 5);
+        assert!(next_gen.nn[0] == pop.nn[1]);
+        assert!(next_gen.nn[1] == pop.nn[3]);
+        config.elitism = 10;
+        let next_gen = pop.create_next_generation(&config, 1);
+        assert_eq!(next_gen.length, 5);
+    }
+
+    #[test]
+    fn test_population_mutation_methods() {
+        let mut config = GaConfig::new();
+        config.num_individuals = 6;
+        config.layer_def = vec![[3, 2], [2, 1]];
+        config.elitism = 0;
+        config.mutation.probability = 1.0;
+        config.mutation.method = MutationMethod::Gaussian { sigma: 0.001 };
+        let mut pop = Population::from_config(&config);
+        let first = pop.nn[0].clone();
+        pop.nn = vec![first.clone(); 6];
+        let next_gen = pop.create_next_generation(&config, 1);
+        // Every gene moves, but only by a small step
+        for nn in next_gen.nn.iter() {
+            let (a, b) = (&nn.layers[0].weights[0], &first.layers[0].weights[0]);
+            assert!(a != b && (a - b).abs() < 0.01);
+        }
+
+        config.mutation.method = MutationMethod::SelfAdaptive {
+            initial_sigma: 0.2,
+            tau: 0.5,
+        };
+        let mut pop = Population::from_config(&config);
+        assert_eq!(pop.sigma, vec![0.2; 6]);
+        let next_gen = pop.create_next_generation(&config, 1);
+        assert_eq!(next_gen.sigma.len(), 6);
+        assert!(next_gen.sigma.iter().all(|s| *s > 0.0 && *s != 0.2));
+    }
+
+    #[test]
+    fn test_population_species_and_diversity() {
+        let layer_def = [[3, 2], [2, 1]];
+        let mut pop = Population::new();
+        let a = NN::new_defined(&layer_def);
+        let mut b = a.clone();
+        b.set_genome(&a.get_genome().iter().map(|g| g + 10.0).collect::<Vec<f64>>())
+            .unwrap();
+        assert_eq!(a.get_distance(&a), 0.0);
+        assert!((a.get_distance(&b) - 10.0).abs() < 1e-9);
+        for nn in [a.clone(), a.clone(), a.clone(), b.clone()].iter() {
+            pop.add(nn.clone());
+        }
+        pop.fitness = vec![1.0, 2.0, 3.0, 0.0];
+        let species = pop.get_species(1.0);
+        assert_eq!(species, vec![vec![2, 1, 0], vec![3]]);
+        // The lone individual keeps all of its (shifted) fitness, the crowded ones share theirs
+        let shared = pop.get_shared_fitness(&species);
+        assert_eq!(shared, vec![1.0 / 3.0, 2.0 / 3.0, 1.0, 0.0]);
+        assert!((pop.get_diversity() - 3.75).abs() < 1e-9);
+        let mut clones = Population::new();
+        clones.add(a.clone());
+        clones.add(a);
+        assert_eq!(clones.get_diversity(), 0.0);
+
+        let mut config = GaConfig::new();
+        config.speciation = Some(SpeciationConfig {
+            threshold: 1.0,
+            fitness_sharing: true,
+        });
+        let next_gen = pop.create_next_generation(&config, 1);
+        assert_eq!(next_gen.length, 4);
+    }
+
+    #[test]
+    fn test_log_failures() {
+        let failure = || Err(Error::InvalidConfig("test".to_string()));
+        let results = log_failures(vec![Ok(2.0), failure(), Ok(-1.0)]).unwrap();
+        assert_eq!(results, vec![Some(2.0), None, Some(-1.0)]);
+        // The failed individual ranks last without distorting the fitness of the others
+        assert_eq!(get_fitness_or_worst(&results), vec![2.0, -1.0, -1.0]);
+        let results: Vec<Result<f64>> = vec![failure(), failure()];
+        assert!(matches!(log_failures(results), Err(Error::InvalidConfig(_))));
+        assert!(log_failures(Vec::<Result<f64>>::new()).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_hall_of_fame_update() {
+        let mut hof = HallOfFame::new(2);
+        assert!(hof.best().is_none());
+        let mut pop = Population::new_defined(3, &[[3, 2], [2, 1]], &[], &[]);
+        pop.fitness = vec![1.0, 3.0, 2.0];
+        hof.update(&pop, 0);
+        assert_eq!(hof.members.len(), 2);
+        assert_eq!(hof.best().unwrap().fitness, 3.0);
+        assert_eq!(hof.members[1].fitness, 2.0);
+        // The same network is not entered twice, while a better newcomer displaces the worst member
+        let mut next = Population::new();
+        next.add(pop.nn[1].clone());
+        next.add(NN::new_defined(&[[3, 2], [2, 1]]));
+        next.fitness = vec![2.0, 2.25];
+        hof.update(&next, 1);
+        assert_eq!(hof.members.len(), 2);
+        // A re-evaluated member keeps the mean of its evaluations and the generation it entered in
+        assert_eq!(hof.best().unwrap().fitness, 2.5);
+        assert_eq!(hof.best().unwrap().evaluations, 2);
+        assert_eq!(hof.best().unwrap().generation, 0);
+        assert_eq!(hof.members[1].fitness, 2.25);
+        assert_eq!(hof.members[1].generation, 1);
+        // A lucky evaluation does not make up for the others, so the newcomer overtakes it
+        next.fitness = vec![1.0, 3.25];
+        hof.update(&next, 2);
+        assert_eq!(hof.members.len(), 2);
+        assert_eq!(hof.best().unwrap().fitness, 2.75);
+        assert!(hof.best().unwrap().nn == next.nn[1]);
+        assert_eq!(hof.members[1].fitness, 2.0);
+        assert_eq!(hof.members[1].evaluations, 3);
+    }
+
+    #[test]
+    fn test_ga_config_load() {
+        let mut config = GaConfig::new();
+        config.selection = SelectionMethod::Truncation { fraction: 0.3 };
+        let path = std::env::temp_dir().join(format!("snake_01_{}_config.json", std::process::id()));
+        fs::write(&path, serde_json::to_string(&config).unwrap()).unwrap();
+        let loaded = GaConfig::load(&path).unwrap();
+        assert_eq!(loaded, config);
+        // A partial config keeps the defaults for the missing fields
+        fs::write(&path, "{ \"elitism\": 0 }").unwrap();
+        let loaded = GaConfig::load(&path).unwrap();
+        assert_eq!(loaded.elitism, 0);
+        assert_eq!(loaded.hall_of_fame_size, HALL_OF_FAME_SIZE);
+        // Layers that do not connect are refused before any network is built
+        fs::write(&path, "{ \"layer_def\": [[8, 8], [6, 4]] }").unwrap();
+        assert!(matches!(GaConfig::load(&path), Err(Error::InvalidConfig(_))));
+        fs::write(&path, "{ \"recurrent_layers\": [2] }").unwrap();
+        assert!(matches!(GaConfig::load(&path), Err(Error::InvalidConfig(_))));
+        fs::write(&path, "{ \"activations\": [\"Relu\", \"Linear\", \"Tanh\"] }").unwrap();
+        assert!(matches!(GaConfig::load(&path), Err(Error::InvalidConfig(_))));
+        fs::write(&path, "{ \"elitism\": ").unwrap();
+        assert!(matches!(GaConfig::load(&path), Err(Error::Parse(_))));
+        fs::remove_file(&path).unwrap();
+        assert!(matches!(GaConfig::load(&path), Err(Error::Io(_))));
+    }
+
+    #[test]
+    fn test_population_seeded_generations() {
+        let mut config = GaConfig::new();
+        config.num_individuals = 6;
+        config.layer_def = vec![[3, 2], [2, 1]];
+        config.seed = Some(7);
+        let mut pop1 = Population::from_config(&config);
+        let mut pop2 = Population::from_config(&config);
+        pop2.nn = pop1.nn.clone();
+        pop1.fitness = vec![1.0, 2.0, 3.0, 4.0, 5.0, 6.0];
+        pop2.fitness = pop1.fitness.clone();
+        let next1 = pop1.create_next_generation(&config, 1);
+        let next2 = pop2.create_next_generation(&config, 1);
+        assert_eq!(next1.length, 6);
+        for (a, b) in next1.nn.iter().zip(next2.nn.iter()) {
+            assert_eq!(a.layers[0].weights, b.layers[0].weights);
+            assert_eq!(a.layers[1].biases, b.layers[1].biases);
+        }
+    }
 }
@@ -0,0 +1,211 @@
+use rand::SeedableRng;
+use rand_pcg::Pcg32;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::constants::*;
+use crate::error::{Error, Result};
+use crate::gen_alg::{get_fitness_or_worst, log_failures, GaConfig, Population, NN};
+
+#[derive(Debug, PartialEq, Copy, Clone, Serialize, Deserialize)]
+pub enum MigrationTopology {
+    // Every island sends its migrants to the next one
+    Ring,
+    // Every island sends its migrants to all the others
+    FullyConnected,
+}
+
+#[derive(Debug, PartialEq, Copy, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct IslandConfig {
+    pub num_islands: usize,
+    // Generations between migrations
+    pub migration_interval: u32,
+    // Best individuals sent by every island, replacing the worst ones of the receiving island
+    pub num_migrants: usize,
+    pub topology: MigrationTopology,
+}
+
+impl IslandConfig {
+    pub fn new() -> IslandConfig {
+        IslandConfig {
+            num_islands: NUM_ISLANDS,
+            migration_interval: MIGRATION_INTERVAL,
+            num_migrants: NUM_MIGRANTS,
+            topology: MigrationTopology::Ring,
+        }
+    }
+}
+
+impl Default for IslandConfig {
+    fn default() -> IslandConfig {
+        IslandConfig::new()
+    }
+}
+
+// Each island is a Population of GaConfig::num_individuals evolving independently between migrations
+pub struct Archipelago {
+    pub islands: Vec<Population>,
+    pub config: IslandConfig,
+}
+
+impl Archipelago {
+    pub fn new(ga_config: &GaConfig, config: IslandConfig) -> Archipelago {
+        let islands = (0..config.num_islands.max(1))
+            .map(|i| {
+                let mut pop = Population::from_config(ga_config);
+                if let Some(seed) = ga_config.seed {
+                    pop.rng = Pcg32::seed_from_u64(seed.wrapping_add(i as u64));
+                }
+                pop
+            })
+            .collect();
+        Archipelago { islands, config }
+    }
+
+    // Every island is evaluated on its own rayon worker, and an individual that fails gets the worst fitness of
+    // its island. An error names the failing individual by its position across all the islands, counting from
+    // the first island
+    pub fn evaluate<F>(&mut self, evaluate: &F) -> Result<()>
+    where
+        F: Fn(&mut NN) -> Result<f64> + Sync,
+    {
+        self.islands.par_iter_mut().enumerate().try_for_each(|(island, pop)| {
+            let offset = island * pop.nn.len();
+            let results = pop
+                .nn
+                .iter_mut()
+                .enumerate()
+                .map(|(i, nn)| evaluate(nn).map_err(|e| Error::individual(offset + i, e)))
+                .collect();
+            pop.fitness = get_fitness_or_worst(&log_failures(results)?);
+            Ok(())
+        })
+    }
+
+    pub fn create_next_generation(&mut self, ga_config: &GaConfig, generation: u32) {
+        self.islands
+            .par_iter_mut()
+            .for_each(|pop| *pop = pop.create_next_generation(ga_config, generation));
+    }
+
+    fn get_destinations(&self, source: usize) -> Vec<usize> {
+        let n = self.islands.len();
+        match self.config.topology {
+            MigrationTopology::Ring => vec![(source + 1) % n],
+            MigrationTopology::FullyConnected => (0..n).filter(|i| *i != source).collect(),
+        }
+    }
+
+    // Copies the best individuals of every island over the worst ones of its destinations, keeping their fitness
+    pub fn migrate(&mut self) {
+        if self.islands.len() < 2 {
+            return;
+        }
+        let mut arrivals: Vec<Vec<(NN, f64, f64)>> = vec![Vec::new(); self.islands.len()];
+        for (source, pop) in self.islands.iter().enumerate() {
+            let migrants: Vec<(NN, f64, f64)> = pop
+                .get_sorted_index()
+                .into_iter()
+                .take(self.config.num_migrants)
+                .map(|i| (pop.nn[i].clone(), pop.fitness[i], pop.sigma[i]))
+                .collect();
+            for destination in self.get_destinations(source) {
+                arrivals[destination].extend(migrants.iter().cloned());
+            }
+        }
+        for (pop, migrants) in self.islands.iter_mut().zip(arrivals) {
+            let worst = pop.get_sorted_index().into_iter().rev();
+            for (i, (nn, fitness, sigma)) in worst.zip(migrants) {
+                pop.nn[i] = nn;
+                pop.fitness[i] = fitness;
+                pop.sigma[i] = sigma;
+            }
+        }
+    }
+
+    pub fn get_island_best(&self) -> Vec<f64> {
+        self.islands
+            .iter()
+            .map(|pop| pop.fitness.iter().cloned().fold(f64::NEG_INFINITY, f64::max))
+            .collect()
+    }
+
+    // Island and individual index of the best individual overall
+    pub fn get_best(&self) -> (usize, usize) {
+        let island_best = self.get_island_best();
+        let island = (0..island_best.len())
+            .max_by(|a, b| {
+                island_best[*a]
+                    .partial_cmp(&island_best[*b])
+                    .unwrap_or(std::cmp::Ordering::Equal)
+            })
+            .unwrap_or(0);
+        (island, self.islands[island].get_sorted_index()[0])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn get_archipelago(topology: MigrationTopology) -> Archipelago {
+        let mut ga_config = GaConfig::new();
+        ga_config.num_individuals = 4;
+        ga_config.layer_def = vec![[3, 2], [2, 1]];
+        let mut config = IslandConfig::new();
+        config.num_islands = 3;
+        config.num_migrants = 1;
+        config.topology = topology;
+        let mut archipelago = Archipelago::new(&ga_config, config);
+        for (i, pop) in archipelago.islands.iter_mut().enumerate() {
+            pop.fitness = [1.0, 2.0, 3.0, 4.0].iter().map(|f| f + 10.0 * i as f64).collect();
+        }
+        archipelago
+    }
+
+    #[test]
+    fn test_archipelago_migrate_ring() {
+        let mut archipelago = get_archipelago(MigrationTopology::Ring);
+        let best: Vec<NN> = archipelago.islands.iter().map(|pop| pop.nn[3].clone()).collect();
+        archipelago.migrate();
+        // The best of island 2 replaces the worst of island 0, and so on around the ring
+        assert!(archipelago.islands[0].nn[0] == best[2]);
+        assert_eq!(archipelago.islands[0].fitness[0], 24.0);
+        assert!(archipelago.islands[1].nn[0] == best[0]);
+        assert!(archipelago.islands[2].nn[0] == best[1]);
+        assert_eq!(archipelago.islands[2].fitness, vec![14.0, 22.0, 23.0, 24.0]);
+    }
+
+    #[test]
+    fn test_archipelago_migrate_fully_connected() {
+        let mut archipelago = get_archipelago(MigrationTopology::FullyConnected);
+        archipelago.migrate();
+        assert_eq!(archipelago.islands[0].fitness, vec![14.0, 24.0, 3.0, 4.0]);
+        assert_eq!(archipelago.islands[1].fitness, vec![4.0, 24.0, 13.0, 14.0]);
+        assert_eq!(archipelago.get_island_best(), vec![24.0, 24.0, 24.0]);
+    }
+
+    #[test]
+    fn test_archipelago_evolve() {
+        let mut ga_config = GaConfig::new();
+        ga_config.num_individuals = 5;
+        ga_config.layer_def = vec![[3, 2], [2, 1]];
+        let mut archipelago = Archipelago::new(&ga_config, IslandConfig::new());
+        let evaluate = |nn: &mut NN| Ok(nn.layers[0].biases[0]);
+        archipelago.evaluate(&evaluate).unwrap();
+        let (island, i) = archipelago.get_best();
+        let best = archipelago.islands[island].fitness[i];
+        assert!(archipelago.get_island_best().iter().all(|f| *f <= best));
+        archipelago.create_next_generation(&ga_config, 1);
+        archipelago.evaluate(&evaluate).unwrap();
+        assert_eq!(archipelago.islands.len(), NUM_ISLANDS);
+        assert!(archipelago.islands.iter().all(|pop| pop.length == 5 && pop.fitness.len() == 5));
+        // Elitism keeps every island's best
+        let (island, i) = archipelago.get_best();
+        assert!(archipelago.islands[island].fitness[i] >= best);
+        // A network that cannot play is reported instead of panicking the pool
+        let failing = |nn: &mut NN| Ok(nn.propagate(&[0.0_f64])?[0]);
+        assert!(matches!(archipelago.evaluate(&failing), Err(Error::Individual { .. })));
+    }
+}
@@ -1,34 +1,207 @@
+mod backprop;
+mod behaviour;
 mod constants;
+mod crossover;
+mod dqn;
+mod dynaq;
+mod error;
+mod es;
+mod evaluate;
 mod game;
 mod gen_alg;
+mod island;
+mod montecarlo;
+mod mutation;
+mod neat;
+mod nn32;
+mod novelty;
+mod nstep;
+mod persist;
+mod qlambda;
 mod qlearn;
 mod render;
+mod scripted;
+mod selection;
 
 extern crate rayon;
 
+use rand::SeedableRng;
+use rand_pcg::Pcg32;
 use rayon::prelude::*;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Instant;
 
+use crate::backprop::{Loss, Optimizer};
+use crate::behaviour::{Behaviour, BehaviourRecorder};
 use crate::constants::*;
+use crate::dqn::Dqn;
+use crate::dynaq::DynaQ;
+use crate::error::{Error, Result};
+use crate::es::{AntitheticEs, CmaEs, EvolutionStrategy};
+use crate::evaluate::{play_seeded, Report};
 use crate::game::{Brain, Game};
-use crate::gen_alg::{Population, NN};
+use crate::gen_alg::{
+    get_fitness_or_worst, get_index_max_float, get_output_action, log_failures, Activation, GaConfig, HallOfFame,
+    Population, NN,
+};
+use crate::island::Archipelago;
+use crate::montecarlo::{MonteCarloLearner, VisitType};
+use crate::neat::{Genome, NeatPopulation};
+use crate::nn32::{Precision, NN32};
+use crate::novelty::{MapElites, NoveltyArchive};
+use crate::nstep::NStepLearner;
+use crate::persist::{load_checkpoint, load_nn, load_qlearner, save_checkpoint, save_nn, save_qlearner};
+use crate::qlambda::{LambdaLearner, LambdaMethod, TraceType};
 use crate::qlearn::QLearner;
 use crate::render::Render;
+use crate::scripted::{GreedyBrain, HamiltonianBrain, PathfinderBrain, RandomBrain, SafeRandomBrain};
 
 enum GameType {
     Human,
     GeneticAlgorithm,
     QLearning,
+    WatkinsQLambda,
+    SarsaLambda,
+    NStep,
+    MonteCarloFirstVisit,
+    MonteCarloEveryVisit,
+    DynaQ,
+    DeepQNetwork,
+    ReplayNN,
+    ReplayQL,
+    Distill,
+    Neat,
+    EvolutionStrategy,
+    CmaEs,
+    Novelty,
+    MapElites,
+    Scripted,
+    Baseline,
+    Evaluate,
+    Benchmark,
+}
+
+impl GameType {
+    fn from_arg(arg: Option<String>) -> GameType {
+        match arg.as_deref() {
+            Some("human") => GameType::Human,
+            Some("ga") => GameType::GeneticAlgorithm,
+            Some("qlambda") => GameType::WatkinsQLambda,
+            Some("sarsalambda") => GameType::SarsaLambda,
+            Some("nstep") => GameType::NStep,
+            Some("mcfirst") => GameType::MonteCarloFirstVisit,
+            Some("mcevery") => GameType::MonteCarloEveryVisit,
+            Some("dynaq") => GameType::DynaQ,
+            Some("dqn") => GameType::DeepQNetwork,
+            Some("replay") => GameType::ReplayNN,
+            Some("replayql") => GameType::ReplayQL,
+            Some("distill") => GameType::Distill,
+            Some("neat") => GameType::Neat,
+            Some("es") => GameType::EvolutionStrategy,
+            Some("cmaes") => GameType::CmaEs,
+            Some("novelty") => GameType::Novelty,
+            Some("mapelites") => GameType::MapElites,
+            Some("scripted") => GameType::Scripted,
+            Some("baseline") => GameType::Baseline,
+            Some("eval") => GameType::Evaluate,
+            Some("bench") => GameType::Benchmark,
+            _ => GameType::QLearning,
+        }
+    }
 }
 
 fn main() {
-    let game_type = GameType::QLearning;
+    let game_type = GameType::from_arg(std::env::args().nth(1));
 
     match game_type {
         GameType::Human => render_game(),
         GameType::GeneticAlgorithm => {
-            iterate_population(NUM_INDIVIDUALS, NUM_GAMES_NN, NUM_GENERATIONS, fitness_function_nn)
+            let args: Vec<String> = std::env::args().skip(2).collect();
+            iterate_population(
+                get_flag(&args, "--config"),
+                get_flag(&args, "--resume"),
+                fitness_function_nn,
+            )
+        }
+        GameType::QLearning => iterate_saved_qls(std::env::args().nth(2), NUM_GAMES_QL, fitness_function_ql),
+        GameType::WatkinsQLambda => {
+            let qls = (0..NUM_QLS)
+                .map(|_| LambdaLearner::new(8, 4, LambdaMethod::WatkinsQ, get_trace_type()))
+                .collect();
+            iterate_qls(qls, NUM_GAMES_QL, fitness_function_ql)
+        }
+        GameType::SarsaLambda => {
+            let qls = (0..NUM_QLS)
+                .map(|_| LambdaLearner::new(8, 4, LambdaMethod::Sarsa, get_trace_type()))
+                .collect();
+            iterate_qls(qls, NUM_GAMES_QL, fitness_function_ql)
+        }
+        GameType::NStep => {
+            let qls = (0..NUM_QLS).map(|_| NStepLearner::new(8, 4, NSTEP_N)).collect();
+            iterate_qls(qls, NUM_GAMES_QL, fitness_function_ql)
+        }
+        GameType::MonteCarloFirstVisit => {
+            let qls = (0..NUM_QLS)
+                .map(|_| MonteCarloLearner::new(8, 4, VisitType::FirstVisit))
+                .collect();
+            iterate_qls(qls, NUM_GAMES_QL, fitness_function_ql)
+        }
+        GameType::MonteCarloEveryVisit => {
+            let qls = (0..NUM_QLS)
+                .map(|_| MonteCarloLearner::new(8, 4, VisitType::EveryVisit))
+                .collect();
+            iterate_qls(qls, NUM_GAMES_QL, fitness_function_ql)
+        }
+        GameType::DynaQ => {
+            let qls = (0..NUM_QLS).map(|_| DynaQ::new(8, 4, DYNA_PLANNING_STEPS)).collect();
+            iterate_qls(qls, NUM_GAMES_QL, fitness_function_ql)
+        }
+        GameType::DeepQNetwork => {
+            let qls = (0..NUM_QLS).map(|_| Dqn::new(&[[8, 16], [16, 4]])).collect();
+            iterate_qls(qls, NUM_GAMES_QL, fitness_function_ql)
+        }
+        GameType::ReplayNN => replay_nn(&std::env::args().nth(2).unwrap_or_else(|| NN_SAVE_PATH.to_string())),
+        GameType::ReplayQL => replay_ql(&std::env::args().nth(2).unwrap_or_else(|| QL_SAVE_PATH.to_string())),
+        GameType::Distill => distill_ql(
+            NUM_GAMES_QL,
+            fitness_function_ql,
+            get_optimizer(std::env::args().nth(2).as_deref()),
+            get_distill_head(std::env::args().nth(3).as_deref()),
+        ),
+        GameType::Neat => iterate_neat(NEAT_NUM_GENERATIONS, NUM_GAMES_NN, fitness_function_nn),
+        GameType::EvolutionStrategy => {
+            let args: Vec<String> = std::env::args().skip(2).collect();
+            if let Some(config) = load_ga_config(get_flag(&args, "--config")) {
+                let template = NN::from_config(&config);
+                let es = AntitheticEs::new(template.get_genome());
+                iterate_es(es, template, ES_NUM_GENERATIONS, NUM_GAMES_NN, fitness_function_nn)
+            }
+        }
+        GameType::CmaEs => {
+            let args: Vec<String> = std::env::args().skip(2).collect();
+            if let Some(config) = load_ga_config(get_flag(&args, "--config")) {
+                let template = NN::from_config(&config);
+                let es = CmaEs::new(template.get_genome(), CMA_SIGMA);
+                iterate_es(es, template, ES_NUM_GENERATIONS, NUM_GAMES_NN, fitness_function_nn)
+            }
         }
-        GameType::QLearning => iterate_qls(NUM_QLS, NUM_GAMES_QL, fitness_function_ql),
+        GameType::Novelty => {
+            let args: Vec<String> = std::env::args().skip(2).collect();
+            iterate_novelty(get_flag(&args, "--config"), fitness_function_nn)
+        }
+        GameType::MapElites => {
+            let args: Vec<String> = std::env::args().skip(2).collect();
+            iterate_map_elites(get_flag(&args, "--config"), fitness_function_nn)
+        }
+        GameType::Scripted => run_scripted(std::env::args().nth(2).as_deref()),
+        GameType::Baseline => run_baselines(),
+        GameType::Evaluate => {
+            let args: Vec<String> = std::env::args().skip(2).collect();
+            run_evaluation(&args)
+        }
+        GameType::Benchmark => run_benchmark(fitness_function_nn),
     }
 }
 
@@ -84,59 +257,670 @@ fn fitness_function_ql(_delta_t: i64, dist_before: i64, dist_after: i64, snake_e
 // ----------------------------------Neural Network--------------------------------------
 // --------------------------------------------------------------------------------------
 
+// Resumes from the checkpoint at the given path, otherwise starts a new population from the config file or defaults
 fn iterate_population(
-    num_nn: u32,
-    num_games: u32,
-    num_generations: u32,
+    config_path: Option<String>,
+    checkpoint: Option<String>,
     fitness_function: fn(i64, i64, i64, i64, i64) -> f64,
 ) {
-    let mut pop = Population::new_defined(num_nn, &[[8, 8], [8, 4]]);
-    for i in 0..num_generations - 1 {
-        pop.fitness = population_play_parallel(&mut pop.nn, num_games, fitness_function);
-        let sorted_index = pop.get_sorted_index();
-        println!("Gen: {}; Fitness: {}", i, pop.fitness[sorted_index[0]]);
-        pop = pop.create_next_generation();
+    // A checkpoint carries its own config, so a second one would be silently ignored
+    if let (Some(path), Some(_)) = (&checkpoint, &config_path) {
+        println!(
+            "Could not resume from {}: --resume and --config cannot be combined",
+            path
+        );
+        return;
+    }
+    let (mut pop, mut hall_of_fame, mut generation, config) = match &checkpoint {
+        Some(path) => match load_checkpoint(Path::new(path)) {
+            Ok((mut pop, hall_of_fame, generation, config)) => {
+                println!("Resuming from generation {} of {}", generation, path);
+                (
+                    pop.create_next_generation(&config, generation + 1),
+                    hall_of_fame,
+                    generation + 1,
+                    config,
+                )
+            }
+            Err(e) => {
+                println!("Could not load {}: {}", path, e);
+                return;
+            }
+        },
+        None => {
+            let config = match load_ga_config(config_path) {
+                Some(config) => config,
+                None => return,
+            };
+            let hall_of_fame = HallOfFame::new(config.hall_of_fame_size);
+            (Population::from_config(&config), hall_of_fame, 0, config)
+        }
+    };
+
+    if config.islands.is_some() {
+        // The islands are not checkpointed, so resuming would silently start them again from scratch
+        if let Some(path) = checkpoint {
+            println!("Could not resume from {}: island runs cannot be resumed", path);
+            return;
+        }
+        iterate_islands(&config, fitness_function);
+        return;
+    }
+
+    // Ctrl-C is only checked once the current generation has finished playing
+    let interrupted = set_interrupt_handler();
+
+    while generation + 1 < config.num_generations {
+        let fitness = population_play_parallel(&mut pop.nn, config.num_games, fitness_function, config.precision);
+        pop.fitness = match fitness {
+            Ok(fitness) => fitness,
+            Err(e) => {
+                println!("Gen {} failed: {}", generation, e);
+                return;
+            }
+        };
+        hall_of_fame.update(&pop, generation);
+        print_generation(generation, &pop, &config, &hall_of_fame);
+
+        let stop = interrupted.load(Ordering::SeqCst);
+        if stop || (config.checkpoint_every > 0 && (generation + 1) % config.checkpoint_every == 0) {
+            let path = Path::new(&config.checkpoint_path);
+            match save_checkpoint(&pop, &hall_of_fame, generation, &config, INPUT_ENCODING, path) {
+                Ok(()) => println!("Saved checkpoint to {}", config.checkpoint_path),
+                Err(e) => println!("Could not save checkpoint to {}: {}", config.checkpoint_path, e),
+            }
+        }
+        if stop {
+            return;
+        }
+        pop = pop.create_next_generation(&config, generation + 1);
+        generation += 1;
     }
 
-    pop.fitness = population_play_parallel(&mut pop.nn, num_games, fitness_function);
+    let fitness = population_play_parallel(&mut pop.nn, config.num_games, fitness_function, config.precision);
+    pop.fitness = match fitness {
+        Ok(fitness) => fitness,
+        Err(e) => {
+            println!("Gen {} failed: {}", generation, e);
+            return;
+        }
+    };
     let sorted_index = pop.get_sorted_index();
+    hall_of_fame.update(&pop, generation);
     println!("Final Fitness: {}", pop.fitness[sorted_index[0]]);
-    let mut render = Render::new();
-    render.run_brain(&mut pop.nn[sorted_index[0]]);
+    // The best-ever individual is the champion, even if the final generation regressed
+    let mut champion = match hall_of_fame.best() {
+        Some(entry) => entry.nn.clone(),
+        None => pop.nn[sorted_index[0]].clone(),
+    };
+    match save_nn(&champion, INPUT_ENCODING, Path::new(NN_SAVE_PATH)) {
+        Ok(()) => println!("Saved champion to {}", NN_SAVE_PATH),
+        Err(e) => println!("Could not save champion to {}: {}", NN_SAVE_PATH, e),
+    }
+    show_brain(&mut champion);
+}
+
+// Ctrl-C sets the returned flag instead of killing the process, so that the run can save its progress
+fn set_interrupt_handler() -> Arc<AtomicBool> {
+    let interrupted = Arc::new(AtomicBool::new(false));
+    let handler_flag = interrupted.clone();
+    if let Err(e) = ctrlc::set_handler(move || handler_flag.store(true, Ordering::SeqCst)) {
+        println!("Could not set the Ctrl-C handler: {}", e);
+    }
+    interrupted
+}
+
+// Island runs are not checkpointed, so Ctrl-C stops them early and saves the best network so far
+fn iterate_islands(config: &GaConfig, fitness_function: fn(i64, i64, i64, i64, i64) -> f64) {
+    let island_config = config.islands.unwrap_or_default();
+    if config.checkpoint_every > 0 {
+        println!("Island runs are not checkpointed, so checkpoint_every is ignored");
+    }
+    let interrupted = set_interrupt_handler();
+    let mut archipelago = Archipelago::new(config, island_config);
+    let evaluate = |nn: &mut NN| play_nn(nn, config.num_games, fitness_function, config.precision);
+    if let Err(e) = archipelago.evaluate(&evaluate) {
+        println!("Gen 0 failed: {}", e);
+        return;
+    }
+    for generation in 0..config.num_generations {
+        let (island, i) = archipelago.get_best();
+        let island_best: Vec<String> = archipelago
+            .get_island_best()
+            .iter()
+            .map(|f| format!("{:.3}", f))
+            .collect();
+        println!(
+            "Gen: {}; Island Best: [{}]; Global Best: {} (island {})",
+            generation,
+            island_best.join(", "),
+            archipelago.islands[island].fitness[i],
+            island
+        );
+        if generation + 1 == config.num_generations || interrupted.load(Ordering::SeqCst) {
+            break;
+        }
+        if island_config.migration_interval > 0 && (generation + 1) % island_config.migration_interval == 0 {
+            archipelago.migrate();
+        }
+        archipelago.create_next_generation(config, generation + 1);
+        if let Err(e) = archipelago.evaluate(&evaluate) {
+            println!("Gen {} failed: {}", generation + 1, e);
+            return;
+        }
+    }
+
+    let (island, i) = archipelago.get_best();
+    let mut champion = archipelago.islands[island].nn[i].clone();
+    match save_nn(&champion, INPUT_ENCODING, Path::new(NN_SAVE_PATH)) {
+        Ok(()) => println!("Saved champion to {}", NN_SAVE_PATH),
+        Err(e) => println!("Could not save champion to {}: {}", NN_SAVE_PATH, e),
+    }
+    show_brain(&mut champion);
+}
+
+fn print_generation(generation: u32, pop: &Population, config: &GaConfig, hall_of_fame: &HallOfFame) {
+    let mut line = format!(
+        "Gen: {}; Fitness: {}; Diversity: {:.4}",
+        generation,
+        pop.fitness[pop.get_sorted_index()[0]],
+        pop.get_diversity()
+    );
+    if let Some(speciation) = config.speciation {
+        line += &format!("; Species: {}", pop.get_species(speciation.threshold).len());
+    }
+    if let Some(best) = hall_of_fame.best() {
+        line += &format!(
+            "; Hall of Fame: {} (gen {}), size {}",
+            best.fitness,
+            best.generation,
+            hall_of_fame.members.len()
+        );
+    }
+    println!("{}", line);
+}
+
+fn iterate_neat(num_generations: u32, num_games: u32, fitness_function: fn(i64, i64, i64, i64, i64) -> f64) {
+    let mut pop = NeatPopulation::new(NEAT_NUM_INDIVIDUALS, 8, 4);
+    for generation in 0..num_generations {
+        pop.fitness = match genome_play_parallel(&mut pop.genomes, num_games, fitness_function) {
+            Ok(fitness) => fitness,
+            Err(e) => {
+                println!("Gen {} failed: {}", generation, e);
+                return;
+            }
+        };
+        let best = get_index_max_fitness(&pop.fitness);
+        println!(
+            "Gen: {}; Fitness: {}; Species: {}; Hidden Nodes: {}; Connections: {}",
+            generation,
+            pop.fitness[best],
+            pop.species.len(),
+            pop.genomes[best].get_num_hidden(),
+            pop.genomes[best].get_num_enabled()
+        );
+        if generation + 1 < num_generations {
+            pop.create_next_generation();
+        }
+    }
+    let best = get_index_max_fitness(&pop.fitness);
+    show_brain(&mut pop.genomes[best]);
+}
+
+// Evolves the weights of template, reporting the games played so far to compare sample efficiency
+fn iterate_es<T: EvolutionStrategy>(
+    mut es: T,
+    template: NN,
+    num_generations: u32,
+    num_games: u32,
+    fitness_function: fn(i64, i64, i64, i64, i64) -> f64,
+) {
+    let mut games: u64 = 0;
+    for generation in 0..num_generations {
+        let candidates = es.ask();
+        let nns: Result<Vec<NN>> = candidates
+            .iter()
+            .map(|genome| {
+                let mut nn = template.clone();
+                nn.set_genome(genome)?;
+                Ok(nn)
+            })
+            .collect();
+        let mut nns = match nns {
+            Ok(nns) => nns,
+            Err(e) => {
+                println!("Gen {} failed: {}", generation, e);
+                return;
+            }
+        };
+        let fitness = match population_play_parallel(&mut nns, num_games, fitness_function, Precision::F64) {
+            Ok(fitness) => fitness,
+            Err(e) => {
+                println!("Gen {} failed: {}", generation, e);
+                return;
+            }
+        };
+        games += (nns.len() as u64) * (num_games as u64);
+        println!(
+            "Gen: {}; Mean Fitness: {}; Max Fitness: {}; Games: {}",
+            generation,
+            fitness.iter().sum::<f64>() / fitness.len() as f64,
+            fitness[get_index_max_fitness(&fitness)],
+            games
+        );
+        es.tell(&candidates, &fitness);
+    }
+
+    let mut champion = template;
+    if let Err(e) = champion.set_genome(es.get_mean()) {
+        println!("Could not build the champion: {}", e);
+        return;
+    }
+    match save_nn(&champion, INPUT_ENCODING, Path::new(NN_SAVE_PATH)) {
+        Ok(()) => println!("Saved champion to {}", NN_SAVE_PATH),
+        Err(e) => println!("Could not save champion to {}: {}", NN_SAVE_PATH, e),
+    }
+    show_brain(&mut champion);
+}
+
+fn load_ga_config(config_path: Option<String>) -> Option<GaConfig> {
+    match config_path {
+        Some(path) => match GaConfig::load(Path::new(&path)) {
+            Ok(config) => Some(config),
+            Err(e) => {
+                println!("Could not load {}: {}", path, e);
+                None
+            }
+        },
+        None => Some(GaConfig::new()),
+    }
+}
+
+// Selects on the novelty of each behaviour instead of fitness, keeping the fittest network seen as the champion
+fn iterate_novelty(config_path: Option<String>, fitness_function: fn(i64, i64, i64, i64, i64) -> f64) {
+    let config = match load_ga_config(config_path) {
+        Some(config) => config,
+        None => return,
+    };
+    let mut pop = Population::from_config(&config);
+    let mut archive = NoveltyArchive::new(NOVELTY_K_NEAREST);
+    let mut champion: Option<(NN, f64)> = None;
+    for generation in 0..config.num_generations {
+        let results = play_parallel(&mut pop.nn, |nn| {
+            play_brain_recorded(nn, config.num_games, fitness_function)
+        });
+        let (fitness, behaviours): (Vec<Option<f64>>, Vec<Option<Behaviour>>) = match results {
+            Ok(results) => results
+                .into_iter()
+                .map(|r| (r.as_ref().map(|(f, _)| *f), r.map(|(_, b)| b)))
+                .unzip(),
+            Err(e) => {
+                println!("Gen {} failed: {}", generation, e);
+                return;
+            }
+        };
+        // Networks that failed have no behaviour, so they get the worst novelty as well as the worst fitness
+        let played: Vec<Behaviour> = behaviours.iter().flatten().cloned().collect();
+        let played_novelty = archive.get_novelty(&played);
+        let mut next_novelty = played_novelty.iter();
+        let novelty: Vec<Option<f64>> = behaviours
+            .iter()
+            .map(|b| b.as_ref().and_then(|_| next_novelty.next().cloned()))
+            .collect();
+        let novelty = get_fitness_or_worst(&novelty);
+        let fitness = get_fitness_or_worst(&fitness);
+        let best = get_index_max_fitness(&fitness);
+        if champion.as_ref().is_none_or(|(_, f)| fitness[best] > *f) {
+            champion = Some((pop.nn[best].clone(), fitness[best]));
+        }
+        archive.add_most_novel(&played, &played_novelty, NOVELTY_ARCHIVE_ADD);
+        println!(
+            "Gen: {}; Novelty: {:.4}; Fitness: {}; Best Fitness: {}; Archive: {}",
+            generation,
+            novelty[get_index_max_fitness(&novelty)],
+            fitness[best],
+            champion.as_ref().map_or(0.0, |(_, f)| *f),
+            archive.behaviours.len()
+        );
+        if generation + 1 < config.num_generations {
+            pop.fitness = novelty;
+            pop = pop.create_next_generation(&config, generation + 1);
+        }
+    }
+
+    if let Some((mut champion, _)) = champion {
+        match save_nn(&champion, INPUT_ENCODING, Path::new(NN_SAVE_PATH)) {
+            Ok(()) => println!("Saved champion to {}", NN_SAVE_PATH),
+            Err(e) => println!("Could not save champion to {}: {}", NN_SAVE_PATH, e),
+        }
+        show_brain(&mut champion);
+    }
+}
+
+// Fills a grid of behaviours with the fittest network of each cell, breeding every batch from random elites
+fn iterate_map_elites(config_path: Option<String>, fitness_function: fn(i64, i64, i64, i64, i64) -> f64) {
+    let config = match load_ga_config(config_path) {
+        Some(config) => config,
+        None => return,
+    };
+    let mut rng = match config.seed {
+        Some(seed) => Pcg32::seed_from_u64(seed),
+        None => Pcg32::from_entropy(),
+    };
+    let mut map = MapElites::new(MAP_ELITES_BINS);
+    let mut nns = Population::from_config(&config).nn;
+    for generation in 0..config.num_generations {
+        let results = play_parallel(&mut nns, |nn| {
+            play_brain_recorded(nn, config.num_games, fitness_function)
+        });
+        let results = match results {
+            Ok(results) => results,
+            Err(e) => {
+                println!("Gen {} failed: {}", generation, e);
+                return;
+            }
+        };
+        let mut added = 0;
+        // Networks that failed are left out of the map
+        for (nn, (fitness, behaviour)) in nns.drain(..).zip(results).filter_map(|(nn, r)| r.map(|r| (nn, r))) {
+            if map.add(nn, fitness, behaviour) {
+                added += 1;
+            }
+        }
+        println!(
+            "Gen: {}; Filled Cells: {}/{}; Added: {}; Best Fitness: {}",
+            generation,
+            map.get_elites().len(),
+            map.cells.len(),
+            added,
+            map.get_best().map_or(0.0, |e| e.fitness)
+        );
+        while nns.len() < config.num_individuals as usize {
+            let (p1, p2) = match (map.get_random_elite(&mut rng), map.get_random_elite(&mut rng)) {
+                (Some(p1), Some(p2)) => (p1, p2),
+                _ => break,
+            };
+            let (c1, c2) = p1.nn.get_offspring(&p2.nn, &config, generation + 1, &mut rng);
+            nns.push(c1);
+            nns.push(c2);
+        }
+        nns.truncate(config.num_individuals as usize);
+    }
+
+    if let Err(e) = std::fs::create_dir_all(MAP_ELITES_DIR) {
+        println!("Could not create {}: {}", MAP_ELITES_DIR, e);
+    }
+    for (x, y, elite) in map.get_elites() {
+        let path = format!("{}/cell_{}_{}.json", MAP_ELITES_DIR, x, y);
+        match save_nn(&elite.nn, INPUT_ENCODING, Path::new(&path)) {
+            Ok(()) => println!(
+                "{}: Fitness: {}; Coverage: {:.3}; Turns per Food: {:.2}; Length: {:.2}",
+                path,
+                elite.fitness,
+                elite.behaviour.get_coverage(),
+                elite.behaviour.turns_per_food,
+                elite.behaviour.mean_length
+            ),
+            Err(e) => println!("Could not save elite to {}: {}", path, e),
+        }
+    }
+    if let Some(best) = map.get_best() {
+        let mut best = best.nn.clone();
+        show_brain(&mut best);
+    }
+}
+
+fn genome_play_parallel(
+    genomes: &mut [Genome],
+    num_games: u32,
+    fitness_function: fn(i64, i64, i64, i64, i64) -> f64,
+) -> Result<Vec<f64>> {
+    let fitness = play_parallel(genomes, |g| play_brain(g, num_games, fitness_function))?;
+    Ok(get_fitness_or_worst(&fitness))
+}
+
+fn replay_nn(path: &str) {
+    match load_nn(Path::new(path), INPUT_ENCODING, 8, 4) {
+        Ok(mut nn) => {
+            let mut render = Render::new();
+            render.run_brain(&mut nn);
+        }
+        Err(e) => println!("Could not load {}: {}", path, e),
+    }
 }
 
 fn population_play_parallel(
-    nns: &mut Vec<NN>,
+    nns: &mut [NN],
+    num_games: u32,
+    fitness_function: fn(i64, i64, i64, i64, i64) -> f64,
+    precision: Precision,
+) -> Result<Vec<f64>> {
+    let fitness = play_parallel(nns, |n| play_nn(n, num_games, fitness_function, precision))?;
+    Ok(get_fitness_or_worst(&fitness))
+}
+
+// Feed-forward networks play all their games at once, recurrent ones one game after the other
+fn play_nn(
+    nn: &mut NN,
     num_games: u32,
     fitness_function: fn(i64, i64, i64, i64, i64) -> f64,
-) -> Vec<f64> {
-    let fitness: Vec<f64> = nns
-        .par_iter_mut()
-        .map(|n| play_brain(n, num_games, fitness_function))
+    precision: Precision,
+) -> Result<f64> {
+    match precision {
+        Precision::F32 => play_brain(&mut NN32::from_nn(nn), num_games, fitness_function),
+        Precision::F64 if nn.is_recurrent() => play_brain(nn, num_games, fitness_function),
+        Precision::F64 => play_nn_batched(nn, num_games, fitness_function),
+    }
+}
+
+// Same as play_brain for a feed-forward network, with one batched forward pass per move for every game still running
+fn play_nn_batched(nn: &NN, num_games: u32, fitness_function: fn(i64, i64, i64, i64, i64) -> f64) -> Result<f64> {
+    let num_outputs = nn.layers.last().map_or(0, |l| l.num_neurons as usize);
+    let mut games: Vec<Game> = (0..num_games)
+        .map(|_| {
+            let mut game = Game::new();
+            game.init();
+            game
+        })
         .collect();
-    fitness
+    let mut fitness: f64 = 0f64;
+    let mut inputs: Vec<f64> = Vec::new();
+    loop {
+        let playing: Vec<usize> = (0..games.len()).filter(|i| games[*i].snake.alive).collect();
+        if playing.is_empty() {
+            break;
+        }
+        inputs.clear();
+        for i in playing.iter() {
+            inputs.extend(games[*i].get_nn_inputs());
+        }
+        let outputs = nn.propagate_batch(&inputs, playing.len())?;
+        for (i, output) in playing.iter().zip(outputs.chunks_exact(num_outputs)) {
+            let action = get_output_action(output)?;
+            fitness += games[*i].play_action(action, fitness_function);
+        }
+    }
+    Ok(fitness / num_games as f64)
+}
+
+// Compares the forward passes and the ways of playing a population's games
+fn run_benchmark(fitness_function: fn(i64, i64, i64, i64, i64) -> f64) {
+    let nn = NN::new_defined(&[[8, 8], [8, 4]]);
+    // The inputs repeat every three passes, so three buffers are reused instead of allocating one per pass
+    let inputs: Vec<Vec<f64>> = (0..3)
+        .map(|i| (0..8).map(|j| ((i + j) % 3) as f64 - 1.0).collect())
+        .collect();
+
+    let start = Instant::now();
+    let checksum: f64 = (0..BENCH_NUM_PASSES)
+        .map(|i| nn.propagate(&inputs[i % 3]).unwrap()[0])
+        .sum();
+    print_benchmark("NN::propagate", start, BENCH_NUM_PASSES, "passes", checksum);
+
+    let mut stepped = nn.clone();
+    let start = Instant::now();
+    let checksum: f64 = (0..BENCH_NUM_PASSES)
+        .map(|i| stepped.step(&inputs[i % 3]).unwrap()[0])
+        .sum();
+    print_benchmark("NN::step", start, BENCH_NUM_PASSES, "passes", checksum);
+
+    let mut nn32 = NN32::from_nn(&nn);
+    let start = Instant::now();
+    let checksum: f64 = (0..BENCH_NUM_PASSES)
+        .map(|i| nn32.step(&inputs[i % 3]).unwrap()[0])
+        .sum();
+    print_benchmark("NN32::step", start, BENCH_NUM_PASSES, "passes", checksum);
+
+    // One batch for each of the three offsets a batch can start at
+    let batches: Vec<Vec<f64>> = (0..3)
+        .map(|i| {
+            (i..i + BENCH_BATCH_SIZE)
+                .flat_map(|k| inputs[k % 3].iter().copied())
+                .collect()
+        })
+        .collect();
+    let num_batches = BENCH_NUM_PASSES / BENCH_BATCH_SIZE;
+    let start = Instant::now();
+    let checksum: f64 = (0..num_batches)
+        .map(|b| {
+            let outputs = nn
+                .propagate_batch(&batches[b * BENCH_BATCH_SIZE % 3], BENCH_BATCH_SIZE)
+                .unwrap();
+            outputs.iter().step_by(4).sum::<f64>()
+        })
+        .sum();
+    print_benchmark(
+        "NN::propagate_batch",
+        start,
+        num_batches * BENCH_BATCH_SIZE,
+        "passes",
+        checksum,
+    );
+
+    let nns: Vec<NN> = (0..BENCH_NUM_INDIVIDUALS)
+        .map(|_| NN::new_defined(&[[8, 8], [8, 4]]))
+        .collect();
+    let num_games = BENCH_NUM_INDIVIDUALS * BENCH_NUM_GAMES as usize;
+    let mut population = nns.clone();
+    let start = Instant::now();
+    let fitness = play_parallel(&mut population, |n| play_brain(n, BENCH_NUM_GAMES, fitness_function)).unwrap();
+    let checksum = fitness.iter().flatten().sum();
+    print_benchmark("Games f64 sequential", start, num_games, "games", checksum);
+    for (name, precision) in [
+        ("Games f64 batched", Precision::F64),
+        ("Games f32 sequential", Precision::F32),
+    ]
+    .iter()
+    {
+        let mut population = nns.clone();
+        let start = Instant::now();
+        let fitness = population_play_parallel(&mut population, BENCH_NUM_GAMES, fitness_function, *precision).unwrap();
+        print_benchmark(name, start, num_games, "games", fitness.iter().sum());
+    }
+}
+
+// The checksum keeps the work from being optimised away
+fn print_benchmark(name: &str, start: Instant, count: usize, unit: &str, checksum: f64) {
+    let seconds = start.elapsed().as_secs_f64();
+    println!(
+        "{:<20} {:>10.0} {}/s ({:.3} s, checksum {:.3})",
+        name,
+        count as f64 / seconds,
+        unit,
+        seconds,
+        checksum
+    );
 }
 
 // --------------------------------------------------------------------------------------
 // ----------------------------------Q Learning------------------------------------------
 // --------------------------------------------------------------------------------------
 
-fn iterate_qls(num_qls: u32, num_games: u32, fitness_function: fn(i64, i64, i64, i64, i64) -> f64) {
-    let mut qls: Vec<QLearner> = (0..num_qls).map(|_| QLearner::new(8, 4)).collect();
-    let max_i = ql_play_parallel(&mut qls, num_games, fitness_function);
-    let mut render = Render::new();
-    render.run_brain(&mut qls[max_i]);
+fn iterate_qls<T: Brain + Send>(mut qls: Vec<T>, num_games: u32, fitness_function: fn(i64, i64, i64, i64, i64) -> f64) {
+    let max_i = match train_qls(&mut qls, num_games, fitness_function) {
+        Ok(max_i) => max_i,
+        Err(e) => {
+            println!("Training failed: {}", e);
+            return;
+        }
+    };
+    show_brain(&mut qls[max_i]);
+}
+
+// Resumes from a saved Q-table when a path is given, and saves the best table afterwards
+fn iterate_saved_qls(path: Option<String>, num_games: u32, fitness_function: fn(i64, i64, i64, i64, i64) -> f64) {
+    let mut qls: Vec<QLearner> = Vec::new();
+    for _ in 0..NUM_QLS {
+        match &path {
+            Some(p) => match load_qlearner(Path::new(p), INPUT_ENCODING, 8, 4) {
+                Ok(ql) => qls.push(ql),
+                Err(e) => {
+                    println!("Could not load {}: {}", p, e);
+                    return;
+                }
+            },
+            None => qls.push(QLearner::new(8, 4)),
+        }
+    }
+    let max_i = match train_qls(&mut qls, num_games, fitness_function) {
+        Ok(max_i) => max_i,
+        Err(e) => {
+            println!("Training failed: {}", e);
+            return;
+        }
+    };
+    match save_qlearner(&qls[max_i], INPUT_ENCODING, Path::new(QL_SAVE_PATH)) {
+        Ok(()) => println!(
+            "Saved Q-table after {} episodes to {}",
+            qls[max_i].episodes, QL_SAVE_PATH
+        ),
+        Err(e) => println!("Could not save Q-table to {}: {}", QL_SAVE_PATH, e),
+    }
+    show_brain(&mut qls[max_i]);
 }
 
-fn ql_play_parallel(
-    qls: &mut Vec<QLearner>,
+fn train_qls<T: Brain + Send>(
+    qls: &mut [T],
     num_games: u32,
     fitness_function: fn(i64, i64, i64, i64, i64) -> f64,
-) -> usize {
-    let fitness: Vec<f64> = qls
-        .par_iter_mut()
-        .map(|ql| play_brain(ql, num_games, fitness_function))
-        .collect();
+) -> Result<usize> {
+    let mut fitness: Vec<f64> = vec![0f64; qls.len()];
+    let mut games_played = 0;
+    while games_played < num_games {
+        let block = std::cmp::min(QL_REPORT_GAMES, num_games - games_played);
+        fitness = ql_play_parallel(qls, block, fitness_function)?;
+        games_played += block;
+        let mean = fitness.iter().sum::<f64>() / fitness.len() as f64;
+        let max_i = get_index_max_fitness(&fitness);
+        println!(
+            "Games: {}; Mean Fitness: {}; Max Fitness: {}",
+            games_played, mean, fitness[max_i]
+        );
+    }
+    Ok(get_index_max_fitness(&fitness))
+}
+
+fn replay_ql(path: &str) {
+    match load_qlearner(Path::new(path), INPUT_ENCODING, 8, 4) {
+        Ok(mut ql) => {
+            println!("Loaded Q-table trained for {} episodes", ql.episodes);
+            let mut render = Render::new();
+            render.run_brain(&mut ql);
+        }
+        Err(e) => println!("Could not load {}: {}", path, e),
+    }
+}
+
+fn ql_play_parallel<T: Brain + Send>(
+    qls: &mut [T],
+    num_games: u32,
+    fitness_function: fn(i64, i64, i64, i64, i64) -> f64,
+) -> Result<Vec<f64>> {
+    let fitness = play_parallel(qls, |ql| play_brain(ql, num_games, fitness_function))?;
+    Ok(get_fitness_or_worst(&fitness))
+}
+
+fn get_index_max_fitness(fitness: &[f64]) -> usize {
     fitness
         .iter()
         .enumerate()
@@ -145,19 +929,250 @@ fn ql_play_parallel(
         .unwrap_or(0)
 }
 
+// Trains a NN by supervised learning to imitate the greedy policy of the best Q-table
+fn distill_ql(
+    num_games: u32,
+    fitness_function: fn(i64, i64, i64, i64, i64) -> f64,
+    mut optimizer: Optimizer,
+    (head, loss): (Activation, Loss),
+) {
+    let mut qls: Vec<QLearner> = (0..NUM_QLS).map(|_| QLearner::new(8, 4)).collect();
+    let max_i = match train_qls(&mut qls, num_games, fitness_function) {
+        Ok(max_i) => max_i,
+        Err(e) => {
+            println!("Training failed: {}", e);
+            return;
+        }
+    };
+    let data: Vec<(Vec<f64>, Vec<f64>)> = qls[max_i]
+        .q
+        .iter()
+        .filter(|sa| sa.quality.iter().any(|q| *q != 0.0_f64))
+        .map(|sa| {
+            let mut target = vec![0.0_f64; sa.quality.len()];
+            target[get_index_max_float(&sa.quality).unwrap()] = 1.0_f64;
+            (sa.state.clone(), target)
+        })
+        .collect();
+    if data.is_empty() {
+        println!("The Q-table has not visited any states");
+        return;
+    }
+
+    let mut nn = NN::new_defined_with_activations(&[[8, 16], [16, 4]], &[Activation::Relu, head], &[]);
+    let mut rng = rand::thread_rng();
+    for epoch in (0..DISTILL_EPOCHS).step_by(100) {
+        let epochs = std::cmp::min(100, DISTILL_EPOCHS - epoch);
+        let result = nn.train_supervised(&data, DISTILL_BATCH_SIZE, epochs, loss, &mut optimizer, &mut rng);
+        match result {
+            Ok(epoch_loss) => println!("Epoch: {}; Loss: {}", epoch + epochs, epoch_loss),
+            Err(e) => {
+                println!("Training failed: {}", e);
+                return;
+            }
+        }
+    }
+    show_brain(&mut nn);
+}
+
+fn get_optimizer(name: Option<&str>) -> Optimizer {
+    match name {
+        Some("sgd") => Optimizer::sgd(DISTILL_LEARNING_RATE),
+        Some("momentum") => Optimizer::momentum(DISTILL_LEARNING_RATE, 0.9),
+        _ => Optimizer::adam(DISTILL_LEARNING_RATE),
+    }
+}
+
+// Output activation of the distilled network and the loss that matches it. A softmax head learns one
+// distribution over the actions, while binary cross-entropy scores each action on its own with a sigmoid
+fn get_distill_head(name: Option<&str>) -> (Activation, Loss) {
+    match name {
+        Some("binary") => (Activation::Sigmoid, Loss::CrossEntropy),
+        _ => (Activation::Softmax, Loss::CategoricalCrossEntropy),
+    }
+}
+
+fn get_trace_type() -> TraceType {
+    if REPLACING_TRACES {
+        TraceType::Replacing
+    } else {
+        TraceType::Accumulating
+    }
+}
+
 // --------------------------------------------------------------------------------------
 // ----------------------------------Generic Brain---------------------------------------
 // --------------------------------------------------------------------------------------
 
+fn play_brain<T: Brain>(
+    brain: &mut T,
+    num_games: u32,
+    fitness_function: fn(i64, i64, i64, i64, i64) -> f64,
+) -> Result<f64> {
+    let mut game = Game::new();
+    let mut fitness: f64 = 0f64;
+    brain.set_training(true);
+    for _ in 0..num_games {
+        game.init();
+        fitness += game.run_brain(brain, fitness_function)?;
+    }
+    Ok(fitness / num_games as f64)
+}
+
+// Plays every brain on the rayon pool, naming the individual that failed instead of panicking the pool.
+// Brains that failed are logged and left out, and it is only an error when all of them failed
+fn play_parallel<T: Send, R: Send, F: Fn(&mut T) -> Result<R> + Sync>(
+    brains: &mut [T],
+    play: F,
+) -> Result<Vec<Option<R>>> {
+    log_failures(
+        brains
+            .par_iter_mut()
+            .enumerate()
+            .map(|(i, brain)| play(brain).map_err(|e| Error::individual(i, e)))
+            .collect(),
+    )
+}
 
-fn play_brain<T: Brain>(brain: &mut T, num_games: u32, fitness_function: fn(i64, i64, i64, i64, i64) -> f64) -> f64 {
+// Also describes how the brain played, for novelty search and MAP-Elites
+fn play_brain_recorded<T: Brain>(
+    brain: &mut T,
+    num_games: u32,
+    fitness_function: fn(i64, i64, i64, i64, i64) -> f64,
+) -> Result<(f64, Behaviour)> {
     let mut game = Game::new();
+    let mut recorder = BehaviourRecorder::new();
     let mut fitness: f64 = 0f64;
+    brain.set_training(true);
     for _ in 0..num_games {
         game.init();
-        fitness += game.run_brain(brain, fitness_function);
+        fitness += game.run_brain_observed(brain, fitness_function, |g| recorder.record_move(g))?;
+        recorder.finish_game(&game);
+    }
+    Ok((fitness / num_games as f64, recorder.get_behaviour()))
+}
+
+// Plays a frozen brain, loaded or scripted, on seeded games and reports how they ended
+fn run_evaluation(args: &[String]) {
+    let num_games = get_flag(args, "--games")
+        .and_then(|g| g.parse().ok())
+        .unwrap_or(EVAL_NUM_GAMES);
+    let seed = get_flag(args, "--seed")
+        .and_then(|s| s.parse().ok())
+        .unwrap_or(EVAL_SEED);
+    let path = args
+        .get(1)
+        .filter(|a| !a.starts_with("--"))
+        .map(|p| Path::new(p.as_str()));
+    let outcomes = match args.first().map(|a| a.as_str()) {
+        Some("nn") => load_nn(path.unwrap_or_else(|| Path::new(NN_SAVE_PATH)), INPUT_ENCODING, 8, 4)
+            .and_then(|mut nn| play_seeded(&mut nn, num_games, seed)),
+        Some("ql") => load_qlearner(path.unwrap_or_else(|| Path::new(QL_SAVE_PATH)), INPUT_ENCODING, 8, 4)
+            .and_then(|mut ql| play_seeded(&mut ql, num_games, seed)),
+        // The best network a Genetic Algorithm checkpoint has seen
+        Some("checkpoint") => load_checkpoint(path.unwrap_or_else(|| Path::new(GA_CHECKPOINT_PATH))).and_then(
+            |(_, hall_of_fame, _, _)| match hall_of_fame.best() {
+                Some(best) => play_seeded(&mut best.nn.clone(), num_games, seed),
+                None => Err(Error::Parse("the checkpoint has no hall of fame".to_string())),
+            },
+        ),
+        Some("greedy") => play_seeded(&mut GreedyBrain, num_games, seed),
+        Some("pathfinder") => play_seeded(&mut PathfinderBrain, num_games, seed),
+        Some("hamiltonian") => HamiltonianBrain::new().and_then(|mut b| play_seeded(&mut b, num_games, seed)),
+        Some("random") => {
+            let mut random = RandomBrain::new();
+            random.rng = Pcg32::seed_from_u64(seed);
+            play_seeded(&mut random, num_games, seed)
+        }
+        Some("saferandom") => {
+            let mut safe = SafeRandomBrain::new();
+            safe.rng = Pcg32::seed_from_u64(seed);
+            play_seeded(&mut safe, num_games, seed)
+        }
+        _ => {
+            println!("Usage: eval nn|ql|checkpoint [path] [--games n] [--seed s]");
+            println!("       eval greedy|pathfinder|hamiltonian|random|saferandom [--games n] [--seed s]");
+            return;
+        }
+    };
+    match outcomes {
+        Ok(outcomes) => println!("{}", Report::new(&outcomes)),
+        Err(e) => println!("Could not evaluate: {}", e),
+    }
+}
+
+// Compares a trained brain with random moves on the same seeded games, then shows it playing
+fn show_brain<T: Brain>(brain: &mut T) {
+    let mut random = RandomBrain::new();
+    random.rng = Pcg32::seed_from_u64(EVAL_SEED);
+    match (
+        play_seeded(brain, EVAL_NUM_GAMES, EVAL_SEED),
+        play_seeded(&mut random, EVAL_NUM_GAMES, EVAL_SEED),
+    ) {
+        (Ok(outcomes), Ok(chance)) => println!(
+            "Mean score over {} seeded games: {:.2}, against {:.2} for random moves",
+            EVAL_NUM_GAMES,
+            Report::new(&outcomes).score.mean,
+            Report::new(&chance).score.mean
+        ),
+        (Err(e), _) | (_, Err(e)) => println!("Could not evaluate the brain: {}", e),
+    }
+    let mut render = Render::new();
+    render.run_brain(brain);
+}
+
+// Value following a flag such as "--config path"
+fn get_flag(args: &[String], flag: &str) -> Option<String> {
+    args.iter()
+        .position(|a| a == flag)
+        .and_then(|i| args.get(i + 1))
+        .cloned()
+}
+
+// --------------------------------------------------------------------------------------
+// --------------------------------Scripted Brains---------------------------------------
+// --------------------------------------------------------------------------------------
+
+fn run_scripted(name: Option<&str>) {
+    match name {
+        Some("greedy") => play_scripted(GreedyBrain),
+        Some("hamiltonian") => match HamiltonianBrain::new() {
+            Ok(brain) => play_scripted(brain),
+            Err(e) => println!("Could not build the Hamiltonian cycle: {}", e),
+        },
+        _ => play_scripted(PathfinderBrain),
+    }
+}
+
+// Reports how a scripted brain does over many seeded games, then shows it playing
+fn play_scripted<T: Brain>(mut brain: T) {
+    match play_seeded(&mut brain, EVAL_NUM_GAMES, EVAL_SEED) {
+        Ok(outcomes) => println!("{}", Report::new(&outcomes)),
+        Err(e) => {
+            println!("The brain could not play: {}", e);
+            return;
+        }
+    }
+    let mut render = Render::new();
+    render.run_brain(&mut brain);
+}
+
+// What chance achieves, for training results to be compared against
+fn run_baselines() {
+    let mut random = RandomBrain::new();
+    random.rng = Pcg32::seed_from_u64(EVAL_SEED);
+    let mut safe = SafeRandomBrain::new();
+    safe.rng = Pcg32::seed_from_u64(EVAL_SEED);
+    match (
+        play_seeded(&mut random, EVAL_NUM_GAMES, EVAL_SEED),
+        play_seeded(&mut safe, EVAL_NUM_GAMES, EVAL_SEED),
+    ) {
+        (Ok(random), Ok(safe)) => {
+            println!("Random moves\n{}", Report::new(&random));
+            println!("Random moves that do not die at once\n{}", Report::new(&safe));
+        }
+        (Err(e), _) | (_, Err(e)) => println!("The baselines could not play: {}", e),
     }
-    fitness / num_games as f64
 }
 
 // --------------------------------------------------------------------------------------
@@ -0,0 +1,125 @@
+use std::collections::HashMap;
+
+use crate::constants::*;
+use crate::error::Result;
+use crate::game::{Brain, EpisodeOutcome, Transition};
+use crate::qlearn::QLearner;
+
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum VisitType {
+    FirstVisit,
+    EveryVisit,
+}
+
+pub struct MonteCarloLearner {
+    pub ql: QLearner,
+    pub visit_type: VisitType,
+    pub counts: Vec<Vec<u32>>,
+    episode: Vec<(usize, usize, f64)>,
+}
+
+impl MonteCarloLearner {
+    pub fn new(len_states: usize, num_actions: usize, visit_type: VisitType) -> MonteCarloLearner {
+        let mut ql = QLearner::new(len_states, num_actions);
+        // Without bootstrapping the only source of exploration is the policy itself
+        ql.epsilon = MC_EPSILON_GREEDY;
+        MonteCarloLearner {
+            counts: vec![vec![0; num_actions]; ql.q.len()],
+            ql,
+            visit_type,
+            episode: Vec::new(),
+        }
+    }
+
+    fn get_state_index(&mut self, state: &[f64]) -> usize {
+        let index = self.ql.get_state_index(state);
+        while self.counts.len() < self.ql.q.len() {
+            self.counts.push(vec![0; self.ql.num_actions]);
+        }
+        index
+    }
+}
+
+impl Brain for MonteCarloLearner {
+    fn get_action(&mut self, state: &[f64]) -> Result<usize> {
+        let index = self.get_state_index(state);
+        Ok(self.ql.get_epsilon_greedy_action(index))
+    }
+
+    fn train(&mut self, transition: &Transition) -> Option<bool> {
+        if transition.action >= self.ql.num_actions {
+            return None;
+        }
+        if !self.ql.training {
+            return Some(false);
+        }
+        let ii = self.get_state_index(transition.state);
+        self.episode.push((ii, transition.action, transition.reward));
+        Some(true)
+    }
+
+    fn on_episode_end(&mut self, outcome: &EpisodeOutcome) {
+        let mut first_visit: HashMap<(usize, usize), usize> = HashMap::new();
+        for (t, (si, action, _)) in self.episode.iter().enumerate() {
+            first_visit.entry((*si, *action)).or_insert(t);
+        }
+
+        let mut ret = 0.0_f64;
+        for (t, (si, action, reward)) in self.episode.iter().enumerate().rev() {
+            ret = self.ql.discount_factor * ret + reward;
+            if self.visit_type == VisitType::FirstVisit && first_visit[&(*si, *action)] != t {
+                continue;
+            }
+            // Incremental sample average of the returns
+            self.counts[*si][*action] += 1;
+            let q = &mut self.ql.q[*si].quality[*action];
+            *q += (ret - *q) / self.counts[*si][*action] as f64;
+        }
+        self.episode.clear();
+        self.ql.on_episode_end(outcome);
+    }
+
+    fn set_training(&mut self, training: bool) {
+        self.ql.set_training(training);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::Game;
+
+    #[test]
+    fn test_monte_carlo_learner_new() {
+        let mc = MonteCarloLearner::new(8, 4, VisitType::FirstVisit);
+        assert_eq!(mc.ql.q.len(), 2_usize.pow(8));
+        assert_eq!(mc.counts.len(), mc.ql.q.len());
+    }
+
+    #[test]
+    fn test_monte_carlo_learner_visits() {
+        let s0 = vec![0.0_f64, 0.0_f64];
+        let s1 = vec![0.0_f64, 1.0_f64];
+        for visit_type in [VisitType::FirstVisit, VisitType::EveryVisit].iter() {
+            let mut mc = MonteCarloLearner::new(2, 4, *visit_type);
+            mc.train(&Transition::new(&s0, 0, 1.0_f64, &s1, false));
+            mc.train(&Transition::new(&s1, 0, 0.0_f64, &s0, false));
+            mc.train(&Transition::new(&s0, 0, 1.0_f64, &s1, true));
+            // Nothing is learnt before the episode ends
+            assert_eq!(mc.ql.q[0].quality[0], 0.0_f64);
+            mc.on_episode_end(&Game::new().get_outcome());
+            let g_first = 1.0_f64 + mc.ql.discount_factor.powi(2);
+            match visit_type {
+                VisitType::FirstVisit => {
+                    assert_eq!(mc.counts[0][0], 1);
+                    assert!((mc.ql.q[0].quality[0] - g_first).abs() < 1e-12);
+                }
+                VisitType::EveryVisit => {
+                    assert_eq!(mc.counts[0][0], 2);
+                    assert!((mc.ql.q[0].quality[0] - (g_first + 1.0_f64) / 2.0_f64).abs() < 1e-12);
+                }
+            }
+            assert!(mc.episode.is_empty());
+        }
+    }
+}
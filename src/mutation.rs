@@ -0,0 +1,190 @@
+extern crate rand;
+
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::constants::*;
+use crate::gen_alg::get_normal_with;
+
+#[derive(Debug, PartialEq, Copy, Clone, Serialize, Deserialize)]
+pub enum MutationMethod {
+    // Replaces a gene with a fresh standard normal sample
+    Replace,
+    // Adds Gaussian noise with a fixed step size
+    Gaussian { sigma: f64 },
+    // Every individual carries its own step size, which is itself mutated before the genes
+    SelfAdaptive { initial_sigma: f64, tau: f64 },
+}
+
+#[derive(Debug, PartialEq, Copy, Clone, Serialize, Deserialize)]
+pub enum MutationSchedule {
+    Constant,
+    // The strength is multiplied by decay every generation, down to min_factor
+    Exponential { decay: f64, min_factor: f64 },
+    // The strength goes linearly from 1 in the first generation to final_factor in the last
+    Linear { final_factor: f64 },
+}
+
+impl MutationSchedule {
+    pub fn get_factor(&self, generation: u32, num_generations: u32) -> f64 {
+        match *self {
+            MutationSchedule::Constant => 1.0_f64,
+            MutationSchedule::Exponential { decay, min_factor } => decay.powi(generation as i32).max(min_factor),
+            MutationSchedule::Linear { final_factor } => {
+                let progress = if num_generations > 1 {
+                    (generation as f64 / (num_generations - 1) as f64).min(1.0_f64)
+                } else {
+                    1.0_f64
+                };
+                1.0_f64 + (final_factor - 1.0_f64) * progress
+            }
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Copy, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MutationConfig {
+    pub method: MutationMethod,
+    // Chance of mutating each weight and bias
+    pub probability: f64,
+    pub schedule: MutationSchedule,
+}
+
+impl MutationConfig {
+    pub fn new() -> MutationConfig {
+        MutationConfig {
+            method: MutationMethod::Replace,
+            probability: MUTATION_PROBABILITY,
+            schedule: MutationSchedule::Constant,
+        }
+    }
+
+    pub fn get_initial_sigma(&self) -> f64 {
+        match self.method {
+            MutationMethod::Replace => MUTATION_SIGMA,
+            MutationMethod::Gaussian { sigma } => sigma,
+            MutationMethod::SelfAdaptive { initial_sigma, .. } => initial_sigma,
+        }
+    }
+
+    // Log-normal update of a self-adaptive step size, so children inherit step sizes that worked
+    pub fn adapt_sigma(&self, sigma: f64, rng: &mut impl Rng) -> f64 {
+        match self.method {
+            MutationMethod::SelfAdaptive { tau, .. } => sigma * (tau * get_normal_with(rng)).exp(),
+            _ => sigma,
+        }
+    }
+
+    // The schedule scales the probability of replacing genes, or the step size of Gaussian noise
+    pub fn get_mutation(&self, sigma: f64, generation: u32, num_generations: u32) -> Mutation {
+        let factor = self.schedule.get_factor(generation, num_generations);
+        match self.method {
+            MutationMethod::Replace => Mutation {
+                probability: (self.probability * factor).min(1.0_f64),
+                perturbation: Perturbation::Replace,
+            },
+            MutationMethod::Gaussian { sigma } => Mutation {
+                probability: self.probability,
+                perturbation: Perturbation::Add { sigma: sigma * factor },
+            },
+            MutationMethod::SelfAdaptive { .. } => Mutation {
+                probability: self.probability,
+                perturbation: Perturbation::Add { sigma: sigma * factor },
+            },
+        }
+    }
+}
+
+impl Default for MutationConfig {
+    fn default() -> MutationConfig {
+        MutationConfig::new()
+    }
+}
+
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum Perturbation {
+    Replace,
+    Add { sigma: f64 },
+}
+
+// The mutation applied to the genes of one child
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub struct Mutation {
+    pub probability: f64,
+    pub perturbation: Perturbation,
+}
+
+impl Mutation {
+    pub fn should_mutate(&self, rng: &mut impl Rng) -> bool {
+        rng.gen::<f64>() > 1f64 - self.probability
+    }
+
+    pub fn perturb(&self, gene: f64, rng: &mut impl Rng) -> f64 {
+        match self.perturbation {
+            Perturbation::Replace => get_normal_with(rng),
+            Perturbation::Add { sigma } => gene + sigma * get_normal_with(rng),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+    use rand_pcg::Pcg32;
+
+    #[test]
+    fn test_mutation_schedule() {
+        assert_eq!(MutationSchedule::Constant.get_factor(7, 10), 1.0);
+        let exponential = MutationSchedule::Exponential {
+            decay: 0.5,
+            min_factor: 0.2,
+        };
+        assert_eq!(exponential.get_factor(0, 10), 1.0);
+        assert_eq!(exponential.get_factor(1, 10), 0.5);
+        assert_eq!(exponential.get_factor(5, 10), 0.2);
+        let linear = MutationSchedule::Linear { final_factor: 0.1 };
+        assert_eq!(linear.get_factor(0, 11), 1.0);
+        assert!((linear.get_factor(5, 11) - 0.55).abs() < 1e-12);
+        assert!((linear.get_factor(10, 11) - 0.1).abs() < 1e-12);
+        assert!((linear.get_factor(20, 11) - 0.1).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_mutation_perturb() {
+        let mut rng = Pcg32::seed_from_u64(5);
+        let mut config = MutationConfig::new();
+        config.method = MutationMethod::Gaussian { sigma: 0.01 };
+        let mutation = config.get_mutation(config.get_initial_sigma(), 0, 10);
+        assert_eq!(mutation.probability, MUTATION_PROBABILITY);
+        // Small Gaussian steps stay close to the original gene
+        for _ in 0..100 {
+            assert!((mutation.perturb(3.0, &mut rng) - 3.0).abs() < 0.1);
+        }
+        config.method = MutationMethod::Replace;
+        config.schedule = MutationSchedule::Exponential {
+            decay: 0.5,
+            min_factor: 0.0,
+        };
+        let mutation = config.get_mutation(config.get_initial_sigma(), 2, 10);
+        assert_eq!(mutation.probability, MUTATION_PROBABILITY * 0.25);
+        assert_eq!(mutation.perturbation, Perturbation::Replace);
+    }
+
+    #[test]
+    fn test_mutation_adapt_sigma() {
+        let mut rng = Pcg32::seed_from_u64(5);
+        let mut config = MutationConfig::new();
+        assert_eq!(config.adapt_sigma(0.3, &mut rng), 0.3);
+        config.method = MutationMethod::SelfAdaptive {
+            initial_sigma: 0.3,
+            tau: 0.5,
+        };
+        let sigmas: Vec<f64> = (0..100).map(|_| config.adapt_sigma(0.3, &mut rng)).collect();
+        assert!(sigmas.iter().all(|s| *s > 0.0));
+        assert!(sigmas.iter().any(|s| *s > 0.3) && sigmas.iter().any(|s| *s < 0.3));
+        let mutation = config.get_mutation(0.7, 0, 10);
+        assert_eq!(mutation.perturbation, Perturbation::Add { sigma: 0.7 });
+    }
+}
@@ -0,0 +1,607 @@
+extern crate rand;
+
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+use rand_pcg::Pcg32;
+use std::collections::HashMap;
+
+use crate::constants::*;
+use crate::error::{Error, Result};
+use crate::game::Brain;
+use crate::gen_alg::{get_index_max_float, get_normal_with, get_output_action, sigmoid};
+
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum NodeType {
+    Input,
+    Hidden,
+    Output,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct NodeGene {
+    pub id: usize,
+    pub node_type: NodeType,
+    pub bias: f64,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct ConnectionGene {
+    pub innovation: usize,
+    pub from: usize,
+    pub to: usize,
+    pub weight: f64,
+    pub enabled: bool,
+}
+
+// Hands out innovation numbers so that the same structural mutation gets the same number in every genome
+pub struct InnovationTracker {
+    next_innovation: usize,
+    next_node: usize,
+    connections: HashMap<(usize, usize), usize>,
+    splits: HashMap<usize, usize>,
+}
+
+impl InnovationTracker {
+    pub fn new(num_inputs: usize, num_outputs: usize) -> InnovationTracker {
+        InnovationTracker {
+            next_innovation: 0,
+            next_node: num_inputs + num_outputs,
+            connections: HashMap::new(),
+            splits: HashMap::new(),
+        }
+    }
+
+    pub fn get_connection_innovation(&mut self, from: usize, to: usize) -> usize {
+        let next = &mut self.next_innovation;
+        *self.connections.entry((from, to)).or_insert_with(|| {
+            *next += 1;
+            *next - 1
+        })
+    }
+
+    // The hidden node that splits a connection
+    pub fn get_split_node(&mut self, innovation: usize) -> usize {
+        let next = &mut self.next_node;
+        *self.splits.entry(innovation).or_insert_with(|| {
+            *next += 1;
+            *next - 1
+        })
+    }
+
+    pub fn get_new_node(&mut self) -> usize {
+        self.next_node += 1;
+        self.next_node - 1
+    }
+}
+
+// Node ids 0..num_inputs are the inputs and the following num_outputs ids are the outputs
+#[derive(Debug, PartialEq, Clone)]
+pub struct Genome {
+    pub num_inputs: usize,
+    pub num_outputs: usize,
+    pub nodes: Vec<NodeGene>,
+    // Sorted by innovation number
+    pub connections: Vec<ConnectionGene>,
+}
+
+impl Genome {
+    // The minimal topology: every input connected directly to every output
+    pub fn new(num_inputs: usize, num_outputs: usize, tracker: &mut InnovationTracker, rng: &mut impl Rng) -> Genome {
+        let mut nodes = Vec::new();
+        for id in 0..num_inputs {
+            nodes.push(NodeGene {
+                id,
+                node_type: NodeType::Input,
+                bias: 0.0_f64,
+            });
+        }
+        for id in num_inputs..num_inputs + num_outputs {
+            nodes.push(NodeGene {
+                id,
+                node_type: NodeType::Output,
+                bias: get_normal_with(rng),
+            });
+        }
+        let mut genome = Genome {
+            num_inputs,
+            num_outputs,
+            nodes,
+            connections: Vec::new(),
+        };
+        for from in 0..num_inputs {
+            for to in num_inputs..num_inputs + num_outputs {
+                let weight = get_normal_with(rng);
+                genome.add_connection_gene(tracker, from, to, weight);
+            }
+        }
+        genome
+    }
+
+    fn add_connection_gene(&mut self, tracker: &mut InnovationTracker, from: usize, to: usize, weight: f64) {
+        let innovation = tracker.get_connection_innovation(from, to);
+        let index = self
+            .connections
+            .iter()
+            .position(|c| c.innovation > innovation)
+            .unwrap_or(self.connections.len());
+        self.connections.insert(
+            index,
+            ConnectionGene {
+                innovation,
+                from,
+                to,
+                weight,
+                enabled: true,
+            },
+        );
+    }
+
+    fn get_node(&self, id: usize) -> Option<&NodeGene> {
+        self.nodes.iter().find(|n| n.id == id)
+    }
+
+    // Whether to can be reached from from, following every connection gene, enabled or not
+    fn is_reachable(&self, from: usize, to: usize) -> bool {
+        let mut stack = vec![from];
+        let mut visited = vec![from];
+        while let Some(node) = stack.pop() {
+            if node == to {
+                return true;
+            }
+            for c in self.connections.iter().filter(|c| c.from == node) {
+                if !visited.contains(&c.to) {
+                    visited.push(c.to);
+                    stack.push(c.to);
+                }
+            }
+        }
+        false
+    }
+
+    // Node ids in an order where every node comes after all the nodes feeding it
+    fn get_evaluation_order(&self) -> Vec<usize> {
+        let mut in_degree: HashMap<usize, usize> = self.nodes.iter().map(|n| (n.id, 0)).collect();
+        for c in self.connections.iter().filter(|c| c.enabled) {
+            *in_degree.entry(c.to).or_insert(0) += 1;
+        }
+        let mut ready: Vec<usize> = self.nodes.iter().filter(|n| in_degree[&n.id] == 0).map(|n| n.id).collect();
+        let mut order = Vec::new();
+        while let Some(node) = ready.pop() {
+            order.push(node);
+            for c in self.connections.iter().filter(|c| c.enabled && c.from == node) {
+                let degree = in_degree.get_mut(&c.to).unwrap();
+                *degree -= 1;
+                if *degree == 0 {
+                    ready.push(c.to);
+                }
+            }
+        }
+        order
+    }
+
+    pub fn propagate(&self, inputs: &[f64]) -> Result<Vec<f64>> {
+        if inputs.len() != self.num_inputs {
+            return Err(Error::ShapeMismatch {
+                what: "genome inputs",
+                expected: self.num_inputs,
+                found: inputs.len(),
+            });
+        }
+        let missing = |id: usize| Error::InvalidConfig(format!("genome has no node {}", id));
+        let mut values: HashMap<usize, f64> = HashMap::new();
+        for id in self.get_evaluation_order() {
+            let node = self.get_node(id).ok_or_else(|| missing(id))?;
+            let value = if node.node_type == NodeType::Input {
+                inputs[id]
+            } else {
+                let sum: f64 = self
+                    .connections
+                    .iter()
+                    .filter(|c| c.enabled && c.to == id)
+                    .map(|c| c.weight * values.get(&c.from).copied().unwrap_or(0.0_f64))
+                    .sum();
+                sigmoid(sum + node.bias)
+            };
+            values.insert(id, value);
+        }
+        (self.num_inputs..self.num_inputs + self.num_outputs)
+            .map(|id| values.get(&id).copied().ok_or_else(|| missing(id)))
+            .collect()
+    }
+
+    pub fn get_num_hidden(&self) -> usize {
+        self.nodes.iter().filter(|n| n.node_type == NodeType::Hidden).count()
+    }
+
+    pub fn get_num_enabled(&self) -> usize {
+        self.connections.iter().filter(|c| c.enabled).count()
+    }
+
+    fn mutate_weights(&mut self, rng: &mut impl Rng) {
+        for c in self.connections.iter_mut() {
+            c.weight = perturb(c.weight, rng);
+        }
+        for n in self.nodes.iter_mut().filter(|n| n.node_type != NodeType::Input) {
+            n.bias = perturb(n.bias, rng);
+        }
+    }
+
+    // Connects two unconnected nodes, keeping the network feed-forward
+    pub fn mutate_add_connection(&mut self, tracker: &mut InnovationTracker, rng: &mut impl Rng) -> bool {
+        let sources: Vec<usize> = self
+            .nodes
+            .iter()
+            .filter(|n| n.node_type != NodeType::Output)
+            .map(|n| n.id)
+            .collect();
+        let targets: Vec<usize> = self
+            .nodes
+            .iter()
+            .filter(|n| n.node_type != NodeType::Input)
+            .map(|n| n.id)
+            .collect();
+        for _ in 0..NEAT_ADD_CONNECTION_ATTEMPTS {
+            let from = *sources.choose(rng).unwrap();
+            let to = *targets.choose(rng).unwrap();
+            if from == to || self.connections.iter().any(|c| c.from == from && c.to == to) || self.is_reachable(to, from)
+            {
+                continue;
+            }
+            let weight = get_normal_with(rng);
+            self.add_connection_gene(tracker, from, to, weight);
+            return true;
+        }
+        false
+    }
+
+    // Splits an enabled connection in two with a new hidden node, leaving the old connection disabled
+    pub fn mutate_add_node(&mut self, tracker: &mut InnovationTracker, rng: &mut impl Rng) -> bool {
+        let enabled: Vec<usize> = (0..self.connections.len()).filter(|i| self.connections[*i].enabled).collect();
+        let index = match enabled.choose(rng) {
+            Some(i) => *i,
+            None => return false,
+        };
+        self.connections[index].enabled = false;
+        let ConnectionGene {
+            innovation,
+            from,
+            to,
+            weight,
+            ..
+        } = self.connections[index];
+        let mut node = tracker.get_split_node(innovation);
+        // A connection re-enabled by crossover can be split again in the same genome
+        if self.get_node(node).is_some() {
+            node = tracker.get_new_node();
+        }
+        self.nodes.push(NodeGene {
+            id: node,
+            node_type: NodeType::Hidden,
+            bias: 0.0_f64,
+        });
+        self.add_connection_gene(tracker, from, node, 1.0_f64);
+        self.add_connection_gene(tracker, node, to, weight);
+        true
+    }
+
+    pub fn mutate(&mut self, tracker: &mut InnovationTracker, rng: &mut impl Rng) {
+        if rng.gen::<f64>() < NEAT_WEIGHT_MUTATION_PROBABILITY {
+            self.mutate_weights(rng);
+        }
+        if rng.gen::<f64>() < NEAT_ADD_CONNECTION_PROBABILITY {
+            self.mutate_add_connection(tracker, rng);
+        }
+        if rng.gen::<f64>() < NEAT_ADD_NODE_PROBABILITY {
+            self.mutate_add_node(tracker, rng);
+        }
+    }
+
+    // Matching genes come from either parent, disjoint and excess genes from self, which must be the fitter parent
+    pub fn crossover(&self, other: &Genome, rng: &mut impl Rng) -> Genome {
+        let mut child = self.clone();
+        for c in child.connections.iter_mut() {
+            if let Some(o) = other.connections.iter().find(|o| o.innovation == c.innovation) {
+                if rng.gen::<bool>() {
+                    c.weight = o.weight;
+                }
+                c.enabled = if !c.enabled || !o.enabled {
+                    rng.gen::<f64>() >= NEAT_DISABLED_GENE_PROBABILITY
+                } else {
+                    true
+                };
+            }
+        }
+        for n in child.nodes.iter_mut() {
+            if let Some(o) = other.get_node(n.id) {
+                if rng.gen::<bool>() {
+                    n.bias = o.bias;
+                }
+            }
+        }
+        child
+    }
+
+    // NEAT compatibility distance, from the excess and disjoint genes and the mean weight difference of matching genes
+    pub fn get_distance(&self, other: &Genome) -> f64 {
+        let max_self = self.connections.last().map_or(0, |c| c.innovation);
+        let max_other = other.connections.last().map_or(0, |c| c.innovation);
+        let mut excess = 0;
+        let mut disjoint = 0;
+        let mut matching = 0;
+        let mut weight_difference = 0.0_f64;
+        for c in self.connections.iter() {
+            match other.connections.iter().find(|o| o.innovation == c.innovation) {
+                Some(o) => {
+                    matching += 1;
+                    weight_difference += (c.weight - o.weight).abs();
+                }
+                None if c.innovation > max_other => excess += 1,
+                None => disjoint += 1,
+            }
+        }
+        for o in other.connections.iter() {
+            if !self.connections.iter().any(|c| c.innovation == o.innovation) {
+                if o.innovation > max_self {
+                    excess += 1;
+                } else {
+                    disjoint += 1;
+                }
+            }
+        }
+        let n = self.connections.len().max(other.connections.len()).max(1) as f64;
+        let mean_weight_difference = if matching > 0 {
+            weight_difference / matching as f64
+        } else {
+            0.0_f64
+        };
+        NEAT_EXCESS_COEFFICIENT * excess as f64 / n
+            + NEAT_DISJOINT_COEFFICIENT * disjoint as f64 / n
+            + NEAT_WEIGHT_COEFFICIENT * mean_weight_difference
+    }
+}
+
+fn perturb(value: f64, rng: &mut impl Rng) -> f64 {
+    if rng.gen::<f64>() < NEAT_WEIGHT_REPLACE_PROBABILITY {
+        get_normal_with(rng)
+    } else {
+        value + NEAT_WEIGHT_SIGMA * get_normal_with(rng)
+    }
+}
+
+impl Brain for Genome {
+    fn get_action(&mut self, inputs: &[f64]) -> Result<usize> {
+        get_output_action(&self.propagate(inputs)?)
+    }
+}
+
+pub struct Species {
+    pub representative: Genome,
+    pub members: Vec<usize>,
+}
+
+pub struct NeatPopulation {
+    pub genomes: Vec<Genome>,
+    pub fitness: Vec<f64>,
+    pub species: Vec<Species>,
+    pub tracker: InnovationTracker,
+    pub rng: Pcg32,
+}
+
+impl NeatPopulation {
+    pub fn new(num_genomes: usize, num_inputs: usize, num_outputs: usize) -> NeatPopulation {
+        let mut rng = Pcg32::from_rng(rand::thread_rng()).unwrap();
+        let mut tracker = InnovationTracker::new(num_inputs, num_outputs);
+        let genomes = (0..num_genomes)
+            .map(|_| Genome::new(num_inputs, num_outputs, &mut tracker, &mut rng))
+            .collect();
+        let mut pop = NeatPopulation {
+            genomes,
+            fitness: vec![0.0_f64; num_genomes],
+            species: Vec::new(),
+            tracker,
+            rng,
+        };
+        pop.speciate();
+        pop
+    }
+
+    // Every genome joins the first species whose representative is close enough, or founds a new one
+    pub fn speciate(&mut self) {
+        for s in self.species.iter_mut() {
+            s.members.clear();
+        }
+        for (i, genome) in self.genomes.iter().enumerate() {
+            match self
+                .species
+                .iter_mut()
+                .find(|s| genome.get_distance(&s.representative) < NEAT_COMPATIBILITY_THRESHOLD)
+            {
+                Some(s) => s.members.push(i),
+                None => self.species.push(Species {
+                    representative: genome.clone(),
+                    members: vec![i],
+                }),
+            }
+        }
+        self.species.retain(|s| !s.members.is_empty());
+        for s in self.species.iter_mut() {
+            let i = *s.members.choose(&mut self.rng).unwrap();
+            s.representative = self.genomes[i].clone();
+        }
+    }
+
+    // Fitness sharing: every genome's fitness is divided by the size of its species
+    pub fn get_adjusted_fitness(&self) -> Vec<f64> {
+        // Fitness can be negative, so it is shifted to start at zero first
+        let min = self.fitness.iter().cloned().fold(f64::INFINITY, f64::min);
+        let mut adjusted = vec![0.0_f64; self.genomes.len()];
+        for s in self.species.iter() {
+            for &i in s.members.iter() {
+                adjusted[i] = (self.fitness[i] - min) / s.members.len() as f64;
+            }
+        }
+        adjusted
+    }
+
+    // Species get offspring in proportion to their share of the adjusted fitness
+    fn get_offspring_counts(&self) -> Vec<usize> {
+        let adjusted = self.get_adjusted_fitness();
+        let sums: Vec<f64> = self
+            .species
+            .iter()
+            .map(|s| s.members.iter().map(|i| adjusted[*i]).sum())
+            .collect();
+        let total: f64 = sums.iter().sum();
+        let n = self.genomes.len();
+        let mut counts: Vec<usize> = if total > 0.0_f64 {
+            sums.iter().map(|s| (s / total * n as f64).floor() as usize).collect()
+        } else {
+            self.species.iter().map(|s| s.members.len()).collect()
+        };
+        // Rounding leftovers go to the species of the best genome
+        let best = get_index_max_float(&self.fitness.clone()).unwrap_or(0);
+        let best_species = self.species.iter().position(|s| s.members.contains(&best)).unwrap_or(0);
+        let assigned: usize = counts.iter().sum();
+        if assigned < n {
+            counts[best_species] += n - assigned;
+        }
+        counts
+    }
+
+    pub fn create_next_generation(&mut self) {
+        let counts = self.get_offspring_counts();
+        let mut genomes = Vec::new();
+        for (s, count) in self.species.iter().zip(counts) {
+            let mut members = s.members.clone();
+            members.sort_by(|a, b| {
+                self.fitness[*b]
+                    .partial_cmp(&self.fitness[*a])
+                    .unwrap_or(std::cmp::Ordering::Equal)
+            });
+            let mut remaining = count;
+            if remaining > 0 && members.len() >= NEAT_CHAMPION_MIN_SPECIES_SIZE {
+                genomes.push(self.genomes[members[0]].clone());
+                remaining -= 1;
+            }
+            let num_survivors = ((members.len() as f64 * NEAT_SURVIVAL_FRACTION).ceil() as usize).max(1);
+            let survivors = &members[..num_survivors];
+            for _ in 0..remaining {
+                let p1 = *survivors.choose(&mut self.rng).unwrap();
+                let mut child = if self.rng.gen::<f64>() < NEAT_CROSSOVER_PROBABILITY {
+                    let p2 = *survivors.choose(&mut self.rng).unwrap();
+                    let (fitter, other) = if self.fitness[p1] >= self.fitness[p2] { (p1, p2) } else { (p2, p1) };
+                    self.genomes[fitter].crossover(&self.genomes[other], &mut self.rng)
+                } else {
+                    self.genomes[p1].clone()
+                };
+                child.mutate(&mut self.tracker, &mut self.rng);
+                genomes.push(child);
+            }
+        }
+        self.fitness = vec![0.0_f64; genomes.len()];
+        self.genomes = genomes;
+        self.speciate();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn get_rng() -> Pcg32 {
+        Pcg32::seed_from_u64(11)
+    }
+
+    #[test]
+    fn test_innovation_tracker() {
+        let mut tracker = InnovationTracker::new(2, 1);
+        assert_eq!(tracker.get_connection_innovation(0, 2), 0);
+        assert_eq!(tracker.get_connection_innovation(1, 2), 1);
+        assert_eq!(tracker.get_connection_innovation(0, 2), 0);
+        assert_eq!(tracker.get_split_node(1), 3);
+        assert_eq!(tracker.get_split_node(1), 3);
+        assert_eq!(tracker.get_new_node(), 4);
+    }
+
+    #[test]
+    fn test_genome_new() {
+        let mut rng = get_rng();
+        let mut tracker = InnovationTracker::new(3, 2);
+        let genome = Genome::new(3, 2, &mut tracker, &mut rng);
+        assert_eq!(genome.nodes.len(), 5);
+        assert_eq!(genome.connections.len(), 6);
+        assert_eq!(genome.get_num_hidden(), 0);
+        let outputs = genome.propagate(&[1.0, 0.0, 1.0]).unwrap();
+        assert_eq!(outputs.len(), 2);
+        assert!(outputs.iter().all(|y| *y > 0.0 && *y < 1.0));
+        assert!(genome.propagate(&[1.0]).is_err());
+        // The same topology gets the same innovation numbers
+        let other = Genome::new(3, 2, &mut tracker, &mut rng);
+        let innovations: Vec<usize> = other.connections.iter().map(|c| c.innovation).collect();
+        assert_eq!(innovations, (0..6).collect::<Vec<usize>>());
+    }
+
+    #[test]
+    fn test_genome_mutate_add_node() {
+        let mut rng = get_rng();
+        let mut tracker = InnovationTracker::new(2, 1);
+        let mut genome = Genome::new(2, 1, &mut tracker, &mut rng);
+        assert!(genome.mutate_add_node(&mut tracker, &mut rng));
+        assert_eq!(genome.get_num_hidden(), 1);
+        assert_eq!(genome.connections.len(), 4);
+        assert_eq!(genome.get_num_enabled(), 3);
+        assert!(genome.connections.windows(2).all(|w| w[0].innovation < w[1].innovation));
+        assert_eq!(genome.propagate(&[1.0, 1.0]).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_genome_mutate_add_connection_stays_acyclic() {
+        let mut rng = get_rng();
+        let mut tracker = InnovationTracker::new(2, 2);
+        let mut genome = Genome::new(2, 2, &mut tracker, &mut rng);
+        for _ in 0..10 {
+            genome.mutate_add_node(&mut tracker, &mut rng);
+            genome.mutate_add_connection(&mut tracker, &mut rng);
+        }
+        for c in genome.connections.iter() {
+            assert!(!genome.is_reachable(c.to, c.from));
+        }
+        // Every node is evaluated
+        assert_eq!(genome.get_evaluation_order().len(), genome.nodes.len());
+        assert_eq!(genome.propagate(&[0.5, 1.0]).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_genome_crossover_and_distance() {
+        let mut rng = get_rng();
+        let mut tracker = InnovationTracker::new(2, 1);
+        let mut fitter = Genome::new(2, 1, &mut tracker, &mut rng);
+        let other = Genome::new(2, 1, &mut tracker, &mut rng);
+        assert_eq!(fitter.get_distance(&fitter), 0.0);
+        let base = fitter.get_distance(&other);
+        fitter.mutate_add_node(&mut tracker, &mut rng);
+        assert!(fitter.get_distance(&other) > base);
+        assert_eq!(fitter.get_distance(&other), other.get_distance(&fitter));
+        // The child has the structure of the fitter parent
+        let child = fitter.crossover(&other, &mut rng);
+        let innovations = |g: &Genome| g.connections.iter().map(|c| c.innovation).collect::<Vec<usize>>();
+        assert_eq!(innovations(&child), innovations(&fitter));
+        assert_eq!(child.nodes.len(), fitter.nodes.len());
+    }
+
+    #[test]
+    fn test_neat_population_next_generation() {
+        let mut pop = NeatPopulation::new(30, 3, 2);
+        assert_eq!(pop.species.iter().map(|s| s.members.len()).sum::<usize>(), 30);
+        for generation in 0..5 {
+            pop.fitness = (0..30).map(|i| ((i * 7 + generation) % 11) as f64 - 3.0).collect();
+            let adjusted = pop.get_adjusted_fitness();
+            assert!(adjusted.iter().all(|f| *f >= 0.0));
+            pop.create_next_generation();
+            assert_eq!(pop.genomes.len(), 30);
+            assert_eq!(pop.species.iter().map(|s| s.members.len()).sum::<usize>(), 30);
+        }
+        let action = pop.genomes[0].get_action(&[1.0, 0.0, 1.0]).unwrap();
+        assert!(action < 2);
+    }
+}
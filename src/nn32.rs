@@ -0,0 +1,167 @@
+use serde::{Deserialize, Serialize};
+
+use crate::constants::*;
+use crate::error::{Error, Result};
+use crate::game::Brain;
+use crate::gen_alg::{get_output_action, Activation, NN};
+
+// Precision of the weights while playing; evolution always works on the f64 network
+#[derive(Debug, Default, PartialEq, Copy, Clone, Serialize, Deserialize)]
+pub enum Precision {
+    #[default]
+    F64,
+    F32,
+}
+
+fn activate_in_place(activation: Activation, zs: &mut [f32]) {
+    match activation {
+        Activation::Sigmoid => zs.iter_mut().for_each(|z| *z = 1.0 / (1.0 + (-*z).exp())),
+        Activation::Tanh => zs.iter_mut().for_each(|z| *z = z.tanh()),
+        Activation::Relu => zs.iter_mut().for_each(|z| *z = z.max(0.0_f32)),
+        Activation::LeakyRelu => zs.iter_mut().for_each(|z| {
+            if *z <= 0.0_f32 {
+                *z *= LEAKY_RELU_SLOPE as f32
+            }
+        }),
+        Activation::Linear => {}
+        Activation::Softmax => {
+            let max = zs.iter().cloned().fold(f32::NEG_INFINITY, f32::max);
+            zs.iter_mut().for_each(|z| *z = (*z - max).exp());
+            let sum: f32 = zs.iter().sum();
+            zs.iter_mut().for_each(|z| *z /= sum);
+        }
+    }
+}
+
+// Same layout as gen_alg::Layer
+struct Layer32 {
+    num_inputs: usize,
+    weights: Vec<f32>,
+    biases: Vec<f32>,
+    activation: Activation,
+    recurrent_weights: Vec<f32>,
+    state: Vec<f32>,
+}
+
+impl Layer32 {
+    fn feed_forward_into(&self, inputs: &[f32], outputs: &mut Vec<f32>) {
+        outputs.clear();
+        for (ws, b) in self.weights.chunks_exact(self.num_inputs).zip(self.biases.iter()) {
+            outputs.push(b + ws.iter().zip(inputs.iter()).map(|(w, x)| w * x).sum::<f32>());
+        }
+        if !self.recurrent_weights.is_empty() {
+            for (z, us) in outputs
+                .iter_mut()
+                .zip(self.recurrent_weights.chunks_exact(self.state.len()))
+            {
+                *z += us.iter().zip(self.state.iter()).map(|(u, h)| u * h).sum::<f32>();
+            }
+        }
+        activate_in_place(self.activation, outputs);
+    }
+}
+
+// Single precision copy of an NN for playing only, halving the memory the weights take
+pub struct NN32 {
+    layers: Vec<Layer32>,
+    inputs: Vec<f32>,
+    buffers: Vec<Vec<f32>>,
+    outputs: Vec<f64>,
+}
+
+impl NN32 {
+    pub fn from_nn(nn: &NN) -> NN32 {
+        let to_f32 = |xs: &[f64]| xs.iter().map(|x| *x as f32).collect::<Vec<f32>>();
+        NN32 {
+            layers: nn
+                .layers
+                .iter()
+                .map(|l| Layer32 {
+                    num_inputs: l.num_inputs as usize,
+                    weights: to_f32(&l.weights),
+                    biases: to_f32(&l.biases),
+                    activation: l.activation,
+                    recurrent_weights: to_f32(&l.recurrent_weights),
+                    state: vec![0.0_f32; l.state.len()],
+                })
+                .collect(),
+            inputs: Vec::new(),
+            buffers: vec![Vec::new(); nn.layers.len()],
+            outputs: Vec::new(),
+        }
+    }
+
+    // Same as NN::step
+    pub fn step(&mut self, inputs: &[f64]) -> Result<&[f64]> {
+        match self.layers.first() {
+            Some(layer) if layer.num_inputs != inputs.len() => {
+                return Err(Error::ShapeMismatch {
+                    what: "layer inputs",
+                    expected: layer.num_inputs,
+                    found: inputs.len(),
+                })
+            }
+            Some(_) => {}
+            None => return Err(Error::InvalidConfig("the network has no layers".to_string())),
+        }
+        self.inputs.clear();
+        self.inputs.extend(inputs.iter().map(|x| *x as f32));
+        for (l, layer) in self.layers.iter_mut().enumerate() {
+            let (done, rest) = self.buffers.split_at_mut(l);
+            let layer_inputs = if l == 0 { &self.inputs } else { &done[l - 1] };
+            layer.feed_forward_into(layer_inputs, &mut rest[0]);
+            if !layer.state.is_empty() {
+                layer.state.copy_from_slice(&rest[0]);
+            }
+        }
+        self.outputs.clear();
+        self.outputs
+            .extend(self.buffers[self.layers.len() - 1].iter().map(|y| *y as f64));
+        Ok(&self.outputs)
+    }
+}
+
+impl Brain for NN32 {
+    fn get_action(&mut self, inputs: &[f64]) -> Result<usize> {
+        get_output_action(self.step(inputs)?)
+    }
+
+    fn on_episode_start(&mut self) {
+        for layer in self.layers.iter_mut() {
+            layer.state.iter_mut().for_each(|h| *h = 0.0_f32);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_nn32_matches_nn() {
+        let activations = [Activation::Relu, Activation::Tanh, Activation::Softmax];
+        let mut nn = NN::new_defined_with_activations(&[[8, 6], [6, 5], [5, 4]], &activations, &[]);
+        let mut nn32 = NN32::from_nn(&nn);
+        let inputs = vec![1.0_f64, 0.0_f64, 0.0_f64, 1.0_f64, 0.0_f64, 0.5_f64, 1.0_f64, 0.0_f64];
+        let expected = nn.step(&inputs).unwrap().to_vec();
+        let outputs = nn32.step(&inputs).unwrap();
+        assert!(expected.iter().zip(outputs.iter()).all(|(a, b)| (a - b).abs() < 1e-5));
+        assert!(nn32.step(&inputs[1..]).is_err());
+    }
+
+    #[test]
+    fn test_nn32_recurrent() {
+        let mut nn = NN::new_defined_with_activations(&[[3, 4], [4, 2]], &[], &[0]);
+        let mut nn32 = NN32::from_nn(&nn);
+        let inputs = vec![1.0_f64, -1.0_f64, 0.5_f64];
+        let first = nn32.step(&inputs).unwrap().to_vec();
+        nn.step(&inputs).unwrap();
+        for _ in 0..3 {
+            let expected = nn.step(&inputs).unwrap().to_vec();
+            let outputs = nn32.step(&inputs).unwrap();
+            assert!(expected.iter().zip(outputs.iter()).all(|(a, b)| (a - b).abs() < 1e-5));
+        }
+        nn32.on_episode_start();
+        assert_eq!(nn32.step(&inputs).unwrap().to_vec(), first);
+    }
+}
@@ -0,0 +1,170 @@
+extern crate rand;
+
+use rand::Rng;
+
+use crate::behaviour::Behaviour;
+use crate::constants::*;
+use crate::gen_alg::NN;
+
+// Behaviours seen in earlier generations, so novelty means new relative to the whole run
+pub struct NoveltyArchive {
+    pub behaviours: Vec<Behaviour>,
+    // Number of nearest neighbours averaged into the novelty score
+    pub k: usize,
+}
+
+impl NoveltyArchive {
+    pub fn new(k: usize) -> NoveltyArchive {
+        NoveltyArchive {
+            behaviours: Vec::new(),
+            k: k.max(1),
+        }
+    }
+
+    // Mean distance of every behaviour to its k nearest neighbours among the rest of the population and the archive
+    pub fn get_novelty(&self, behaviours: &[Behaviour]) -> Vec<f64> {
+        behaviours
+            .iter()
+            .enumerate()
+            .map(|(i, b)| {
+                let mut distances: Vec<f64> = behaviours
+                    .iter()
+                    .enumerate()
+                    .filter(|(j, _)| *j != i)
+                    .map(|(_, other)| b.get_distance(other))
+                    .chain(self.behaviours.iter().map(|other| b.get_distance(other)))
+                    .collect();
+                if distances.is_empty() {
+                    return 0.0_f64;
+                }
+                distances.sort_by(|x, y| x.partial_cmp(y).unwrap_or(std::cmp::Ordering::Equal));
+                let k = self.k.min(distances.len());
+                distances[..k].iter().sum::<f64>() / k as f64
+            })
+            .collect()
+    }
+
+    // Archives the most novel behaviours of a generation
+    pub fn add_most_novel(&mut self, behaviours: &[Behaviour], novelty: &[f64], count: usize) {
+        let mut index: Vec<usize> = (0..behaviours.len()).collect();
+        index.sort_by(|a, b| novelty[*b].partial_cmp(&novelty[*a]).unwrap_or(std::cmp::Ordering::Equal));
+        for i in index.into_iter().take(count) {
+            self.behaviours.push(behaviours[i].clone());
+        }
+    }
+}
+
+pub struct Elite {
+    pub nn: NN,
+    pub fitness: f64,
+    pub behaviour: Behaviour,
+}
+
+// MAP-Elites archive: the fittest network found in every cell of a grid of board coverage by turns per food
+pub struct MapElites {
+    pub bins: usize,
+    // Row-major, indexed by turns per food then coverage
+    pub cells: Vec<Option<Elite>>,
+}
+
+impl MapElites {
+    pub fn new(bins: usize) -> MapElites {
+        let bins = bins.max(1);
+        MapElites {
+            bins,
+            cells: (0..bins * bins).map(|_| None).collect(),
+        }
+    }
+
+    // Cell coordinates (coverage, turns per food) of a behaviour
+    pub fn get_cell(&self, behaviour: &Behaviour) -> (usize, usize) {
+        let to_bin = |v: f64| ((v * self.bins as f64) as usize).min(self.bins - 1);
+        (
+            to_bin(behaviour.get_coverage()),
+            to_bin(behaviour.turns_per_food / MAP_ELITES_MAX_TURNS_PER_FOOD),
+        )
+    }
+
+    // Keeps the network if its cell is empty or holds a less fit one
+    pub fn add(&mut self, nn: NN, fitness: f64, behaviour: Behaviour) -> bool {
+        let (x, y) = self.get_cell(&behaviour);
+        let cell = &mut self.cells[y * self.bins + x];
+        if cell.as_ref().is_some_and(|e| e.fitness >= fitness) {
+            return false;
+        }
+        *cell = Some(Elite { nn, fitness, behaviour });
+        true
+    }
+
+    pub fn get_elites(&self) -> Vec<(usize, usize, &Elite)> {
+        self.cells
+            .iter()
+            .enumerate()
+            .filter_map(|(i, cell)| cell.as_ref().map(|e| (i % self.bins, i / self.bins, e)))
+            .collect()
+    }
+
+    pub fn get_random_elite(&self, rng: &mut impl Rng) -> Option<&Elite> {
+        let elites = self.get_elites();
+        if elites.is_empty() {
+            None
+        } else {
+            Some(elites[rng.gen_range(0, elites.len())].2)
+        }
+    }
+
+    pub fn get_best(&self) -> Option<&Elite> {
+        self.cells.iter().flatten().max_by(|a, b| {
+            a.fitness
+                .partial_cmp(&b.fitness)
+                .unwrap_or(std::cmp::Ordering::Equal)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::behaviour::BehaviourRecorder;
+
+    fn get_behaviour(cell: usize, turns_per_food: f64) -> Behaviour {
+        let mut behaviour = BehaviourRecorder::new().get_behaviour();
+        behaviour.cells[cell] = 1.0;
+        behaviour.turns_per_food = turns_per_food;
+        behaviour
+    }
+
+    #[test]
+    fn test_novelty_archive() {
+        let mut archive = NoveltyArchive::new(1);
+        let behaviours = vec![get_behaviour(0, 0.0), get_behaviour(0, 0.0), get_behaviour(1, 0.0)];
+        let novelty = archive.get_novelty(&behaviours);
+        // The odd one out is the most novel
+        assert_eq!(novelty, vec![0.0, 0.0, 2.0]);
+        archive.add_most_novel(&behaviours, &novelty, 1);
+        assert_eq!(archive.behaviours, vec![get_behaviour(1, 0.0)]);
+        // Behaviours close to the archive are no longer novel
+        let novelty = archive.get_novelty(&[get_behaviour(1, 0.0)]);
+        assert_eq!(novelty, vec![0.0]);
+        assert_eq!(NoveltyArchive::new(3).get_novelty(&[get_behaviour(1, 0.0)]), vec![0.0]);
+    }
+
+    #[test]
+    fn test_map_elites() {
+        let mut map = MapElites::new(4);
+        let nn = NN::new_defined(&[[3, 2], [2, 1]]);
+        let behaviour = get_behaviour(0, MAP_ELITES_MAX_TURNS_PER_FOOD * 0.6);
+        assert_eq!(map.get_cell(&behaviour), (0, 2));
+        assert_eq!(map.get_cell(&get_behaviour(0, 1000.0)), (0, 3));
+        assert!(map.add(nn.clone(), 1.0, behaviour.clone()));
+        assert!(!map.add(nn.clone(), 0.5, behaviour.clone()));
+        assert!(map.add(nn.clone(), 2.0, behaviour));
+        assert!(map.add(nn.clone(), 0.0, get_behaviour(0, 0.0)));
+        let elites = map.get_elites();
+        assert_eq!(elites.len(), 2);
+        assert_eq!((elites[0].0, elites[0].1), (0, 0));
+        assert_eq!(map.get_best().unwrap().fitness, 2.0);
+        assert!(map.get_random_elite(&mut rand::thread_rng()).is_some());
+        assert!(MapElites::new(2).get_random_elite(&mut rand::thread_rng()).is_none());
+    }
+}
@@ -0,0 +1,142 @@
+use std::collections::VecDeque;
+
+use crate::error::Result;
+use crate::game::{Brain, EpisodeOutcome, Transition};
+use crate::qlearn::{get_max_float, QLearner};
+
+pub struct NStepLearner {
+    pub ql: QLearner,
+    pub n: usize,
+    buffer: VecDeque<(usize, usize, f64)>,
+    // State reached by the last move that did not end the game, bootstrapped from if the game is cut short
+    last_index: Option<usize>,
+}
+
+impl NStepLearner {
+    pub fn new(len_states: usize, num_actions: usize, n: usize) -> NStepLearner {
+        NStepLearner {
+            ql: QLearner::new(len_states, num_actions),
+            n: n.max(1),
+            buffer: VecDeque::new(),
+            last_index: None,
+        }
+    }
+
+    fn update_oldest(&mut self, bootstrap_index: Option<usize>) {
+        let mut ret = 0.0_f64;
+        let mut discount = 1.0_f64;
+        for (_, _, reward) in self.buffer.iter() {
+            ret += discount * reward;
+            discount *= self.ql.discount_factor;
+        }
+        if let Some(bi) = bootstrap_index {
+            ret += discount * get_max_float(&self.ql.q[bi].quality).unwrap_or(&0.0_f64);
+        }
+        if let Some((si, action, _)) = self.buffer.pop_front() {
+            let q = &mut self.ql.q[si].quality[action];
+            *q += self.ql.learning_rate * (ret - *q);
+        }
+    }
+
+    // The episode is over, so the remaining returns are only bootstrapped when it was cut short by the time limit
+    fn flush(&mut self, bootstrap_index: Option<usize>) {
+        while !self.buffer.is_empty() {
+            self.update_oldest(bootstrap_index);
+        }
+    }
+}
+
+impl Brain for NStepLearner {
+    fn get_action(&mut self, state: &[f64]) -> Result<usize> {
+        let index = self.ql.get_state_index(state);
+        Ok(self.ql.get_epsilon_greedy_action(index))
+    }
+
+    fn train(&mut self, transition: &Transition) -> Option<bool> {
+        if transition.action >= self.ql.num_actions {
+            return None;
+        }
+        if !self.ql.training {
+            return Some(false);
+        }
+        let ii = self.ql.get_state_index(transition.state);
+        let fi = self.ql.get_state_index(transition.next_state);
+        self.buffer.push_back((ii, transition.action, transition.reward));
+        if transition.done {
+            self.last_index = None;
+            self.flush(None);
+        } else {
+            self.last_index = Some(fi);
+            if self.buffer.len() >= self.n {
+                self.update_oldest(Some(fi));
+            }
+        }
+        Some(true)
+    }
+
+    fn on_episode_end(&mut self, outcome: &EpisodeOutcome) {
+        let bootstrap_index = self.last_index.take();
+        self.flush(bootstrap_index);
+        self.ql.on_episode_end(outcome);
+    }
+
+    fn set_training(&mut self, training: bool) {
+        self.ql.set_training(training);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::Game;
+
+    #[test]
+    fn test_nstep_learner_new() {
+        let nl = NStepLearner::new(8, 4, 0);
+        assert_eq!(nl.ql.q.len(), 2_usize.pow(8));
+        assert_eq!(nl.n, 1);
+    }
+
+    #[test]
+    fn test_nstep_learner_train() {
+        let mut nl = NStepLearner::new(2, 4, 3);
+        let s0 = vec![0.0_f64, 0.0_f64];
+        let s1 = vec![0.0_f64, 1.0_f64];
+        nl.train(&Transition::new(&s0, 0, 0.0_f64, &s1, false));
+        nl.train(&Transition::new(&s1, 1, 10.0_f64, &s0, false));
+        // Nothing is updated until n transitions are buffered
+        assert_eq!(nl.ql.q[0].quality[0], 0.0_f64);
+        nl.train(&Transition::new(&s0, 2, 0.0_f64, &s1, false));
+        let expected = nl.ql.learning_rate * nl.ql.discount_factor * 10.0_f64;
+        assert!((nl.ql.q[0].quality[0] - expected).abs() < 1e-12);
+        nl.on_episode_end(&Game::new().get_outcome());
+        assert!(nl.buffer.is_empty());
+        assert!(nl.ql.q[1].quality[1] > 0.0_f64);
+    }
+
+    #[test]
+    fn test_nstep_learner_done() {
+        let mut nl = NStepLearner::new(2, 4, 3);
+        let s0 = vec![0.0_f64, 0.0_f64];
+        let s1 = vec![0.0_f64, 1.0_f64];
+        nl.ql.q[1].quality = vec![100.0_f64; 4];
+        nl.train(&Transition::new(&s0, 0, 1.0_f64, &s1, true));
+        // A final move flushes the buffer without bootstrapping from the next state
+        assert!(nl.buffer.is_empty());
+        assert!((nl.ql.q[0].quality[0] - nl.ql.learning_rate).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_nstep_learner_timed_out() {
+        let mut nl = NStepLearner::new(2, 4, 3);
+        let s0 = vec![0.0_f64, 0.0_f64];
+        let s1 = vec![0.0_f64, 1.0_f64];
+        nl.ql.q[1].quality = vec![100.0_f64; 4];
+        nl.train(&Transition::new(&s0, 0, 1.0_f64, &s1, false));
+        // A game cut short by the time limit still bootstraps from the state it stopped in
+        nl.on_episode_end(&Game::new().get_outcome());
+        assert!(nl.buffer.is_empty());
+        let expected = nl.ql.learning_rate * (1.0_f64 + nl.ql.discount_factor * 100.0_f64);
+        assert!((nl.ql.q[0].quality[0] - expected).abs() < 1e-12);
+    }
+}
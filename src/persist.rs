@@ -0,0 +1,851 @@
+use bincode::Options;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+
+use rand_pcg::Pcg32;
+
+use crate::error::{Error, Result};
+use crate::gen_alg::{Activation, GaConfig, HallOfFame, HallOfFameEntry, Layer, Population, NN};
+use crate::qlearn::{QLearner, StateAction};
+
+pub const NN_FILE_VERSION: u32 = 2;
+const NN_BINARY_MAGIC: &[u8; 4] = b"SNNB";
+pub const QTABLE_FILE_VERSION: u32 = 1;
+const QTABLE_BINARY_MAGIC: &[u8; 4] = b"SQTB";
+pub const CHECKPOINT_FILE_VERSION: u32 = 10;
+const CHECKPOINT_BINARY_MAGIC: &[u8; 4] = b"SGAC";
+
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum FileFormat {
+    Json,
+    Binary,
+}
+
+impl FileFormat {
+    pub fn from_path(path: &Path) -> FileFormat {
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("json") => FileFormat::Json,
+            _ => FileFormat::Binary,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct LayerRecord {
+    pub num_inputs: u32,
+    pub num_neurons: u32,
+    pub activation: Activation,
+    pub weights: Vec<Vec<f64>>,
+    pub biases: Vec<f64>,
+    // Empty for a feed-forward layer
+    #[serde(default)]
+    pub recurrent_weights: Vec<Vec<f64>>,
+}
+
+// Layout of the layers saved before recurrent layers, read to migrate version 1 files
+#[derive(Deserialize)]
+struct LayerRecordV1 {
+    num_inputs: u32,
+    num_neurons: u32,
+    activation: Activation,
+    weights: Vec<Vec<f64>>,
+    biases: Vec<f64>,
+}
+
+#[derive(Deserialize)]
+struct NNRecordV1 {
+    #[serde(rename = "version")]
+    _version: u32,
+    input_encoding: String,
+    num_inputs: u32,
+    num_outputs: u32,
+    layers: Vec<LayerRecordV1>,
+}
+
+impl NNRecordV1 {
+    // Every layer of a version 1 network is feed-forward
+    fn into_current(self) -> NNRecord {
+        NNRecord {
+            version: NN_FILE_VERSION,
+            input_encoding: self.input_encoding,
+            num_inputs: self.num_inputs,
+            num_outputs: self.num_outputs,
+            layers: self
+                .layers
+                .into_iter()
+                .map(|l| LayerRecord {
+                    num_inputs: l.num_inputs,
+                    num_neurons: l.num_neurons,
+                    activation: l.activation,
+                    weights: l.weights,
+                    biases: l.biases,
+                    recurrent_weights: Vec::new(),
+                })
+                .collect(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct NNRecord {
+    pub version: u32,
+    pub input_encoding: String,
+    pub num_inputs: u32,
+    pub num_outputs: u32,
+    pub layers: Vec<LayerRecord>,
+}
+
+impl NNRecord {
+    pub fn from_nn(nn: &NN, input_encoding: &str) -> NNRecord {
+        NNRecord {
+            version: NN_FILE_VERSION,
+            input_encoding: input_encoding.to_string(),
+            num_inputs: nn.layers.first().map(|l| l.num_inputs).unwrap_or(0),
+            num_outputs: nn.layers.last().map(|l| l.num_neurons).unwrap_or(0),
+            layers: nn
+                .layers
+                .iter()
+                .map(|l| LayerRecord {
+                    num_inputs: l.num_inputs,
+                    num_neurons: l.num_neurons,
+                    activation: l.activation,
+                    weights: l.weights.chunks(l.num_inputs as usize).map(|ws| ws.to_vec()).collect(),
+                    biases: l.biases.clone(),
+                    recurrent_weights: l
+                        .recurrent_weights
+                        .chunks(l.num_neurons as usize)
+                        .map(|us| us.to_vec())
+                        .collect(),
+                })
+                .collect(),
+        }
+    }
+
+    // Checks the record against the network the caller expects before building it
+    pub fn to_nn(&self, input_encoding: &str, num_inputs: u32, num_outputs: u32) -> Result<NN> {
+        if self.version != NN_FILE_VERSION {
+            return Err(Error::Parse(format!("unsupported NN file version {}", self.version)));
+        }
+        if self.input_encoding != input_encoding {
+            return Err(Error::Parse(format!(
+                "input encoding '{}' does not match '{}'",
+                self.input_encoding, input_encoding
+            )));
+        }
+        if self.num_inputs != num_inputs || self.num_outputs != num_outputs {
+            return Err(Error::Parse(format!(
+                "network is {} -> {}, expected {} -> {}",
+                self.num_inputs, self.num_outputs, num_inputs, num_outputs
+            )));
+        }
+
+        let mut nn = NN::new();
+        for (i, record) in self.layers.iter().enumerate() {
+            let shape_ok = record.biases.len() == record.num_neurons as usize
+                && record.weights.len() == record.num_neurons as usize
+                && record.weights.iter().all(|ws| ws.len() == record.num_inputs as usize);
+            let recurrent = !record.recurrent_weights.is_empty();
+            let recurrent_ok = !recurrent
+                || (record.recurrent_weights.len() == record.num_neurons as usize
+                    && record
+                        .recurrent_weights
+                        .iter()
+                        .all(|us| us.len() == record.num_neurons as usize));
+            let layer = if recurrent {
+                Layer::new_recurrent(record.num_inputs, record.num_neurons, record.activation)
+            } else {
+                Layer::new_with_activation(record.num_inputs, record.num_neurons, record.activation)
+            };
+            let mut layer = match layer {
+                Ok(layer) if shape_ok && recurrent_ok => layer,
+                _ => return Err(Error::Parse(format!("layer {} has inconsistent dimensions", i))),
+            };
+            layer.weights = record.weights.concat();
+            layer.biases = record.biases.clone();
+            if recurrent {
+                layer.recurrent_weights = record.recurrent_weights.concat();
+            }
+            if nn.add(layer).is_err() {
+                return Err(Error::Parse(format!(
+                    "layer {} does not connect to the previous layer",
+                    i
+                )));
+            }
+        }
+        let first_ok = nn.layers.first().map(|l| l.num_inputs) == Some(num_inputs);
+        let last_ok = nn.layers.last().map(|l| l.num_neurons) == Some(num_outputs);
+        if first_ok && last_ok {
+            Ok(nn)
+        } else {
+            Err(Error::Parse(
+                "layers do not match the declared inputs and outputs".to_string(),
+            ))
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct QTableRecord {
+    pub version: u32,
+    pub state_encoding: String,
+    pub epsilon: f64,
+    pub learning_rate: f64,
+    pub discount_factor: f64,
+    pub episodes: u32,
+    pub len_states: usize,
+    pub num_actions: usize,
+    pub q: Vec<StateAction>,
+}
+
+impl QTableRecord {
+    pub fn from_qlearner(ql: &QLearner, state_encoding: &str) -> QTableRecord {
+        QTableRecord {
+            version: QTABLE_FILE_VERSION,
+            state_encoding: state_encoding.to_string(),
+            epsilon: ql.epsilon,
+            learning_rate: ql.learning_rate,
+            discount_factor: ql.discount_factor,
+            episodes: ql.episodes,
+            len_states: ql.len_states,
+            num_actions: ql.num_actions,
+            q: ql
+                .q
+                .iter()
+                .map(|sa| StateAction {
+                    state: sa.state.clone(),
+                    quality: sa.quality.clone(),
+                })
+                .collect(),
+        }
+    }
+
+    pub fn into_qlearner(self, state_encoding: &str, len_states: usize, num_actions: usize) -> Result<QLearner> {
+        if self.version != QTABLE_FILE_VERSION {
+            return Err(Error::Parse(format!(
+                "unsupported Q-table file version {}",
+                self.version
+            )));
+        }
+        if self.state_encoding != state_encoding {
+            return Err(Error::Parse(format!(
+                "state encoding '{}' does not match '{}'",
+                self.state_encoding, state_encoding
+            )));
+        }
+        if self.len_states != len_states || self.num_actions != num_actions {
+            return Err(Error::Parse(format!(
+                "Q-table is {} states x {} actions, expected {} x {}",
+                self.len_states, self.num_actions, len_states, num_actions
+            )));
+        }
+        if let Some(i) = self
+            .q
+            .iter()
+            .position(|sa| sa.state.len() != len_states || sa.quality.len() != num_actions)
+        {
+            return Err(Error::Parse(format!("state {} has inconsistent dimensions", i)));
+        }
+
+        let mut ql = QLearner::new(len_states, num_actions);
+        ql.num_states = self.q.len();
+        ql.q = self.q;
+        ql.epsilon = self.epsilon;
+        ql.learning_rate = self.learning_rate;
+        ql.discount_factor = self.discount_factor;
+        ql.episodes = self.episodes;
+        Ok(ql)
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct HallOfFameRecord {
+    pub fitness: f64,
+    pub evaluations: u32,
+    pub generation: u32,
+    pub nn: NNRecord,
+}
+
+// An evaluated generation, so resuming breeds the next generation from the stored fitness
+#[derive(Serialize, Deserialize)]
+pub struct CheckpointRecord {
+    pub version: u32,
+    pub generation: u32,
+    pub config: GaConfig,
+    pub input_encoding: String,
+    pub nns: Vec<NNRecord>,
+    pub fitness: Vec<f64>,
+    pub rng: Pcg32,
+    #[serde(default)]
+    pub sigma: Vec<f64>,
+    #[serde(default)]
+    pub hall_of_fame: Vec<HallOfFameRecord>,
+}
+
+// Layout of the first checkpoints, read to migrate version 1 files
+#[derive(Deserialize)]
+struct GaConfigV1 {
+    num_individuals: u32,
+    num_games: u32,
+    num_generations: u32,
+    layer_def: Vec<[usize; 2]>,
+    checkpoint_every: u32,
+    checkpoint_path: String,
+    seed: Option<u64>,
+}
+
+#[derive(Deserialize)]
+struct CheckpointRecordV1 {
+    #[serde(rename = "version")]
+    _version: u32,
+    generation: u32,
+    config: GaConfigV1,
+    input_encoding: String,
+    nns: Vec<NNRecordV1>,
+    fitness: Vec<f64>,
+    rng: Pcg32,
+}
+
+impl CheckpointRecordV1 {
+    // The settings added since take their defaults
+    fn into_current(self) -> CheckpointRecord {
+        CheckpointRecord {
+            version: CHECKPOINT_FILE_VERSION,
+            generation: self.generation,
+            config: GaConfig {
+                num_individuals: self.config.num_individuals,
+                num_games: self.config.num_games,
+                num_generations: self.config.num_generations,
+                layer_def: self.config.layer_def,
+                checkpoint_every: self.config.checkpoint_every,
+                checkpoint_path: self.config.checkpoint_path,
+                seed: self.config.seed,
+                ..GaConfig::new()
+            },
+            input_encoding: self.input_encoding,
+            nns: self.nns.into_iter().map(|nn| nn.into_current()).collect(),
+            fitness: self.fitness,
+            rng: self.rng,
+            sigma: Vec::new(),
+            hall_of_fame: Vec::new(),
+        }
+    }
+}
+
+impl CheckpointRecord {
+    pub fn from_population(
+        pop: &Population,
+        hall_of_fame: &HallOfFame,
+        generation: u32,
+        config: &GaConfig,
+        input_encoding: &str,
+    ) -> CheckpointRecord {
+        CheckpointRecord {
+            version: CHECKPOINT_FILE_VERSION,
+            generation,
+            config: config.clone(),
+            input_encoding: input_encoding.to_string(),
+            nns: pop.nn.iter().map(|nn| NNRecord::from_nn(nn, input_encoding)).collect(),
+            fitness: pop.fitness.clone(),
+            rng: pop.rng.clone(),
+            sigma: pop.sigma.clone(),
+            hall_of_fame: hall_of_fame
+                .members
+                .iter()
+                .map(|m| HallOfFameRecord {
+                    fitness: m.fitness,
+                    evaluations: m.evaluations,
+                    generation: m.generation,
+                    nn: NNRecord::from_nn(&m.nn, input_encoding),
+                })
+                .collect(),
+        }
+    }
+
+    fn get_io_sizes(&self) -> (u32, u32) {
+        let num_inputs = self.config.layer_def.first().map(|l| l[0] as u32).unwrap_or(0);
+        let num_outputs = self.config.layer_def.last().map(|l| l[1] as u32).unwrap_or(0);
+        (num_inputs, num_outputs)
+    }
+
+    pub fn to_population(&self) -> Result<Population> {
+        if self.version != CHECKPOINT_FILE_VERSION {
+            return Err(Error::Parse(format!(
+                "unsupported checkpoint file version {}",
+                self.version
+            )));
+        }
+        if self.nns.len() != self.fitness.len() {
+            return Err(Error::Parse("every individual needs a fitness".to_string()));
+        }
+        let (num_inputs, num_outputs) = self.get_io_sizes();
+        let mut pop = Population::new();
+        for (i, record) in self.nns.iter().enumerate() {
+            let nn = record
+                .to_nn(&self.input_encoding, num_inputs, num_outputs)
+                .map_err(|e| Error::individual(i, e))?;
+            pop.add(nn);
+        }
+        pop.fitness = self.fitness.clone();
+        // Checkpoints written before mutation step sizes were stored start from the configured one
+        if self.sigma.is_empty() {
+            pop.sigma = vec![self.config.mutation.get_initial_sigma(); pop.length];
+        } else {
+            if self.sigma.len() != self.nns.len() {
+                return Err(Error::Parse("every individual needs a mutation step size".to_string()));
+            }
+            pop.sigma = self.sigma.clone();
+        }
+        pop.rng = self.rng.clone();
+        Ok(pop)
+    }
+
+    pub fn to_hall_of_fame(&self) -> Result<HallOfFame> {
+        let (num_inputs, num_outputs) = self.get_io_sizes();
+        let mut hall_of_fame = HallOfFame::new(self.config.hall_of_fame_size);
+        for (i, record) in self.hall_of_fame.iter().enumerate() {
+            let nn = record
+                .nn
+                .to_nn(&self.input_encoding, num_inputs, num_outputs)
+                .map_err(|e| Error::Parse(format!("hall of fame member {}: {}", i, e)))?;
+            hall_of_fame.members.push(HallOfFameEntry {
+                fitness: record.fitness,
+                evaluations: record.evaluations,
+                generation: record.generation,
+                nn,
+            });
+        }
+        Ok(hall_of_fame)
+    }
+}
+
+fn write_record<T: Serialize>(record: &T, magic: &[u8; 4], path: &Path) -> Result<()> {
+    match FileFormat::from_path(path) {
+        FileFormat::Json => fs::write(path, serde_json::to_string_pretty(record)?)?,
+        FileFormat::Binary => {
+            let mut bytes = magic.to_vec();
+            bytes.extend(bincode::serialize(record)?);
+            fs::write(path, bytes)?
+        }
+    }
+    Ok(())
+}
+
+// The contents of a saved file, so that its version can be read before choosing the layout to parse
+struct RecordFile {
+    format: FileFormat,
+    bytes: Vec<u8>,
+}
+
+#[derive(Deserialize)]
+struct VersionRecord {
+    version: u32,
+}
+
+impl RecordFile {
+    fn read(magic: &[u8; 4], path: &Path) -> Result<RecordFile> {
+        let format = FileFormat::from_path(path);
+        let mut bytes = fs::read(path)?;
+        if format == FileFormat::Binary {
+            if !bytes.starts_with(magic) {
+                return Err(Error::Parse(format!(
+                    "missing {} header",
+                    String::from_utf8_lossy(magic)
+                )));
+            }
+            bytes.drain(..magic.len());
+        }
+        Ok(RecordFile { format, bytes })
+    }
+
+    // Every record starts with its version
+    fn get_version(&self) -> Result<u32> {
+        match self.format {
+            FileFormat::Json => Ok(serde_json::from_slice::<VersionRecord>(&self.bytes)?.version),
+            FileFormat::Binary => Ok(bincode::deserialize(&self.bytes)?),
+        }
+    }
+
+    // A binary record must use up every byte, so that a file of another layout is not misread
+    fn parse<T: DeserializeOwned>(&self) -> Result<T> {
+        match self.format {
+            FileFormat::Json => Ok(serde_json::from_slice(&self.bytes)?),
+            FileFormat::Binary => Ok(bincode::DefaultOptions::new()
+                .with_fixint_encoding()
+                .deserialize(&self.bytes)?),
+        }
+    }
+}
+
+fn read_record<T: DeserializeOwned>(magic: &[u8; 4], path: &Path) -> Result<T> {
+    RecordFile::read(magic, path)?.parse()
+}
+
+fn read_nn_record(file: &RecordFile) -> Result<NNRecord> {
+    match file.get_version()? {
+        1 => Ok(file.parse::<NNRecordV1>()?.into_current()),
+        NN_FILE_VERSION => file.parse(),
+        version => Err(Error::Parse(format!("unsupported NN file version {}", version))),
+    }
+}
+
+fn read_checkpoint_record(file: &RecordFile) -> Result<CheckpointRecord> {
+    match (file.get_version()?, file.format) {
+        (CHECKPOINT_FILE_VERSION, _) => file.parse(),
+        (1, _) => Ok(file.parse::<CheckpointRecordV1>()?.into_current()),
+        // The fields added since take their defaults in JSON, but they shift every later field of a binary layout
+        (version, FileFormat::Json) if (2..CHECKPOINT_FILE_VERSION).contains(&version) => {
+            let mut record: CheckpointRecord = file.parse()?;
+            record.version = CHECKPOINT_FILE_VERSION;
+            // Networks saved before recurrent layers are feed-forward, which the missing recurrent weights give
+            for nn in record
+                .nns
+                .iter_mut()
+                .chain(record.hall_of_fame.iter_mut().map(|m| &mut m.nn))
+            {
+                nn.version = NN_FILE_VERSION;
+            }
+            Ok(record)
+        }
+        (version, FileFormat::Binary) if (2..CHECKPOINT_FILE_VERSION).contains(&version) => Err(Error::Parse(format!(
+            "binary checkpoint version {} was written by an older build and cannot be migrated, resume from a JSON checkpoint",
+            version
+        ))),
+        (version, _) => Err(Error::Parse(format!("unsupported checkpoint file version {}", version))),
+    }
+}
+
+pub fn save_nn(nn: &NN, input_encoding: &str, path: &Path) -> Result<()> {
+    write_record(&NNRecord::from_nn(nn, input_encoding), NN_BINARY_MAGIC, path)
+}
+
+pub fn load_nn(path: &Path, input_encoding: &str, num_inputs: u32, num_outputs: u32) -> Result<NN> {
+    read_nn_record(&RecordFile::read(NN_BINARY_MAGIC, path)?)?.to_nn(input_encoding, num_inputs, num_outputs)
+}
+
+pub fn save_qlearner(ql: &QLearner, state_encoding: &str, path: &Path) -> Result<()> {
+    write_record(
+        &QTableRecord::from_qlearner(ql, state_encoding),
+        QTABLE_BINARY_MAGIC,
+        path,
+    )
+}
+
+pub fn load_qlearner(path: &Path, state_encoding: &str, len_states: usize, num_actions: usize) -> Result<QLearner> {
+    let record: QTableRecord = read_record(QTABLE_BINARY_MAGIC, path)?;
+    record.into_qlearner(state_encoding, len_states, num_actions)
+}
+
+pub fn save_checkpoint(
+    pop: &Population,
+    hall_of_fame: &HallOfFame,
+    generation: u32,
+    config: &GaConfig,
+    input_encoding: &str,
+    path: &Path,
+) -> Result<()> {
+    let record = CheckpointRecord::from_population(pop, hall_of_fame, generation, config, input_encoding);
+    write_record(&record, CHECKPOINT_BINARY_MAGIC, path)
+}
+
+pub fn load_checkpoint(path: &Path) -> Result<(Population, HallOfFame, u32, GaConfig)> {
+    let record = read_checkpoint_record(&RecordFile::read(CHECKPOINT_BINARY_MAGIC, path)?)?;
+    let pop = record.to_population()?;
+    let hall_of_fame = record.to_hall_of_fame()?;
+    Ok((pop, hall_of_fame, record.generation, record.config))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::{Brain, Transition};
+    use crate::mutation::MutationMethod;
+    use rand::{Rng, SeedableRng};
+    use std::path::PathBuf;
+
+    fn get_temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("snake_01_{}_{}", std::process::id(), name))
+    }
+
+    #[test]
+    fn test_file_format_from_path() {
+        assert_eq!(FileFormat::from_path(Path::new("a.json")), FileFormat::Json);
+        assert_eq!(FileFormat::from_path(Path::new("a.bin")), FileFormat::Binary);
+        assert_eq!(FileFormat::from_path(Path::new("a")), FileFormat::Binary);
+    }
+
+    #[test]
+    fn test_save_load_nn() {
+        let nn = NN::new_defined_with_activations(&[[8, 6], [6, 4]], &[Activation::Relu, Activation::Softmax], &[]);
+        let inputs = vec![1.0_f64, 0.0_f64, 0.0_f64, 1.0_f64, 0.0_f64, 0.0_f64, 1.0_f64, 0.0_f64];
+        for name in ["nn.json", "nn.bin"].iter() {
+            let path = get_temp_path(name);
+            save_nn(&nn, "test", &path).unwrap();
+            let loaded = load_nn(&path, "test", 8, 4).unwrap();
+            fs::remove_file(&path).unwrap();
+            assert_eq!(loaded.layers.len(), 2);
+            assert_eq!(loaded.layers[0].activation, Activation::Relu);
+            assert_eq!(loaded.layers[1].activation, Activation::Softmax);
+            assert_eq!(loaded.propagate(&inputs).unwrap(), nn.propagate(&inputs).unwrap());
+        }
+    }
+
+    #[test]
+    fn test_save_load_recurrent_nn() {
+        let nn = NN::new_defined_with_activations(&[[8, 6], [6, 4]], &[], &[0]);
+        let inputs = vec![1.0_f64, 0.0_f64, 0.0_f64, 1.0_f64, 0.0_f64, 0.0_f64, 1.0_f64, 0.0_f64];
+        let path = get_temp_path("recurrent.bin");
+        save_nn(&nn, "test", &path).unwrap();
+        let mut loaded = load_nn(&path, "test", 8, 4).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert!(loaded == nn);
+        assert!(loaded.layers[0].is_recurrent() && !loaded.layers[1].is_recurrent());
+        let mut nn = nn;
+        for _ in 0..3 {
+            assert_eq!(loaded.step(&inputs).unwrap(), nn.step(&inputs).unwrap());
+        }
+
+        let mut record = NNRecord::from_nn(&nn, "test");
+        record.layers[0].recurrent_weights[2].pop();
+        assert!(record.to_nn("test", 8, 4).is_err());
+    }
+
+    #[test]
+    fn test_load_nn_v1() {
+        let expected: Vec<f64> = (0..8).map(|i| i as f64 / 100.0_f64).collect();
+        let inputs = vec![1.0_f64, 0.0_f64, 0.0_f64, 1.0_f64, 0.0_f64, 0.0_f64, 1.0_f64, 0.0_f64];
+        // Saved by the version before recurrent layers, without their weights
+        let path = get_temp_path("nn_v1.bin");
+        fs::write(&path, &include_bytes!("../tests/fixtures/nn_v1.bin")[..]).unwrap();
+        let nn = load_nn(&path, "test", 8, 4).unwrap();
+        assert_eq!(nn.layers[0].activation, Activation::Tanh);
+        assert!(!nn.layers[0].is_recurrent());
+        assert_eq!(nn.layers[0].weights[..8], expected[..]);
+        assert_eq!(nn.layers[0].biases, vec![0.5_f64, -0.5_f64, 0.25_f64, -0.25_f64]);
+
+        // Saving again writes the current version, which loads the same network
+        save_nn(&nn, "test", &path).unwrap();
+        let loaded = load_nn(&path, "test", 8, 4).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(loaded.propagate(&inputs).unwrap(), nn.propagate(&inputs).unwrap());
+        let file = RecordFile {
+            format: FileFormat::Binary,
+            bytes: bincode::serialize(&NNRecord::from_nn(&nn, "test")).unwrap(),
+        };
+        assert_eq!(file.get_version().unwrap(), NN_FILE_VERSION);
+
+        // A version this build does not know is named rather than misread
+        let mut record = NNRecord::from_nn(&nn, "test");
+        record.version = NN_FILE_VERSION + 1;
+        let file = RecordFile {
+            format: FileFormat::Binary,
+            bytes: bincode::serialize(&record).unwrap(),
+        };
+        let e = read_nn_record(&file).err().unwrap();
+        assert!(e.to_string().contains("unsupported NN file version"));
+    }
+
+    #[test]
+    fn test_load_nn_validation() {
+        let nn = NN::new_defined(&[[8, 6], [6, 4]]);
+        let path = get_temp_path("validation.json");
+        save_nn(&nn, "test", &path).unwrap();
+        assert!(load_nn(&path, "test", 8, 3).is_err());
+        assert!(load_nn(&path, "test", 7, 4).is_err());
+        assert!(load_nn(&path, "other", 8, 4).is_err());
+        fs::remove_file(&path).unwrap();
+
+        let mut record = NNRecord::from_nn(&nn, "test");
+        record.version = NN_FILE_VERSION + 1;
+        assert!(record.to_nn("test", 8, 4).is_err());
+        let mut record = NNRecord::from_nn(&nn, "test");
+        record.layers[1].weights[0].pop();
+        assert!(record.to_nn("test", 8, 4).is_err());
+        let mut record = NNRecord::from_nn(&nn, "test");
+        record.layers.pop();
+        assert!(record.to_nn("test", 8, 4).is_err());
+
+        let path = get_temp_path("garbage.bin");
+        fs::write(&path, b"garbage").unwrap();
+        assert!(load_nn(&path, "test", 8, 4).is_err());
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_save_load_qlearner() {
+        let mut ql = QLearner::new(8, 4);
+        ql.epsilon = 0.2_f64;
+        ql.episodes = 42;
+        let state = vec![1.0_f64, 0.0_f64, 1.0_f64, 0.0_f64, 0.0_f64, 0.0_f64, 0.0_f64, 1.0_f64];
+        ql.train(&Transition::new(&state, 2, 10.0_f64, &state, false));
+        // States outside the binary encoding are added on the fly and must survive too
+        ql.get_state_index(&[0.5_f64; 8]);
+        for name in ["ql.json", "ql.bin"].iter() {
+            let path = get_temp_path(name);
+            save_qlearner(&ql, "test", &path).unwrap();
+            let loaded = load_qlearner(&path, "test", 8, 4).unwrap();
+            fs::remove_file(&path).unwrap();
+            assert_eq!(loaded.q.len(), ql.q.len());
+            assert_eq!(loaded.epsilon, 0.2_f64);
+            assert_eq!(loaded.episodes, 42);
+            for (a, b) in loaded.q.iter().zip(ql.q.iter()) {
+                assert_eq!(a.state, b.state);
+                assert_eq!(a.quality, b.quality);
+            }
+        }
+    }
+
+    #[test]
+    fn test_load_qlearner_validation() {
+        let ql = QLearner::new(2, 4);
+        let path = get_temp_path("ql_validation.bin");
+        save_qlearner(&ql, "test", &path).unwrap();
+        assert!(load_qlearner(&path, "test", 3, 4).is_err());
+        assert!(load_qlearner(&path, "test", 2, 3).is_err());
+        assert!(load_qlearner(&path, "other", 2, 4).is_err());
+        assert!(load_nn(&path, "test", 2, 4).is_err());
+        fs::remove_file(&path).unwrap();
+
+        let mut record = QTableRecord::from_qlearner(&ql, "test");
+        record.q[1].quality.pop();
+        assert!(record.into_qlearner("test", 2, 4).is_err());
+    }
+
+    #[test]
+    fn test_save_load_checkpoint() {
+        let mut config = GaConfig::new();
+        config.num_individuals = 4;
+        config.seed = Some(3);
+        config.mutation.method = MutationMethod::SelfAdaptive {
+            initial_sigma: 0.2,
+            tau: 0.3,
+        };
+        let mut pop = Population::from_config(&config);
+        pop.fitness = vec![1.0, 4.0, 2.0, 3.0];
+        pop.sigma[1] = 0.5;
+        let mut hall_of_fame = HallOfFame::new(config.hall_of_fame_size);
+        hall_of_fame.update(&pop, 9);
+        hall_of_fame.update(&pop, 10);
+        for name in ["ga.json", "ga.bin"].iter() {
+            let path = get_temp_path(name);
+            save_checkpoint(&pop, &hall_of_fame, 9, &config, "test", &path).unwrap();
+            let (mut loaded, loaded_hall_of_fame, generation, loaded_config) = load_checkpoint(&path).unwrap();
+            fs::remove_file(&path).unwrap();
+            assert_eq!(generation, 9);
+            assert_eq!(loaded_hall_of_fame.members.len(), 4);
+            assert_eq!(loaded_hall_of_fame.best().unwrap().fitness, 4.0);
+            assert!(loaded_hall_of_fame.best().unwrap().nn == pop.nn[1]);
+            assert_eq!(loaded_hall_of_fame.best().unwrap().evaluations, 2);
+            assert_eq!(loaded_config, config);
+            assert_eq!(loaded.fitness, pop.fitness);
+            assert_eq!(loaded.length, 4);
+            assert_eq!(loaded.sigma, pop.sigma);
+            // The restored random state breeds exactly the same next generation
+            let mut original = Population::new();
+            for (nn, sigma) in pop.nn.iter().zip(pop.sigma.iter()) {
+                original.add_with_sigma(nn.clone(), *sigma);
+            }
+            original.fitness = pop.fitness.clone();
+            original.rng = pop.rng.clone();
+            let next1 = original.create_next_generation(&config, 10);
+            let next2 = loaded.create_next_generation(&loaded_config, 10);
+            for (a, b) in next1.nn.iter().zip(next2.nn.iter()) {
+                assert_eq!(a.layers[0].weights, b.layers[0].weights);
+            }
+            assert_eq!(next1.sigma, next2.sigma);
+        }
+    }
+
+    #[test]
+    fn test_load_checkpoint_v1() {
+        // Written by the first checkpointing build, before the version was bumped
+        let path = get_temp_path("checkpoint_v1.bin");
+        fs::write(&path, &include_bytes!("../tests/fixtures/checkpoint_v1.bin")[..]).unwrap();
+        let (mut pop, hall_of_fame, generation, config) = load_checkpoint(&path).unwrap();
+        assert_eq!(generation, 7);
+        assert_eq!(config.num_individuals, 2);
+        assert_eq!(config.num_games, 3);
+        assert_eq!(config.num_generations, 11);
+        assert_eq!(config.layer_def, vec![[8, 4]]);
+        assert_eq!(config.seed, Some(5));
+        assert_eq!(config.selection, GaConfig::new().selection);
+        assert_eq!(config.elitism, GaConfig::new().elitism);
+        assert_eq!(pop.fitness, vec![1.0, 2.0]);
+        assert_eq!(pop.nn[1].layers[0].biases, vec![0.5, -0.5, 0.25, -0.25]);
+        assert!(hall_of_fame.members.is_empty());
+        assert_eq!(pop.sigma, vec![config.mutation.get_initial_sigma(); 2]);
+        assert_eq!(pop.rng.gen::<u64>(), Pcg32::seed_from_u64(5).gen::<u64>());
+
+        // Saving again writes the current version
+        save_checkpoint(&pop, &hall_of_fame, generation, &config, "test", &path).unwrap();
+        let (loaded, _, generation, _) = load_checkpoint(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(generation, 7);
+        assert_eq!(loaded.nn[0].layers[0].weights, pop.nn[0].layers[0].weights);
+
+        // A version this build does not know is named rather than misread
+        let mut record = CheckpointRecord::from_population(&pop, &hall_of_fame, generation, &config, "test");
+        record.version = CHECKPOINT_FILE_VERSION + 1;
+        let file = RecordFile {
+            format: FileFormat::Binary,
+            bytes: bincode::serialize(&record).unwrap(),
+        };
+        let e = read_checkpoint_record(&file).err().unwrap();
+        assert!(e.to_string().contains("unsupported checkpoint file version"));
+    }
+
+    #[test]
+    fn test_load_checkpoint_older_version() {
+        let mut config = GaConfig::new();
+        config.num_individuals = 2;
+        config.mutation.method = MutationMethod::Gaussian { sigma: 0.3 };
+        let pop = Population::from_config(&config);
+        let hall_of_fame = HallOfFame::new(config.hall_of_fame_size);
+        let mut record = CheckpointRecord::from_population(&pop, &hall_of_fame, 3, &config, "test");
+        record.version = 2;
+
+        // A version 2 JSON checkpoint, from before step sizes and the hall of fame were stored
+        let mut json = serde_json::to_value(&record).unwrap();
+        json.as_object_mut().unwrap().remove("sigma");
+        json.as_object_mut().unwrap().remove("hall_of_fame");
+        json["config"].as_object_mut().unwrap().remove("elitism");
+        for nn in json["nns"].as_array_mut().unwrap() {
+            nn["version"] = 1.into();
+            for layer in nn["layers"].as_array_mut().unwrap() {
+                layer.as_object_mut().unwrap().remove("recurrent_weights");
+            }
+        }
+        let path = get_temp_path("checkpoint_v2.json");
+        fs::write(&path, json.to_string()).unwrap();
+        let (loaded, loaded_hall_of_fame, generation, loaded_config) = load_checkpoint(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(generation, 3);
+        assert_eq!(loaded_config.elitism, GaConfig::new().elitism);
+        assert_eq!(loaded.sigma, vec![0.3; 2]);
+        assert!(loaded_hall_of_fame.members.is_empty());
+        assert!(loaded.nn[1] == pop.nn[1]);
+
+        // The same version in binary cannot be told apart from the current layout, so it is refused by name
+        let file = RecordFile {
+            format: FileFormat::Binary,
+            bytes: bincode::serialize(&record).unwrap(),
+        };
+        let e = read_checkpoint_record(&file).err().unwrap();
+        assert!(e.to_string().contains("cannot be migrated"));
+    }
+
+    #[test]
+    fn test_load_checkpoint_validation() {
+        let config = GaConfig::new();
+        let mut pop = Population::new_defined(2, &config.layer_def, &[], &[]);
+        pop.fitness = vec![1.0];
+        let hall_of_fame = HallOfFame::new(config.hall_of_fame_size);
+        let record = CheckpointRecord::from_population(&pop, &hall_of_fame, 0, &config, "test");
+        assert!(record.to_population().is_err());
+        let mut record = CheckpointRecord::from_population(&pop, &hall_of_fame, 0, &config, "test");
+        record.fitness.push(2.0);
+        record.config.layer_def = vec![[8, 8], [8, 3]];
+        assert!(record.to_population().is_err());
+    }
+}
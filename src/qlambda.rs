@@ -0,0 +1,177 @@
+use crate::constants::*;
+use crate::error::Result;
+use crate::game::{Brain, EpisodeOutcome, Transition};
+use crate::qlearn::{get_index_max_float, QLearner};
+
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum LambdaMethod {
+    WatkinsQ,
+    Sarsa,
+}
+
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum TraceType {
+    Accumulating,
+    Replacing,
+}
+
+pub struct LambdaLearner {
+    pub ql: QLearner,
+    pub lambda: f64,
+    pub method: LambdaMethod,
+    pub trace_type: TraceType,
+    pub traces: Vec<Vec<f64>>,
+    active: Vec<usize>,
+    next_action: Option<usize>,
+}
+
+impl LambdaLearner {
+    pub fn new(len_states: usize, num_actions: usize, method: LambdaMethod, trace_type: TraceType) -> LambdaLearner {
+        let ql = QLearner::new(len_states, num_actions);
+        LambdaLearner {
+            traces: vec![vec![0.0_f64; num_actions]; ql.q.len()],
+            ql,
+            lambda: LAMBDA,
+            method,
+            trace_type,
+            active: Vec::new(),
+            next_action: None,
+        }
+    }
+
+    fn get_state_index(&mut self, state: &[f64]) -> usize {
+        let index = self.ql.get_state_index(state);
+        while self.traces.len() < self.ql.q.len() {
+            self.traces.push(vec![0.0_f64; self.ql.num_actions]);
+        }
+        index
+    }
+
+    fn clear_traces(&mut self) {
+        for &i in self.active.iter() {
+            for e in self.traces[i].iter_mut() {
+                *e = 0.0_f64;
+            }
+        }
+        self.active.clear();
+    }
+}
+
+impl Brain for LambdaLearner {
+    fn get_action(&mut self, state: &[f64]) -> Result<usize> {
+        // The action for this state was already chosen (and bootstrapped from) during the last update
+        if let Some(action) = self.next_action.take() {
+            return Ok(action);
+        }
+        let index = self.get_state_index(state);
+        Ok(self.ql.get_epsilon_greedy_action(index))
+    }
+
+    fn train(&mut self, transition: &Transition) -> Option<bool> {
+        let action = transition.action;
+        if action >= self.ql.num_actions {
+            return None;
+        }
+        if !self.ql.training {
+            return Some(false);
+        }
+        let ii = self.get_state_index(transition.state);
+        let fi = self.get_state_index(transition.next_state);
+
+        let greedy_action = get_index_max_float(&self.ql.q[fi].quality)?;
+        let next_action = self.ql.get_epsilon_greedy_action(fi);
+        let target_action = match self.method {
+            LambdaMethod::WatkinsQ => greedy_action,
+            LambdaMethod::Sarsa => next_action,
+        };
+        let next_value = if transition.done {
+            0.0_f64
+        } else {
+            self.ql.q[fi].quality[target_action]
+        };
+        let delta = transition.reward + self.ql.discount_factor * next_value - self.ql.q[ii].quality[action];
+
+        match self.trace_type {
+            TraceType::Accumulating => self.traces[ii][action] += 1.0_f64,
+            TraceType::Replacing => self.traces[ii][action] = 1.0_f64,
+        }
+        if !self.active.contains(&ii) {
+            self.active.push(ii);
+        }
+
+        // Watkins's Q(lambda) only follows the greedy policy, so an exploratory action cuts the traces
+        let exploratory = self.ql.q[fi].quality[next_action] < self.ql.q[fi].quality[greedy_action];
+        let cut = self.method == LambdaMethod::WatkinsQ && exploratory;
+        let decay = self.ql.discount_factor * self.lambda;
+        for &i in self.active.iter() {
+            for a in 0..self.ql.num_actions {
+                self.ql.q[i].quality[a] += self.ql.learning_rate * delta * self.traces[i][a];
+                self.traces[i][a] = if cut { 0.0_f64 } else { self.traces[i][a] * decay };
+            }
+        }
+        if cut {
+            self.active.clear();
+        }
+
+        self.next_action = Some(next_action);
+        Some(true)
+    }
+
+    fn on_episode_end(&mut self, outcome: &EpisodeOutcome) {
+        self.clear_traces();
+        self.next_action = None;
+        self.ql.on_episode_end(outcome);
+    }
+
+    fn set_training(&mut self, training: bool) {
+        self.ql.set_training(training);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::Game;
+
+    #[test]
+    fn test_lambda_learner_new() {
+        let ll = LambdaLearner::new(8, 4, LambdaMethod::WatkinsQ, TraceType::Accumulating);
+        assert_eq!(ll.ql.q.len(), 2_usize.pow(8));
+        assert_eq!(ll.traces.len(), ll.ql.q.len());
+        assert_eq!(ll.traces[0].len(), 4);
+    }
+
+    #[test]
+    fn test_lambda_learner_train_propagates() {
+        for method in [LambdaMethod::WatkinsQ, LambdaMethod::Sarsa].iter() {
+            let mut ll = LambdaLearner::new(2, 4, *method, TraceType::Replacing);
+            let s0 = vec![0.0_f64, 0.0_f64];
+            let s1 = vec![0.0_f64, 1.0_f64];
+            let s2 = vec![1.0_f64, 0.0_f64];
+            let a0 = ll.get_action(&s0).unwrap();
+            ll.train(&Transition::new(&s0, a0, 0.0_f64, &s1, false));
+            let a1 = ll.get_action(&s1).unwrap();
+            ll.train(&Transition::new(&s1, a1, 10.0_f64, &s2, false));
+            // The reward reaches the first state after a single update thanks to the trace
+            assert!(ll.ql.q[0].quality[a0] > 0.0_f64);
+            assert!(ll.ql.q[1].quality[a1] > ll.ql.q[0].quality[a0]);
+        }
+    }
+
+    #[test]
+    fn test_lambda_learner_traces() {
+        let mut ll = LambdaLearner::new(2, 4, LambdaMethod::Sarsa, TraceType::Accumulating);
+        let s0 = vec![0.0_f64, 0.0_f64];
+        let transition = Transition::new(&s0, 0, 0.0_f64, &s0, false);
+        ll.train(&transition);
+        ll.train(&transition);
+        assert!(ll.traces[0][0] > 1.0_f64);
+        let mut ll = LambdaLearner::new(2, 4, LambdaMethod::Sarsa, TraceType::Replacing);
+        ll.train(&transition);
+        ll.train(&transition);
+        assert!(ll.traces[0][0] <= 1.0_f64);
+        ll.on_episode_end(&Game::new().get_outcome());
+        assert_eq!(ll.traces[0][0], 0.0_f64);
+        assert!(ll.next_action.is_none());
+    }
+}
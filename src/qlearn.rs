@@ -2,10 +2,13 @@ extern crate rand;
 
 use itertools::Itertools;
 use rand::Rng;
+use serde::{Deserialize, Serialize};
 
 use crate::constants::*;
-use crate::game::Brain;
+use crate::error::{Error, Result};
+use crate::game::{Brain, EpisodeOutcome, Transition};
 
+#[derive(Serialize, Deserialize)]
 pub struct StateAction {
     pub state: Vec<f64>,
     pub quality: Vec<f64>,
@@ -29,6 +32,9 @@ pub struct QLearner {
     pub num_states: usize,
     pub len_states: usize,
     pub num_actions: usize,
+    pub episodes: u32,
+    // Greedy and frozen when false
+    pub training: bool,
 }
 
 impl QLearner {
@@ -47,20 +53,60 @@ impl QLearner {
             discount_factor: DISCOUNT_FACTOR,
             len_states,
             num_actions,
+            episodes: 0,
+            training: true,
         }
     }
+
+    pub fn get_state_index(&mut self, state: &[f64]) -> usize {
+        match self.q.iter().position(|sa| sa.state == *state) {
+            Some(index) => index,
+            None => {
+                self.q.push(StateAction::new(state.to_vec(), self.num_actions));
+                self.q.len() - 1
+            }
+        }
+    }
+
+    pub fn get_epsilon_greedy_action(&self, index: usize) -> usize {
+        let mut rng = rand::thread_rng();
+        if self.training && rng.gen::<f64>() > 1.0_f64 - self.epsilon {
+            rng.gen_range(0, self.num_actions)
+        } else {
+            get_index_max_float(&self.q[index].quality).unwrap_or(0)
+        }
+    }
+
+    // A final move has no future value to bootstrap from
+    pub fn update(&mut self, index_initial: usize, action: usize, reward: f64, index_final: usize, done: bool) {
+        let max_final = if done {
+            0.0_f64
+        } else {
+            *get_max_float(&self.q[index_final].quality).unwrap()
+        };
+        self.q[index_initial].quality[action] = self.q[index_initial].quality[action]
+            + self.learning_rate * (reward + self.discount_factor * max_final - self.q[index_initial].quality[action]);
+    }
 }
 
 impl Brain for QLearner {
-    fn get_action(&mut self, state: &Vec<f64>) -> Option<usize> {
+    fn get_action(&mut self, state: &[f64]) -> Result<usize> {
         // Following the epsilon-greedy policy
         let mut rng = rand::thread_rng();
-        if rng.gen::<f64>() > 1.0_f64 - self.epsilon {
+        let action = if self.training && rng.gen::<f64>() > 1.0_f64 - self.epsilon {
             Some(rng.gen_range(0, self.num_actions))
         } else {
             let found = self.q.iter().find(|sa| sa.state == *state);
             match found {
                 Some(sa) => get_index_max_float(&sa.quality),
+                // A frozen table does not grow, so an unseen state plays the first action of a zero row
+                None if !self.training => {
+                    if self.num_actions > 0 {
+                        Some(0)
+                    } else {
+                        None
+                    }
+                }
                 None => {
                     let state_copy: Vec<f64> = state.iter().copied().map(|x| x).collect();
                     let sa = StateAction::new(state_copy, self.num_actions);
@@ -69,27 +115,42 @@ impl Brain for QLearner {
                     action
                 }
             }
-        }
+        };
+        action.ok_or_else(|| Error::InvalidConfig("the Q-table has no actions".to_string()))
     }
 
-    fn train(&mut self, state_initial: &Vec<f64>, action: usize, reward: f64, state_final: &Vec<f64>) -> Option<bool> {
-        let index_initial = self.q.iter().position(|sa| sa.state == *state_initial);
-        let index_final = self.q.iter().position(|sa| sa.state == *state_final);
+    fn train(&mut self, transition: &Transition) -> Option<bool> {
+        if !self.training {
+            return Some(false);
+        }
+        let index_initial = self.q.iter().position(|sa| sa.state == *transition.state);
+        let index_final = self.q.iter().position(|sa| sa.state == *transition.next_state);
         if index_initial == None || index_final == None {
             None
         } else {
-            let ii = index_initial.unwrap();
-            let fi = index_final.unwrap();
-            self.q[ii].quality[action] = self.q[ii].quality[action]
-                + self.learning_rate
-                    * (reward + self.discount_factor * get_max_float(&self.q[fi].quality).unwrap()
-                        - self.q[ii].quality[action]);
+            self.update(
+                index_initial.unwrap(),
+                transition.action,
+                transition.reward,
+                index_final.unwrap(),
+                transition.done,
+            );
             Some(true)
         }
     }
+
+    fn on_episode_end(&mut self, _outcome: &EpisodeOutcome) {
+        if self.training {
+            self.episodes += 1;
+        }
+    }
+
+    fn set_training(&mut self, training: bool) {
+        self.training = training;
+    }
 }
 
-fn get_index_max_float(input: &Vec<f64>) -> Option<usize> {
+pub fn get_index_max_float(input: &[f64]) -> Option<usize> {
     input
         .iter()
         .enumerate()
@@ -97,7 +158,7 @@ fn get_index_max_float(input: &Vec<f64>) -> Option<usize> {
         .map(|(index, _)| index)
 }
 
-fn get_max_float(input: &Vec<f64>) -> Option<&f64> {
+pub fn get_max_float(input: &[f64]) -> Option<&f64> {
     input
         .iter()
         .max_by(|&a, &b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal))
@@ -106,6 +167,7 @@ fn get_max_float(input: &Vec<f64>) -> Option<&f64> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::game::Game;
 
     #[test]
     fn get_permutations() {
@@ -149,6 +211,55 @@ mod tests {
         let state = vec![0.0_f64; 8];
         let action = ql.get_action(&state);
         println!("{:?}", get_index_max_float(&ql.q[0].quality));
-        assert_eq!(action, get_index_max_float(&ql.q[0].quality));
+        assert_eq!(action.ok(), get_index_max_float(&ql.q[0].quality));
+    }
+
+    #[test]
+    fn test_qlearner_get_state_index() {
+        let mut ql = QLearner::new(2, 4);
+        assert_eq!(ql.get_state_index(&[0.0_f64, 1.0_f64]), 1);
+        assert_eq!(ql.q.len(), 4);
+        assert_eq!(ql.get_state_index(&[0.5_f64, 0.5_f64]), 4);
+        assert_eq!(ql.q.len(), 5);
+    }
+
+    #[test]
+    fn test_qlearner_update_done() {
+        let mut ql = QLearner::new(2, 4);
+        ql.q[1].quality = vec![100.0_f64; 4];
+        ql.update(0, 0, 1.0_f64, 1, true);
+        // A final move is not bootstrapped from the next state
+        assert!((ql.q[0].quality[0] - ql.learning_rate).abs() < 1e-12);
+        ql.update(0, 1, 1.0_f64, 1, false);
+        assert!(ql.q[0].quality[1] > ql.q[0].quality[0]);
+    }
+
+    #[test]
+    fn test_qlearner_set_training() {
+        let mut ql = QLearner::new(2, 4);
+        ql.epsilon = 1.0_f64;
+        ql.q[0].quality[2] = 1.0_f64;
+        ql.set_training(false);
+        // Frozen learners act greedily and learn nothing
+        for _ in 0..20 {
+            assert_eq!(ql.get_action(&[0.0_f64, 0.0_f64]).unwrap(), 2);
+        }
+        // Nor do they add the states they have not seen
+        assert_eq!(ql.get_action(&[0.5_f64, 0.5_f64]).unwrap(), 0);
+        assert_eq!(ql.q.len(), 4);
+        let state = [0.0_f64, 0.0_f64];
+        assert_eq!(
+            ql.train(&Transition::new(&state, 0, 1.0_f64, &state, false)),
+            Some(false)
+        );
+        assert_eq!(ql.q[0].quality[0], 0.0_f64);
+        ql.on_episode_end(&Game::new().get_outcome());
+        assert_eq!(ql.episodes, 0);
+        ql.set_training(true);
+        assert_eq!(
+            ql.train(&Transition::new(&state, 0, 1.0_f64, &state, false)),
+            Some(true)
+        );
+        assert!(ql.q[0].quality[0] > 0.0_f64);
     }
 }
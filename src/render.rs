@@ -58,6 +58,8 @@ impl Render {
     pub fn run_brain<T: Brain>(&mut self, brain: &mut T) {
         let mut game = Game::new();
         game.init();
+        brain.set_training(false);
+        brain.on_episode_start();
 
         while let Some(e) = self.events.next(&mut self.window) {
             if let Some(args) = e.render_args() {
@@ -65,13 +67,27 @@ impl Render {
             }
 
             if let Some(args) = e.update_args() {
-                let dir = game.get_dir_from_brain(brain);
-                game.update(dir);
-                game.next_tick(args.dt);
+                if game.snake.alive {
+                    let dir = match game.get_dir_from_brain(brain) {
+                        Ok(dir) => dir,
+                        Err(e) => {
+                            println!("The brain could not play: {}", e);
+                            return;
+                        }
+                    };
+                    game.update(dir);
+                    game.next_tick(args.dt);
+                    if !game.snake.alive {
+                        brain.on_episode_end(&game.get_outcome());
+                    }
+                }
             }
 
             if let Some(button) = e.press_args() {
                 self.handle_events(button, &mut game);
+                if button == Button::Keyboard(Key::Space) {
+                    brain.on_episode_start();
+                }
             }
         }
     }
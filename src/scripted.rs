@@ -0,0 +1,501 @@
+use rand::{Rng, SeedableRng};
+use rand_pcg::Pcg32;
+use std::collections::VecDeque;
+
+use crate::constants::*;
+use crate::error::{Error, Result};
+use crate::game::{Brain, Game, Position};
+
+// Moves of the actions in the order of Game::get_direction_from_index
+const MOVES: [(i16, i16); 4] = [(1, 0), (0, -1), (-1, 0), (0, 1)];
+
+fn get_needs_game_error() -> Error {
+    Error::InvalidConfig("scripted brains play from the whole game, not from network inputs".to_string())
+}
+
+fn get_neighbour(pos: Position, action: usize) -> Option<Position> {
+    let (dx, dy) = MOVES[action];
+    let x = pos.x as i16 + dx;
+    let y = pos.y as i16 + dy;
+    if x < 0 || y < 0 || x >= BOARD_WIDTH as i16 || y >= BOARD_HEIGHT as i16 {
+        None
+    } else {
+        Some(Position { x: x as u8, y: y as u8 })
+    }
+}
+
+fn get_cell(pos: Position) -> usize {
+    pos.y as usize * BOARD_WIDTH as usize + pos.x as usize
+}
+
+fn get_body(game: &Game) -> VecDeque<Position> {
+    game.snake.body.iter().map(|block| block.position).collect()
+}
+
+fn get_blocked(body: &VecDeque<Position>) -> Vec<bool> {
+    let mut blocked = vec![false; BOARD_WIDTH as usize * BOARD_HEIGHT as usize];
+    for pos in body {
+        blocked[get_cell(*pos)] = true;
+    }
+    blocked
+}
+
+// The head moves onto a free cell. As in the game, the tail still blocks it on this move
+fn get_safe_actions(body: &VecDeque<Position>) -> Vec<(usize, Position)> {
+    (0..MOVES.len())
+        .filter_map(|a| get_neighbour(body[0], a).map(|pos| (a, pos)))
+        .filter(|(_, pos)| !body.contains(pos))
+        .collect()
+}
+
+// The body after the head moves to pos, growing when it eats
+fn get_moved_body(body: &VecDeque<Position>, pos: Position, food: Position) -> VecDeque<Position> {
+    let mut moved = body.clone();
+    moved.push_front(pos);
+    if pos != food {
+        moved.pop_back();
+    }
+    moved
+}
+
+// Breadth first search from start over the free cells, giving every reached cell the one it was reached from
+fn get_parents(start: Position, blocked: &[bool]) -> Vec<Option<Position>> {
+    let mut parents: Vec<Option<Position>> = vec![None; blocked.len()];
+    parents[get_cell(start)] = Some(start);
+    let mut queue = VecDeque::from(vec![start]);
+    while let Some(pos) = queue.pop_front() {
+        for a in 0..MOVES.len() {
+            if let Some(next) = get_neighbour(pos, a) {
+                if !blocked[get_cell(next)] && parents[get_cell(next)].is_none() {
+                    parents[get_cell(next)] = Some(pos);
+                    queue.push_back(next);
+                }
+            }
+        }
+    }
+    parents
+}
+
+// The shortest path from start to goal, without start
+fn get_path(start: Position, goal: Position, blocked: &[bool]) -> Option<Vec<Position>> {
+    let parents = get_parents(start, blocked);
+    parents[get_cell(goal)]?;
+    let mut path = vec![goal];
+    let mut pos = goal;
+    while let Some(parent) = parents[get_cell(pos)] {
+        if parent == start {
+            path.reverse();
+            return Some(path);
+        }
+        path.push(parent);
+        pos = parent;
+    }
+    None
+}
+
+// Number of moves from start to every reachable cell
+fn get_distances(start: Position, blocked: &[bool]) -> Vec<Option<u32>> {
+    let mut distances: Vec<Option<u32>> = vec![None; blocked.len()];
+    distances[get_cell(start)] = Some(0);
+    let mut queue = VecDeque::from(vec![start]);
+    while let Some(pos) = queue.pop_front() {
+        let distance = distances[get_cell(pos)].unwrap_or(0);
+        for a in 0..MOVES.len() {
+            if let Some(next) = get_neighbour(pos, a) {
+                if !blocked[get_cell(next)] && distances[get_cell(next)].is_none() {
+                    distances[get_cell(next)] = Some(distance + 1);
+                    queue.push_back(next);
+                }
+            }
+        }
+    }
+    distances
+}
+
+// Whether the head can still follow its tail, which keeps the snake from shutting itself in
+fn get_tail_reachable(body: &VecDeque<Position>) -> bool {
+    let tail = match body.back() {
+        Some(tail) => *tail,
+        None => return false,
+    };
+    let distances = get_distances(tail, &get_blocked(body));
+    get_safe_actions(body)
+        .iter()
+        .any(|(_, pos)| distances[get_cell(*pos)].is_some())
+}
+
+fn get_food_dist(pos: Position, food: Position) -> i16 {
+    (pos.x as i16 - food.x as i16).abs() + (pos.y as i16 - food.y as i16).abs()
+}
+
+// Closest move to the food that does not die at once, or straight on when every move dies
+fn get_greedy_action(body: &VecDeque<Position>, food: Position) -> usize {
+    get_safe_actions(body)
+        .iter()
+        .min_by_key(|(_, pos)| get_food_dist(*pos, food))
+        .map(|(a, _)| *a)
+        .unwrap_or(0)
+}
+
+// Chance level: every move is picked uniformly at random
+pub struct RandomBrain {
+    pub rng: Pcg32,
+}
+
+impl RandomBrain {
+    pub fn new() -> RandomBrain {
+        RandomBrain {
+            rng: Pcg32::from_rng(rand::thread_rng()).unwrap(),
+        }
+    }
+}
+
+impl Brain for RandomBrain {
+    fn get_action(&mut self, _inputs: &[f64]) -> Result<usize> {
+        Ok(self.rng.gen_range(0, MOVES.len()))
+    }
+}
+
+// Picks uniformly among the moves that do not die at once
+pub struct SafeRandomBrain {
+    pub rng: Pcg32,
+}
+
+impl SafeRandomBrain {
+    pub fn new() -> SafeRandomBrain {
+        SafeRandomBrain {
+            rng: Pcg32::from_rng(rand::thread_rng()).unwrap(),
+        }
+    }
+}
+
+impl Brain for SafeRandomBrain {
+    fn get_action(&mut self, _inputs: &[f64]) -> Result<usize> {
+        Err(get_needs_game_error())
+    }
+
+    fn get_game_action(&mut self, game: &Game, _inputs: &[f64]) -> Result<usize> {
+        let safe = get_safe_actions(&get_body(game));
+        if safe.is_empty() {
+            Ok(self.rng.gen_range(0, MOVES.len()))
+        } else {
+            Ok(safe[self.rng.gen_range(0, safe.len())].0)
+        }
+    }
+}
+
+// Heads for the food, avoiding immediate death
+pub struct GreedyBrain;
+
+impl Brain for GreedyBrain {
+    fn get_action(&mut self, _inputs: &[f64]) -> Result<usize> {
+        Err(get_needs_game_error())
+    }
+
+    fn get_game_action(&mut self, game: &Game, _inputs: &[f64]) -> Result<usize> {
+        Ok(get_greedy_action(&get_body(game), game.food.position))
+    }
+}
+
+// Takes the shortest path to the food when the tail can still be reached after eating,
+// and otherwise follows its tail the long way round until it can
+pub struct PathfinderBrain;
+
+impl PathfinderBrain {
+    fn get_path_action(body: &VecDeque<Position>, food: Position) -> Option<usize> {
+        let path = get_path(body[0], food, &get_blocked(body))?;
+        let mut moved = body.clone();
+        for pos in path.iter() {
+            moved = get_moved_body(&moved, *pos, food);
+        }
+        if !get_tail_reachable(&moved) {
+            return None;
+        }
+        get_safe_actions(body)
+            .iter()
+            .find(|(_, pos)| *pos == path[0])
+            .map(|(a, _)| *a)
+    }
+
+    fn get_tail_action(body: &VecDeque<Position>, food: Position) -> Option<usize> {
+        let distances = get_distances(*body.back()?, &get_blocked(body));
+        get_safe_actions(body)
+            .iter()
+            .filter(|(_, pos)| get_tail_reachable(&get_moved_body(body, *pos, food)))
+            .filter_map(|(a, pos)| distances[get_cell(*pos)].map(|d| (*a, d)))
+            .max_by_key(|(_, d)| *d)
+            .map(|(a, _)| a)
+    }
+}
+
+impl Brain for PathfinderBrain {
+    fn get_action(&mut self, _inputs: &[f64]) -> Result<usize> {
+        Err(get_needs_game_error())
+    }
+
+    fn get_game_action(&mut self, game: &Game, _inputs: &[f64]) -> Result<usize> {
+        let body = get_body(game);
+        let food = game.food.position;
+        Ok(PathfinderBrain::get_path_action(&body, food)
+            .or_else(|| PathfinderBrain::get_tail_action(&body, food))
+            .unwrap_or_else(|| get_greedy_action(&body, food)))
+    }
+}
+
+// Follows a cycle through every cell of the board. Once the body lies along the cycle the next cell
+// is always free, so the snake eats every food in turn and fills the board
+pub struct HamiltonianBrain {
+    cycle: Vec<Position>,
+    // Position of every cell in the cycle
+    order: Vec<usize>,
+    // Whether the cycle is followed backwards, chosen to match the way the body lies
+    reversed: bool,
+}
+
+impl HamiltonianBrain {
+    pub fn new() -> Result<HamiltonianBrain> {
+        HamiltonianBrain::with_size(BOARD_WIDTH, BOARD_HEIGHT)
+    }
+
+    // Runs along the first row, snakes back through the other columns and returns up the first column,
+    // which needs an even number of rows, or of columns when the board is transposed
+    fn with_size(width: u8, height: u8) -> Result<HamiltonianBrain> {
+        if width < 2 || height < 2 || (width % 2 == 1 && height % 2 == 1) {
+            return Err(Error::InvalidConfig(format!(
+                "a {}x{} board has no Hamiltonian cycle",
+                width, height
+            )));
+        }
+        let transposed = height % 2 == 1;
+        let (columns, rows) = if transposed { (height, width) } else { (width, height) };
+        let mut cells: Vec<(u8, u8)> = (0..columns).map(|x| (x, 0)).collect();
+        for y in 1..rows {
+            if y % 2 == 1 {
+                cells.extend((1..columns).rev().map(|x| (x, y)));
+            } else {
+                cells.extend((1..columns).map(|x| (x, y)));
+            }
+        }
+        cells.extend((1..rows).rev().map(|y| (0, y)));
+
+        let cycle: Vec<Position> = cells
+            .iter()
+            .map(|&(x, y)| {
+                if transposed {
+                    Position { x: y, y: x }
+                } else {
+                    Position { x, y }
+                }
+            })
+            .collect();
+        let mut order = vec![0; cycle.len()];
+        for (i, pos) in cycle.iter().enumerate() {
+            order[pos.y as usize * width as usize + pos.x as usize] = i;
+        }
+        Ok(HamiltonianBrain {
+            cycle,
+            order,
+            reversed: false,
+        })
+    }
+
+    fn get_next(&self, pos: Position, reversed: bool) -> Position {
+        let len = self.cycle.len();
+        let i = self.order[get_cell(pos)];
+        if reversed {
+            self.cycle[(i + len - 1) % len]
+        } else {
+            self.cycle[(i + 1) % len]
+        }
+    }
+
+    fn get_aligned(&self, body: &VecDeque<Position>, reversed: bool) -> bool {
+        body.iter()
+            .zip(body.iter().skip(1))
+            .all(|(front, back)| self.get_next(*back, reversed) == *front)
+    }
+}
+
+impl Brain for HamiltonianBrain {
+    fn get_action(&mut self, _inputs: &[f64]) -> Result<usize> {
+        Err(get_needs_game_error())
+    }
+
+    fn get_game_action(&mut self, game: &Game, _inputs: &[f64]) -> Result<usize> {
+        let body = get_body(game);
+        if !self.get_aligned(&body, self.reversed) && self.get_aligned(&body, !self.reversed) {
+            self.reversed = !self.reversed;
+        }
+        let next = self.get_next(body[0], self.reversed);
+        // A body that does not lie along the cycle may block it, until it has followed the cycle for a while
+        Ok(get_safe_actions(&body)
+            .iter()
+            .find(|(_, pos)| *pos == next)
+            .map(|(a, _)| *a)
+            .unwrap_or_else(|| get_greedy_action(&body, game.food.position)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::{Block, Direction};
+
+    fn set_body(game: &mut Game, cells: &[(u8, u8)]) {
+        game.snake.body = cells
+            .iter()
+            .map(|&(x, y)| Block {
+                position: Position { x, y },
+                colour: GREEN,
+            })
+            .collect();
+    }
+
+    #[test]
+    fn test_get_neighbour() {
+        let pos = Position { x: 0, y: 0 };
+        assert!(get_neighbour(pos, 0) == Some(Position { x: 1, y: 0 }));
+        assert!(get_neighbour(pos, 1).is_none());
+        assert!(get_neighbour(pos, 2).is_none());
+        assert!(get_neighbour(pos, 3) == Some(Position { x: 0, y: 1 }));
+        let corner = Position {
+            x: BOARD_WIDTH - 1,
+            y: BOARD_HEIGHT - 1,
+        };
+        assert!(get_neighbour(corner, 0).is_none());
+        assert!(get_neighbour(corner, 3).is_none());
+    }
+
+    #[test]
+    fn test_get_path() {
+        let mut game = Game::new();
+        set_body(&mut game, &[(2, 1), (1, 1), (1, 0)]);
+        let body = get_body(&game);
+        let path = get_path(body[0], Position { x: 0, y: 0 }, &get_blocked(&body)).unwrap();
+        // The body is in the way of the straight route
+        assert_eq!(path.len(), 5);
+        assert!(path[4] == Position { x: 0, y: 0 });
+        set_body(&mut game, &[(1, 0), (0, 1), (1, 1)]);
+        let body = get_body(&game);
+        assert!(get_path(body[2], Position { x: 0, y: 0 }, &get_blocked(&body)).is_none());
+    }
+
+    #[test]
+    fn test_scripted_get_action() {
+        let game = Game::new();
+        assert!(GreedyBrain.get_action(&game.get_nn_inputs()).is_err());
+        assert!(PathfinderBrain.get_action(&game.get_nn_inputs()).is_err());
+        assert!(game.get_dir_from_brain(&mut GreedyBrain).is_ok());
+    }
+
+    #[test]
+    fn test_random_brains() {
+        let mut game = Game::new();
+        game.init();
+        let mut random = RandomBrain::new();
+        random.rng = Pcg32::seed_from_u64(1);
+        let actions: Vec<usize> = (0..100).map(|_| random.get_action(&[]).unwrap()).collect();
+        assert!((0..MOVES.len()).all(|a| actions.contains(&a)));
+        // Only up and down are safe next to the right wall
+        set_body(&mut game, &[(9, 5), (8, 5), (7, 5)]);
+        let mut safe = SafeRandomBrain::new();
+        assert!(safe.get_action(&[]).is_err());
+        let actions: Vec<usize> = (0..100).map(|_| safe.get_game_action(&game, &[]).unwrap()).collect();
+        assert!(actions.iter().all(|a| *a == 1 || *a == 3));
+        assert!(actions.contains(&1) && actions.contains(&3));
+    }
+
+    #[test]
+    fn test_greedy_avoids_death() {
+        let mut game = Game::new();
+        game.init();
+        // The food is straight ahead, behind the snake's own body
+        set_body(&mut game, &[(3, 5), (3, 4), (4, 4), (4, 5), (4, 6)]);
+        game.snake.direction = Direction::DOWN;
+        game.food.position = Position { x: 6, y: 5 };
+        let action = GreedyBrain.get_game_action(&game, &[]).unwrap();
+        assert!(action == 2 || action == 3);
+    }
+
+    #[test]
+    fn test_get_tail_reachable() {
+        let mut game = Game::new();
+        assert!(get_tail_reachable(&get_body(&game)));
+        // The head is shut in a pocket along the top wall, away from the tail
+        set_body(
+            &mut game,
+            &[(0, 0), (0, 1), (1, 1), (2, 1), (3, 1), (3, 0), (4, 0), (5, 0)],
+        );
+        assert!(!get_tail_reachable(&get_body(&game)));
+    }
+
+    #[test]
+    fn test_pathfinder_avoids_trap() {
+        let mut game = Game::new();
+        game.init();
+        let mut brain = PathfinderBrain;
+        // In open space it takes the shortest path
+        game.food.position = Position { x: 5, y: 8 };
+        assert_eq!(brain.get_game_action(&game, &[]).unwrap(), 3);
+        // Eating the food in the pocket above would shut the snake in, so it turns away
+        set_body(
+            &mut game,
+            &[
+                (0, 2),
+                (1, 2),
+                (2, 2),
+                (3, 2),
+                (3, 1),
+                (3, 0),
+                (4, 0),
+                (5, 0),
+                (6, 0),
+                (7, 0),
+            ],
+        );
+        game.snake.direction = Direction::LEFT;
+        game.food.position = Position { x: 2, y: 0 };
+        assert_eq!(get_greedy_action(&get_body(&game), game.food.position), 1);
+        assert_eq!(brain.get_game_action(&game, &[]).unwrap(), 3);
+    }
+
+    #[test]
+    fn test_pathfinder_plays() {
+        let mut game = Game::new();
+        let mut brain = PathfinderBrain;
+        let mut score = 0;
+        for _ in 0..20 {
+            game.init();
+            game.run_brain(&mut brain, |_, _, _, _, _| 0.0_f64).unwrap();
+            score += game.score;
+        }
+        assert!(score > 20 * 5);
+    }
+
+    #[test]
+    fn test_hamiltonian_with_size() {
+        let brain = HamiltonianBrain::with_size(4, 3).unwrap();
+        assert_eq!(brain.cycle.len(), 12);
+        for (i, pos) in brain.cycle.iter().enumerate() {
+            let next = brain.cycle[(i + 1) % brain.cycle.len()];
+            assert_eq!(get_food_dist(*pos, next), 1);
+        }
+        assert!(HamiltonianBrain::with_size(3, 3).is_err());
+        assert!(HamiltonianBrain::with_size(1, 4).is_err());
+    }
+
+    #[test]
+    fn test_hamiltonian_fills_board() {
+        let mut game = Game::new();
+        game.init();
+        let mut brain = HamiltonianBrain::new().unwrap();
+        // Without the time limit of brain-driven games the snake only stops once the board is full
+        while game.snake.alive {
+            let dir = game.get_dir_from_brain(&mut brain).unwrap();
+            game.update(dir);
+            game.next_tick(1.0_f64);
+        }
+        assert!(game.is_board_full());
+        assert_eq!(game.score as usize, BOARD_WIDTH as usize * BOARD_HEIGHT as usize - 3);
+    }
+}
@@ -0,0 +1,156 @@
+extern crate rand;
+
+use rand::{Rng, RngCore};
+use serde::{Deserialize, Serialize};
+
+pub trait Selection {
+    // Picks the index of one parent, given the fitness of the population and its indices sorted best first
+    fn select(&self, fitness: &[f64], sorted_index: &[usize], rng: &mut dyn RngCore) -> usize;
+}
+
+pub struct Tournament {
+    pub size: usize,
+}
+
+impl Selection for Tournament {
+    fn select(&self, fitness: &[f64], _sorted_index: &[usize], rng: &mut dyn RngCore) -> usize {
+        let mut best = rng.gen_range(0, fitness.len());
+        for _ in 1..self.size {
+            let challenger = rng.gen_range(0, fitness.len());
+            if fitness[challenger] > fitness[best] {
+                best = challenger;
+            }
+        }
+        best
+    }
+}
+
+pub struct Roulette;
+
+impl Selection for Roulette {
+    fn select(&self, fitness: &[f64], sorted_index: &[usize], rng: &mut dyn RngCore) -> usize {
+        // Fitness can be negative, so the wheel is shifted to start at the worst individual
+        let min = fitness.iter().cloned().fold(f64::INFINITY, f64::min);
+        let total: f64 = fitness.iter().map(|f| f - min).sum();
+        if total <= 0.0_f64 {
+            return rng.gen_range(0, fitness.len());
+        }
+        let mut pick = rng.gen::<f64>() * total;
+        for (i, f) in fitness.iter().enumerate() {
+            pick -= f - min;
+            if pick <= 0.0_f64 {
+                return i;
+            }
+        }
+        sorted_index[0]
+    }
+}
+
+pub struct Rank;
+
+impl Selection for Rank {
+    fn select(&self, _fitness: &[f64], sorted_index: &[usize], rng: &mut dyn RngCore) -> usize {
+        // Linear ranking: the best of n individuals has weight n, the worst has weight 1
+        let n = sorted_index.len();
+        let mut pick = rng.gen_range(0, n * (n + 1) / 2);
+        for (rank, i) in sorted_index.iter().enumerate() {
+            let weight = n - rank;
+            if pick < weight {
+                return *i;
+            }
+            pick -= weight;
+        }
+        sorted_index[n - 1]
+    }
+}
+
+pub struct Truncation {
+    pub fraction: f64,
+}
+
+impl Selection for Truncation {
+    fn select(&self, _fitness: &[f64], sorted_index: &[usize], rng: &mut dyn RngCore) -> usize {
+        let n = sorted_index.len();
+        let cutoff = ((n as f64 * self.fraction).ceil() as usize).max(1).min(n);
+        sorted_index[rng.gen_range(0, cutoff)]
+    }
+}
+
+#[derive(Debug, PartialEq, Copy, Clone, Serialize, Deserialize)]
+pub enum SelectionMethod {
+    Tournament { size: usize },
+    Roulette,
+    Rank,
+    Truncation { fraction: f64 },
+}
+
+impl SelectionMethod {
+    pub fn build(&self) -> Box<dyn Selection> {
+        match *self {
+            SelectionMethod::Tournament { size } => Box::new(Tournament { size: size.max(1) }),
+            SelectionMethod::Roulette => Box::new(Roulette),
+            SelectionMethod::Rank => Box::new(Rank),
+            SelectionMethod::Truncation { fraction } => Box::new(Truncation { fraction }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+    use rand_pcg::Pcg32;
+
+    fn get_counts(method: SelectionMethod, fitness: &[f64], sorted_index: &[usize]) -> Vec<usize> {
+        let selection = method.build();
+        let mut rng = Pcg32::seed_from_u64(1);
+        let mut counts = vec![0; fitness.len()];
+        for _ in 0..10000 {
+            counts[selection.select(fitness, sorted_index, &mut rng)] += 1;
+        }
+        counts
+    }
+
+    #[test]
+    fn test_selection_pressure() {
+        let fitness = [1.0, 4.0, -2.0, 3.0];
+        let sorted_index = [1, 3, 0, 2];
+        let methods = [
+            SelectionMethod::Tournament { size: 3 },
+            SelectionMethod::Roulette,
+            SelectionMethod::Rank,
+            SelectionMethod::Truncation { fraction: 0.5 },
+        ];
+        for method in methods.iter() {
+            let counts = get_counts(*method, &fitness, &sorted_index);
+            // Better individuals are always picked more often than worse ones
+            assert!(counts[1] > counts[3], "{:?} {:?}", method, counts);
+            assert!(counts[3] >= counts[0], "{:?} {:?}", method, counts);
+            assert!(counts[0] >= counts[2], "{:?} {:?}", method, counts);
+        }
+    }
+
+    #[test]
+    fn test_selection_edge_cases() {
+        let sorted_index = [1, 3, 0, 2];
+        // The worst individual is never picked by roulette or truncation
+        let counts = get_counts(SelectionMethod::Roulette, &[1.0, 4.0, -2.0, 3.0], &sorted_index);
+        assert_eq!(counts[2], 0);
+        let counts = get_counts(
+            SelectionMethod::Truncation { fraction: 0.5 },
+            &[1.0, 4.0, -2.0, 3.0],
+            &sorted_index,
+        );
+        assert_eq!(counts[0] + counts[2], 0);
+        // Equal fitness falls back to uniform roulette
+        let counts = get_counts(SelectionMethod::Roulette, &[2.0; 4], &sorted_index);
+        assert!(counts.iter().all(|c| *c > 2000));
+        // A tournament of one is uniform
+        let counts = get_counts(
+            SelectionMethod::Tournament { size: 0 },
+            &[1.0, 4.0, -2.0, 3.0],
+            &sorted_index,
+        );
+        assert!(counts.iter().all(|c| *c > 2000));
+    }
+}